@@ -0,0 +1,274 @@
+//! buddy allocator over `2^n` blocks of `block_size` bytes
+//!
+//! `wasm2sb` generates the same allocator as scratch procedures,
+//! `longest` is a list there and every loop is a `repeat until`
+
+use anyhow::{anyhow, Result};
+
+#[derive(Debug, Clone)]
+pub struct BuddyBlock {
+    n: usize,
+    block_size: usize,
+    /// the largest free run of blocks in the subtree of each node
+    ///
+    /// node `i` has the children `2i+1` and `2i+2`, the root covers every block
+    longest: Vec<usize>,
+}
+
+impl BuddyBlock {
+    pub fn new(n: usize, block_size: usize) -> Self {
+        let mut buddy = BuddyBlock {
+            n,
+            block_size,
+            longest: Vec::new(),
+        };
+        buddy.init();
+        buddy
+    }
+
+    /// every block is free, the run of each node is its own size
+    pub fn init(&mut self) {
+        self.longest.clear();
+        let mut node = self.blocks_len();
+        for _ in 0..=self.n {
+            for _ in 0..self.blocks_len() / node {
+                self.longest.push(node);
+            }
+            node /= 2;
+        }
+    }
+
+    pub fn blocks_len(&self) -> usize {
+        1 << self.n
+    }
+
+    pub fn heap_size(&self) -> usize {
+        self.blocks_len() * self.block_size
+    }
+
+    pub fn tree_len(&self) -> usize {
+        self.longest.len()
+    }
+
+    /// number of blocks for `size` bytes, rounded up to a power of two
+    pub fn units(&self, size: usize) -> usize {
+        let mut units = 1;
+        while units * self.block_size < size {
+            units *= 2;
+        }
+        units
+    }
+
+    /// byte offset of a new block, `None` if there is no room
+    pub fn alloc(&mut self, size: usize) -> Option<usize> {
+        let units = self.units(size);
+        if self.longest[0] < units {
+            return None;
+        }
+
+        // go down to the first node of the size with enough room
+        let mut index = 0;
+        let mut node = self.blocks_len();
+        while node != units {
+            index = if self.longest[2 * index + 1] >= units {
+                2 * index + 1
+            } else {
+                2 * index + 2
+            };
+            node /= 2;
+        }
+        self.longest[index] = 0;
+        let offset = (index + 1) * node - self.blocks_len();
+
+        // the parents keep the larger run of their children
+        while index != 0 {
+            index = (index - 1) / 2;
+            self.longest[index] = self.longest[2 * index + 1].max(self.longest[2 * index + 2]);
+        }
+
+        Some(offset * self.block_size)
+    }
+
+    /// (index, blocks) of the node allocated at `offset`
+    fn find(&self, offset: usize) -> Result<(usize, usize)> {
+        if offset % self.block_size != 0 || offset >= self.heap_size() {
+            return Err(anyhow!("invalid offset: {offset}"));
+        }
+        let offset = offset / self.block_size;
+
+        // the allocated node is the first one with no room above the leaf
+        let mut index = offset + self.blocks_len() - 1;
+        let mut node = 1;
+        while self.longest[index] != 0 && index != 0 {
+            node *= 2;
+            index = (index - 1) / 2;
+        }
+        if self.longest[index] != 0 || (index + 1) * node - self.blocks_len() != offset {
+            return Err(anyhow!("not allocated: {offset}"));
+        }
+        Ok((index, node))
+    }
+
+    /// bytes of the block allocated at `offset`
+    pub fn size_of(&self, offset: usize) -> Result<usize> {
+        let (_, node) = self.find(offset)?;
+        Ok(node * self.block_size)
+    }
+
+    pub fn free(&mut self, offset: usize) -> Result<()> {
+        let (mut index, mut node) = self.find(offset)?;
+        self.longest[index] = node;
+
+        // buddies both free are merged
+        while index != 0 {
+            index = (index - 1) / 2;
+            node *= 2;
+            let left = self.longest[2 * index + 1];
+            let right = self.longest[2 * index + 2];
+            self.longest[index] = if left + right == node {
+                node
+            } else {
+                left.max(right)
+            };
+        }
+        Ok(())
+    }
+
+    /// byte offset of the block resized to `size`, `None` if there is no room
+    ///
+    /// a moved block gets the first `min(old size, size)` bytes copied,
+    /// the old block is kept if there is no room
+    pub fn realloc(&mut self, offset: usize, size: usize) -> Result<Option<usize>> {
+        let old = self.size_of(offset)?;
+        if self.units(size) * self.block_size == old {
+            return Ok(Some(offset));
+        }
+        let new = match self.alloc(size) {
+            Some(new) => new,
+            None => return Ok(None),
+        };
+        self.free(offset)?;
+        Ok(Some(new))
+    }
+
+    /// number of free blocks, none of their nodes up to the root is allocated
+    pub fn free_len(&self) -> usize {
+        (0..self.blocks_len())
+            .filter(|offset| {
+                let mut index = offset + self.blocks_len() - 1;
+                while self.longest[index] != 0 && index != 0 {
+                    index = (index - 1) / 2;
+                }
+                self.longest[index] != 0
+            })
+            .count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use rand::Rng;
+
+    use super::BuddyBlock;
+
+    #[test]
+    fn test_init() {
+        let buddy = BuddyBlock::new(4, 8);
+        assert_eq!(buddy.tree_len(), 31);
+        assert_eq!(buddy.heap_size(), 128);
+        assert_eq!(buddy.free_len(), 16);
+    }
+
+    #[test]
+    fn test_units() {
+        let buddy = BuddyBlock::new(4, 8);
+        assert_eq!(buddy.units(0), 1);
+        assert_eq!(buddy.units(8), 1);
+        assert_eq!(buddy.units(9), 2);
+        assert_eq!(buddy.units(17), 4);
+        assert_eq!(buddy.units(128), 16);
+    }
+
+    #[test]
+    fn test_alloc_free() {
+        let mut buddy = BuddyBlock::new(4, 8);
+        let a = buddy.alloc(8).unwrap();
+        let b = buddy.alloc(16).unwrap();
+        let c = buddy.alloc(8).unwrap();
+        assert_eq!((a, b, c), (0, 16, 8));
+        assert_eq!(buddy.size_of(b).unwrap(), 16);
+        assert_eq!(buddy.alloc(128), None);
+
+        buddy.free(a).unwrap();
+        buddy.free(c).unwrap();
+        buddy.free(b).unwrap();
+        assert_eq!(buddy.alloc(128), Some(0));
+    }
+
+    #[test]
+    fn test_invalid_free() {
+        let mut buddy = BuddyBlock::new(4, 8);
+        let a = buddy.alloc(32).unwrap();
+        assert!(buddy.free(a + 8).is_err());
+        assert!(buddy.free(a + 1).is_err());
+        assert!(buddy.free(1024).is_err());
+        buddy.free(a).unwrap();
+        assert!(buddy.free(a).is_err());
+    }
+
+    #[test]
+    fn test_realloc() {
+        let mut buddy = BuddyBlock::new(4, 8);
+        let a = buddy.alloc(10).unwrap();
+        assert_eq!(buddy.realloc(a, 16).unwrap(), Some(a));
+        let b = buddy.realloc(a, 40).unwrap().unwrap();
+        assert_eq!(buddy.size_of(b).unwrap(), 64);
+        assert!(buddy.size_of(a).is_err());
+        assert_eq!(buddy.realloc(b, 256).unwrap(), None);
+        assert_eq!(buddy.size_of(b).unwrap(), 64);
+    }
+
+    /// allocated blocks never overlap and everything is merged back at the end
+    #[test]
+    fn test_random() {
+        let mut rng = rand::thread_rng();
+        for _ in 0..100 {
+            let mut buddy = BuddyBlock::new(8, 4);
+            let mut allocated: HashMap<usize, usize> = HashMap::new();
+
+            for _ in 0..1000 {
+                if allocated.is_empty() || rng.gen_bool(0.6) {
+                    let size = rng.gen_range(0..200);
+                    if let Some(offset) = buddy.alloc(size) {
+                        let len = buddy.size_of(offset).unwrap();
+                        assert!(len >= size);
+                        assert!(offset + len <= buddy.heap_size());
+                        assert_eq!(offset % len, 0);
+                        for (&other, &other_len) in allocated.iter() {
+                            assert!(offset + len <= other || other + other_len <= offset);
+                        }
+                        allocated.insert(offset, len);
+                    }
+                } else {
+                    let &offset = allocated.keys().next().unwrap();
+                    if rng.gen_bool(0.5) {
+                        buddy.free(offset).unwrap();
+                        allocated.remove(&offset);
+                    } else if let Some(new) = buddy.realloc(offset, rng.gen_range(0..200)).unwrap() {
+                        allocated.remove(&offset);
+                        allocated.insert(new, buddy.size_of(new).unwrap());
+                    }
+                }
+                let used: usize = allocated.values().sum();
+                assert_eq!(buddy.free_len() * 4, buddy.heap_size() - used);
+            }
+
+            for offset in allocated.keys() {
+                buddy.free(*offset).unwrap();
+            }
+            assert_eq!(buddy.alloc(buddy.heap_size()), Some(0));
+        }
+    }
+}
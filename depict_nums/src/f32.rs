@@ -1,4 +1,13 @@
-use std::ops::Add;
+use crate::sb_mod;
+
+// 2^-149, the smallest subnormal
+const MIN_SUBNORMAL_EXP: f64 = -149f64;
+// 2^-126, the smallest normal
+const MIN_NORMAL_EXP: f64 = -126f64;
+// 2^128
+const OVERFLOW: f64 = 340282366920938463463374607431768211456f64;
+// 2^31
+const HALF: f64 = 2147483648.0;
 
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub struct DepictF32(f64);
@@ -15,18 +24,149 @@ impl From<DepictF32> for f32 {
     }
 }
 
+/// scratchではリストから引く
+#[inline]
+fn pow2(n: f64) -> f64 {
+    2f64.powi(n as i32)
+}
+
+/// floor(log2(x))、lnの誤差は2の冪と比べて直す
+fn exponent(x: f64) -> f64 {
+    let mut e = (x.ln() / std::f64::consts::LN_2).floor();
+    if pow2(e) > x {
+        e -= 1f64;
+    }
+    if pow2(e + 1f64) <= x {
+        e += 1f64;
+    }
+    e
+}
+
+// 四則演算はf64で計算してから丸めても二重丸めにならない
 impl std::ops::Add for DepictF32 {
     type Output = Self;
 
     fn add(self, rhs: Self) -> Self::Output {
-        println!("calc_e: {:?}", self.calc_e());
+        DepictF32::fround(self.0 + rhs.0)
+    }
+}
+
+impl std::ops::Sub for DepictF32 {
+    type Output = Self;
 
-        // DepictF32(self.0 + rhs.0)
-        DepictF32(0f64)
+    fn sub(self, rhs: Self) -> Self::Output {
+        DepictF32::fround(self.0 - rhs.0)
+    }
+}
+
+impl std::ops::Mul for DepictF32 {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self::Output {
+        DepictF32::fround(self.0 * rhs.0)
+    }
+}
+
+impl std::ops::Div for DepictF32 {
+    type Output = Self;
+
+    fn div(self, rhs: Self) -> Self::Output {
+        DepictF32::fround(self.0 / rhs.0)
     }
 }
 
 impl DepictF32 {
+    /// `Math.fround`と同じ、最も近いf32に丸める (同じ距離なら偶数)
+    ///
+    /// scratchにはround half upしかないので、floorと端数で丸める
+    pub fn fround(x: f64) -> DepictF32 {
+        let abs = x.abs();
+        // 0, 無限大, NaNはそのまま
+        if abs == 0f64 || abs.is_infinite() || abs.is_nan() {
+            return DepictF32(x);
+        }
+
+        // 仮数部が2^23以上2^24未満の整数部になるようにずらす
+        let e = if abs < pow2(MIN_NORMAL_EXP) {
+            MIN_NORMAL_EXP
+        } else {
+            exponent(abs)
+        };
+        let scaled = abs / pow2(e - 23f64);
+        let mut m = scaled.floor();
+        let frac = scaled - m;
+        if frac > 0.5 || (frac == 0.5 && sb_mod(m, 2f64) == 1f64) {
+            m += 1f64;
+        }
+
+        let mut rounded = m * pow2(e - 23f64);
+        if rounded >= OVERFLOW {
+            rounded = f64::INFINITY;
+        }
+        if x < 0f64 {
+            DepictF32(-rounded)
+        } else {
+            DepictF32(rounded)
+        }
+    }
+
+    pub fn sqrt(self) -> DepictF32 {
+        DepictF32::fround(self.0.sqrt())
+    }
+
+    /// i32.reinterpret_f32、u32として返す
+    ///
+    /// f64のbit列から組み立て直す
+    pub fn to_bits(self) -> f64 {
+        if self.0.is_nan() {
+            // canonical NaN
+            return 2143289344f64;
+        }
+        let bits = self.calc_bits();
+        let value = |bits: &[bool]| bits.iter().fold(0f64, |acc, b| acc * 2f64 + *b as u8 as f64);
+
+        let sign = if bits[0] { HALF } else { 0f64 };
+        let e = value(&bits[1..12]);
+        // 上位23bit、残りはf32の値なら0
+        let f = value(&bits[12..35]);
+        let (e, f) = if e == 2047f64 {
+            (255f64, 0f64)
+        } else if e == 0f64 {
+            (0f64, 0f64)
+        } else if e - 1023f64 >= MIN_NORMAL_EXP {
+            (e - 1023f64 + 127f64, f)
+        } else {
+            // f32では非正規化数
+            (0f64, (f + pow2(23f64)) / pow2(MIN_NORMAL_EXP - (e - 1023f64)))
+        };
+        sign + e * pow2(23f64) + f
+    }
+
+    /// f32.reinterpret_i32、負の数はu32として読む
+    pub fn from_bits(bits: f64) -> DepictF32 {
+        let bits = sb_mod(bits, HALF * 2f64);
+        let negative = bits >= HALF;
+        let e = sb_mod((bits / pow2(23f64)).floor(), 256f64);
+        let m = sb_mod(bits, pow2(23f64));
+
+        let abs = if e == 255f64 {
+            if m == 0f64 {
+                f64::INFINITY
+            } else {
+                f64::NAN
+            }
+        } else if e == 0f64 {
+            m * pow2(MIN_SUBNORMAL_EXP)
+        } else {
+            (m + pow2(23f64)) * pow2(e - 150f64)
+        };
+        if negative {
+            DepictF32(-abs)
+        } else {
+            DepictF32(abs)
+        }
+    }
+
     // bitの計算
     pub fn calc_bits(&self) -> Vec<bool> {
         let mut bits = vec![];
@@ -625,15 +765,141 @@ mod test {
         }
     }
 
-    // mod add {
-    //     use super::super::DepictF32;
-
-    //     #[test]
-    //     fn test_add() {
-    //         let a = DepictF32::from(1.0);
-    //         let b = DepictF32::from(2.0);
-    //         let c = a + b;
-    //         assert_eq!(c, DepictF32::from(3.0));
-    //     }
-    // }
+    mod round {
+        use super::super::DepictF32;
+
+        use rand::distributions::Uniform;
+        use rand::Rng;
+
+        /// NaN同士も等しいとみなす
+        fn assert_same(a: DepictF32, b: f32) {
+            let a: f32 = a.into();
+            if b.is_nan() {
+                assert!(a.is_nan());
+            } else {
+                assert_eq!(a.to_bits(), b.to_bits(), "{a} {b}");
+            }
+        }
+
+        fn random_f32(rng: &mut impl Rng) -> f32 {
+            f32::from_bits(rng.sample(Uniform::new_inclusive(u32::MIN, u32::MAX)))
+        }
+
+        #[test]
+        fn test_fround() {
+            let mut rng = rand::thread_rng();
+            let range = Uniform::new_inclusive(u64::MIN, u64::MAX);
+            for _ in 0..100000 {
+                let x = f64::from_bits(rng.sample(range));
+                assert_same(DepictF32::fround(x), x as f32);
+            }
+        }
+
+        #[test]
+        fn test_fround_edge() {
+            let edge = [
+                0f64,
+                -0f64,
+                1.5f64,
+                f32::MAX as f64,
+                // 丸めると無限大になる境目
+                f32::MAX as f64 + 2f64.powi(103),
+                f32::MAX as f64 + 2f64.powi(103) - 2f64.powi(75),
+                f32::MIN_POSITIVE as f64,
+                2f64.powi(-149),
+                2f64.powi(-150),
+                2f64.powi(-150) * 1.5,
+                -2f64.powi(-151),
+                1f64 + 2f64.powi(-24),
+                1f64 + 2f64.powi(-24) * 3f64,
+                f64::INFINITY,
+                f64::NEG_INFINITY,
+                f64::NAN,
+                16777217f64,
+            ];
+            for x in edge {
+                assert_same(DepictF32::fround(x), x as f32);
+            }
+        }
+
+        #[test]
+        fn test_arithmetic() {
+            let mut rng = rand::thread_rng();
+            for _ in 0..100000 {
+                let (a, b) = (random_f32(&mut rng), random_f32(&mut rng));
+                let (x, y) = (DepictF32::from(a), DepictF32::from(b));
+                assert_same(x + y, a + b);
+                assert_same(x - y, a - b);
+                assert_same(x * y, a * b);
+                assert_same(x / y, a / b);
+                assert_same(x.sqrt(), a.sqrt());
+            }
+        }
+
+        #[test]
+        fn test_convert() {
+            let mut rng = rand::thread_rng();
+            let range = Uniform::new_inclusive(i32::MIN, i32::MAX);
+            for _ in 0..100000 {
+                let a = rng.sample(range);
+                assert_same(DepictF32::fround(a as f64), a as f32);
+                assert_same(DepictF32::fround(a as u32 as f64), a as u32 as f32);
+            }
+        }
+    }
+
+    mod reinterpret {
+        use super::super::DepictF32;
+
+        use rand::distributions::Uniform;
+        use rand::Rng;
+
+        #[test]
+        fn test_to_bits() {
+            let mut rng = rand::thread_rng();
+            let range = Uniform::new_inclusive(u32::MIN, u32::MAX);
+            for _ in 0..100000 {
+                let a = f32::from_bits(rng.sample(range));
+                if a.is_nan() {
+                    continue;
+                }
+                assert_eq!(DepictF32::from(a).to_bits(), a.to_bits() as f64);
+            }
+            assert_eq!(DepictF32::from(f32::NAN).to_bits(), f32::NAN.to_bits() as f64);
+        }
+
+        #[test]
+        fn test_from_bits() {
+            let mut rng = rand::thread_rng();
+            let range = Uniform::new_inclusive(i32::MIN, i32::MAX);
+            for _ in 0..100000 {
+                // i32として渡される
+                let bits = rng.sample(range);
+                let a = f32::from_bits(bits as u32);
+                let x: f32 = DepictF32::from_bits(bits as f64).into();
+                if a.is_nan() {
+                    assert!(x.is_nan());
+                } else {
+                    assert_eq!(x.to_bits(), a.to_bits());
+                }
+            }
+        }
+
+        #[test]
+        fn test_round_trip() {
+            let mut rng = rand::thread_rng();
+            let range = Uniform::new_inclusive(u32::MIN, u32::MAX);
+            for _ in 0..100000 {
+                let a = f32::from_bits(rng.sample(range));
+                if a.is_nan() {
+                    continue;
+                }
+                let x = DepictF32::from(a);
+                assert_eq!(DepictF32::from_bits(x.to_bits()), x);
+                // shiftで求めたbit列と符号、指数が一致する
+                let bits = x.get_bits_by_shift();
+                assert_eq!(x.to_bits() >= 2147483648f64, bits[0]);
+            }
+        }
+    }
 }
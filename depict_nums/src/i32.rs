@@ -1,7 +1,7 @@
 use crate::sb_mod;
 
 #[derive(Debug, PartialEq, Clone, Copy)]
-struct DepictI32(f64);
+pub struct DepictI32(pub(crate) f64);
 
 impl From<DepictI32> for i32 {
     fn from(x: DepictI32) -> i32 {
@@ -117,14 +117,235 @@ impl std::ops::Div for DepictI32 {
     type Output = DepictI32;
 
     fn div(self, rhs: DepictI32) -> Self::Output {
-        println!("self: {}, rhs: {}", self.0, rhs.0);
-
+        DepictI32::check_divisor(rhs);
+        // 0に向かって丸める
         let div = self.0 / rhs.0;
+        let div = if div < 0f64 { div.ceil() } else { div.floor() };
+
+        // i32::MIN / -1 だけがオーバーフローする、生成されるdiv_sと同じくtrapする
+        if div == Self::HALF {
+            panic!("integer overflow");
+        }
+        DepictI32(div)
+    }
+}
 
-        println!("div: {}", div);
+impl std::ops::Rem for DepictI32 {
+    type Output = DepictI32;
 
+    fn rem(self, rhs: DepictI32) -> Self::Output {
+        DepictI32::check_divisor(rhs);
+        let div = self.0 / rhs.0;
         let div = if div < 0f64 { div.ceil() } else { div.floor() };
-        DepictI32(div)
+
+        // 結果は必ずi32に収まる
+        DepictI32(self.0 - rhs.0 * div)
+    }
+}
+
+/// shift amount is taken modulo 32, as `i32::wrapping_shl`
+impl std::ops::Shl<u32> for DepictI32 {
+    type Output = DepictI32;
+
+    fn shl(self, rhs: u32) -> Self::Output {
+        self.shift_left(sb_mod(rhs as f64, 32f64))
+    }
+}
+
+impl DepictI32 {
+    const HALF: f64 = (i32::MAX as f64) + 1.0;
+    const FULL: f64 = ((i32::MAX as f64) + 1.0) * 2.0;
+
+    #[inline]
+    fn wrap(x: f64) -> DepictI32 {
+        DepictI32(sb_mod(x + Self::HALF, Self::FULL) - Self::HALF)
+    }
+
+    /// the same bits read as u32
+    #[inline]
+    fn to_u32(self) -> f64 {
+        sb_mod(self.0, Self::FULL)
+    }
+
+    /// the generated procedures trap on a zero divisor
+    #[inline]
+    fn check_divisor(rhs: DepictI32) {
+        if rhs.0 == 0f64 {
+            panic!("integer divide by zero");
+        }
+    }
+
+    pub fn div_u(self, rhs: DepictI32) -> DepictI32 {
+        DepictI32::check_divisor(rhs);
+        DepictI32::wrap((self.to_u32() / rhs.to_u32()).floor())
+    }
+
+    pub fn rem_u(self, rhs: DepictI32) -> DepictI32 {
+        DepictI32::check_divisor(rhs);
+        DepictI32::wrap(sb_mod(self.to_u32(), rhs.to_u32()))
+    }
+
+    pub fn lt_s(self, rhs: DepictI32) -> bool {
+        self.0 < rhs.0
+    }
+
+    pub fn lt_u(self, rhs: DepictI32) -> bool {
+        self.to_u32() < rhs.to_u32()
+    }
+
+    pub fn gt_s(self, rhs: DepictI32) -> bool {
+        self.0 > rhs.0
+    }
+
+    pub fn gt_u(self, rhs: DepictI32) -> bool {
+        self.to_u32() > rhs.to_u32()
+    }
+
+    pub fn le_s(self, rhs: DepictI32) -> bool {
+        self.0 <= rhs.0
+    }
+
+    pub fn le_u(self, rhs: DepictI32) -> bool {
+        self.to_u32() <= rhs.to_u32()
+    }
+
+    pub fn ge_s(self, rhs: DepictI32) -> bool {
+        self.0 >= rhs.0
+    }
+
+    pub fn ge_u(self, rhs: DepictI32) -> bool {
+        self.to_u32() >= rhs.to_u32()
+    }
+}
+
+/// 4bitずつ引くための表 (16 * 16)
+fn nibble_table(op: fn(usize, usize) -> usize) -> Vec<f64> {
+    (0..256).map(|i| op(i / 16, i % 16) as f64).collect()
+}
+
+/// 4bitごとの1の数
+fn popcnt_table() -> Vec<f64> {
+    (0..16usize).map(|i| i.count_ones() as f64).collect()
+}
+
+/// scratchではリストから引く
+fn pow2(n: f64) -> f64 {
+    (1u64 << n as u32) as f64
+}
+
+impl DepictI32 {
+    /// 4bitずつ表を引いて足し合わせる
+    fn nibble_op(self, rhs: DepictI32, table: &[f64]) -> DepictI32 {
+        let mut result = 0f64;
+        let mut scale = 1f64;
+        for _ in 0..8 {
+            let a = sb_mod((self.0 / scale).floor(), 16f64);
+            let b = sb_mod((rhs.0 / scale).floor(), 16f64);
+            result += table[(a * 16f64 + b) as usize] * scale;
+            scale *= 16f64;
+        }
+        DepictI32::wrap(result)
+    }
+
+    fn shift_left(self, n: f64) -> DepictI32 {
+        DepictI32::wrap(self.to_u32() * pow2(n))
+    }
+
+    /// shift amount is taken modulo 32
+    #[inline]
+    fn shift_amount(rhs: DepictI32) -> f64 {
+        sb_mod(rhs.0, 32f64)
+    }
+
+    pub fn shr_s(self, rhs: DepictI32) -> DepictI32 {
+        DepictI32((self.0 / pow2(Self::shift_amount(rhs))).floor())
+    }
+
+    pub fn shr_u(self, rhs: DepictI32) -> DepictI32 {
+        DepictI32::wrap((self.to_u32() / pow2(Self::shift_amount(rhs))).floor())
+    }
+
+    pub fn rotl(self, rhs: DepictI32) -> DepictI32 {
+        let n = Self::shift_amount(rhs);
+        let u = self.to_u32();
+        let high = sb_mod(u * pow2(n), Self::FULL);
+        let low = (u / pow2(32f64 - n)).floor();
+        DepictI32::wrap(high + low)
+    }
+
+    pub fn rotr(self, rhs: DepictI32) -> DepictI32 {
+        let n = Self::shift_amount(rhs);
+        let u = self.to_u32();
+        let high = sb_mod(u * pow2(32f64 - n), Self::FULL);
+        let low = (u / pow2(n)).floor();
+        DepictI32::wrap(high + low)
+    }
+
+    /// binary search, shifting the upper bits out
+    pub fn clz(self) -> DepictI32 {
+        let mut u = self.to_u32();
+        if u == 0f64 {
+            return DepictI32(32f64);
+        }
+        let mut n = 0f64;
+        for bits in [16f64, 8f64, 4f64, 2f64, 1f64] {
+            if u < pow2(32f64 - bits) {
+                n += bits;
+                u *= pow2(bits);
+            }
+        }
+        DepictI32(n)
+    }
+
+    /// binary search, shifting the lower bits out
+    pub fn ctz(self) -> DepictI32 {
+        let mut u = self.to_u32();
+        if u == 0f64 {
+            return DepictI32(32f64);
+        }
+        let mut n = 0f64;
+        for bits in [16f64, 8f64, 4f64, 2f64, 1f64] {
+            if sb_mod(u, pow2(bits)) == 0f64 {
+                n += bits;
+                u = (u / pow2(bits)).floor();
+            }
+        }
+        DepictI32(n)
+    }
+
+    pub fn popcnt(self) -> DepictI32 {
+        let table = popcnt_table();
+        let mut n = 0f64;
+        let mut scale = 1f64;
+        for _ in 0..8 {
+            n += table[sb_mod((self.0 / scale).floor(), 16f64) as usize];
+            scale *= 16f64;
+        }
+        DepictI32(n)
+    }
+}
+
+impl std::ops::BitAnd for DepictI32 {
+    type Output = DepictI32;
+
+    fn bitand(self, rhs: DepictI32) -> Self::Output {
+        self.nibble_op(rhs, &nibble_table(|a, b| a & b))
+    }
+}
+
+impl std::ops::BitOr for DepictI32 {
+    type Output = DepictI32;
+
+    fn bitor(self, rhs: DepictI32) -> Self::Output {
+        self.nibble_op(rhs, &nibble_table(|a, b| a | b))
+    }
+}
+
+impl std::ops::BitXor for DepictI32 {
+    type Output = DepictI32;
+
+    fn bitxor(self, rhs: DepictI32) -> Self::Output {
+        self.nibble_op(rhs, &nibble_table(|a, b| a ^ b))
     }
 }
 
@@ -413,6 +634,18 @@ mod tests {
             }
         }
 
+        #[test]
+        #[should_panic(expected = "integer overflow")]
+        fn test_division_min_by_minus_one_traps() {
+            let _ = DepictI32::from(i32::MIN) / DepictI32::from(-1);
+        }
+
+        #[test]
+        #[should_panic(expected = "integer divide by zero")]
+        fn test_division_by_zero_traps() {
+            let _ = DepictI32::from(1) / DepictI32::from(0);
+        }
+
         use rand::distributions::Uniform;
         use rand::Rng;
 
@@ -424,6 +657,9 @@ mod tests {
             for _ in 0..1000000 {
                 let a = rng.sample(range);
                 let b = rng.sample(range);
+                if b == 0 || (a == i32::MIN && b == -1) {
+                    continue;
+                }
                 assert_eq!(
                     a.wrapping_div(b),
                     (DepictI32::from(a) / DepictI32::from(b)).into()
@@ -431,4 +667,172 @@ mod tests {
             }
         }
     }
+
+    mod rem {
+        use crate::i32::DepictI32;
+
+        #[test]
+        fn test_remainder_signs() {
+            for (a, b) in [(7, 3), (-7, 3), (7, -3), (-7, -3), (i32::MIN, -1)] {
+                let result = DepictI32::from(a) % DepictI32::from(b);
+                assert_eq!(Into::<i32>::into(result), a.wrapping_rem(b));
+            }
+        }
+
+        use rand::distributions::Uniform;
+        use rand::Rng;
+
+        #[test]
+        fn test_remainder_consistency_with_i32() {
+            let mut rng = rand::thread_rng();
+            let range = Uniform::new_inclusive(i32::MIN, i32::MAX);
+
+            for _ in 0..1000000 {
+                let a = rng.sample(range);
+                let b = rng.sample(range);
+                if b == 0 {
+                    continue;
+                }
+                assert_eq!(
+                    a.wrapping_rem(b),
+                    (DepictI32::from(a) % DepictI32::from(b)).into()
+                );
+            }
+        }
+    }
+
+    mod unsigned {
+        use crate::i32::DepictI32;
+
+        #[test]
+        fn test_unsigned_division_of_negative() {
+            let a: DepictI32 = (-1).into();
+            let b: DepictI32 = 2.into();
+            assert_eq!(Into::<i32>::into(a.div_u(b)), (u32::MAX / 2) as i32);
+            assert_eq!(Into::<i32>::into(a.rem_u(b)), 1);
+        }
+
+        use rand::distributions::Uniform;
+        use rand::Rng;
+
+        #[test]
+        fn test_unsigned_division_consistency_with_u32() {
+            let mut rng = rand::thread_rng();
+            let range = Uniform::new_inclusive(i32::MIN, i32::MAX);
+
+            for _ in 0..1000000 {
+                let a = rng.sample(range);
+                let b = rng.sample(range);
+                if b == 0 {
+                    continue;
+                }
+                let (ua, ub) = (a as u32, b as u32);
+                assert_eq!(
+                    (ua / ub) as i32,
+                    DepictI32::from(a).div_u(DepictI32::from(b)).into()
+                );
+                assert_eq!(
+                    (ua % ub) as i32,
+                    DepictI32::from(a).rem_u(DepictI32::from(b)).into()
+                );
+            }
+        }
+    }
+
+    mod cmp {
+        use crate::i32::DepictI32;
+
+        use rand::distributions::Uniform;
+        use rand::Rng;
+
+        #[test]
+        fn test_comparison_consistency_with_i32() {
+            let mut rng = rand::thread_rng();
+            // narrow range so that equal values are also tested
+            let range = Uniform::new_inclusive(-4, 4);
+            let wide = Uniform::new_inclusive(i32::MIN, i32::MAX);
+
+            for k in 0..100000 {
+                let (a, b) = if k % 2 == 0 {
+                    (rng.sample(range), rng.sample(range))
+                } else {
+                    (rng.sample(wide), rng.sample(wide))
+                };
+                let (x, y) = (DepictI32::from(a), DepictI32::from(b));
+                let (ua, ub) = (a as u32, b as u32);
+                assert_eq!(a < b, x.lt_s(y));
+                assert_eq!(a > b, x.gt_s(y));
+                assert_eq!(a <= b, x.le_s(y));
+                assert_eq!(a >= b, x.ge_s(y));
+                assert_eq!(ua < ub, x.lt_u(y));
+                assert_eq!(ua > ub, x.gt_u(y));
+                assert_eq!(ua <= ub, x.le_u(y));
+                assert_eq!(ua >= ub, x.ge_u(y));
+            }
+        }
+    }
+
+    mod bitwise {
+        use crate::i32::DepictI32;
+
+        use rand::distributions::Uniform;
+        use rand::Rng;
+
+        #[test]
+        fn test_bitwise_edge_values() {
+            let values = [0, 1, -1, 2, i32::MIN, i32::MAX, 0x0f0f_0f0f, -0x1000];
+            for a in values {
+                for b in values {
+                    let (x, y) = (DepictI32::from(a), DepictI32::from(b));
+                    assert_eq!(a & b, (x & y).into());
+                    assert_eq!(a | b, (x | y).into());
+                    assert_eq!(a ^ b, (x ^ y).into());
+                }
+                let x = DepictI32::from(a);
+                assert_eq!(a.leading_zeros() as i32, x.clz().into());
+                assert_eq!(a.trailing_zeros() as i32, x.ctz().into());
+                assert_eq!(a.count_ones() as i32, x.popcnt().into());
+            }
+        }
+
+        #[test]
+        fn test_bitwise_consistency_with_i32() {
+            let mut rng = rand::thread_rng();
+            let range = Uniform::new_inclusive(i32::MIN, i32::MAX);
+
+            for _ in 0..100000 {
+                let a = rng.sample(range);
+                let b = rng.sample(range);
+                let (x, y) = (DepictI32::from(a), DepictI32::from(b));
+                assert_eq!(a & b, (x & y).into());
+                assert_eq!(a | b, (x | y).into());
+                assert_eq!(a ^ b, (x ^ y).into());
+                assert_eq!(a.leading_zeros() as i32, x.clz().into());
+                assert_eq!(a.trailing_zeros() as i32, x.ctz().into());
+                assert_eq!(a.count_ones() as i32, x.popcnt().into());
+            }
+        }
+
+        #[test]
+        fn test_shift_consistency_with_i32() {
+            let mut rng = rand::thread_rng();
+            let range = Uniform::new_inclusive(i32::MIN, i32::MAX);
+            // amounts over 31 and negative ones are taken modulo 32
+            let amount = Uniform::new_inclusive(-40, 40);
+
+            for _ in 0..100000 {
+                let a = rng.sample(range);
+                let n = rng.sample(amount);
+                let (x, y) = (DepictI32::from(a), DepictI32::from(n));
+                assert_eq!(a.wrapping_shl(n as u32), (x << n as u32).into());
+                assert_eq!(a.wrapping_shr(n as u32), x.shr_s(y).into());
+                assert_eq!(
+                    (a as u32).wrapping_shr(n as u32) as i32,
+                    x.shr_u(y).into()
+                );
+                assert_eq!(a.rotate_left(n as u32 % 32), x.rotl(y).into());
+                assert_eq!(a.rotate_right(n as u32 % 32), x.rotr(y).into());
+            }
+        }
+    }
 }
@@ -1 +1,614 @@
+use crate::{f32::DepictF32, i32::DepictI32, sb_mod};
 
+// 2^16
+const HALF_WORD: f64 = 65536.0;
+// 2^31
+const HALF: f64 = 2147483648.0;
+// 2^32
+const FULL: f64 = 4294967296.0;
+
+/// i64 as two halves, the same as `Wasm8Bytes { first, last }` of wasm-sb-bindgen
+///
+/// `hi` is the upper 32 bits as i32, `lo` is the lower 32 bits as u32
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct DepictI64 {
+    hi: f64,
+    lo: f64,
+}
+
+impl From<i64> for DepictI64 {
+    fn from(x: i64) -> DepictI64 {
+        DepictI64 {
+            hi: (x >> 32) as f64,
+            lo: (x as u32) as f64,
+        }
+    }
+}
+
+impl From<DepictI64> for i64 {
+    fn from(x: DepictI64) -> i64 {
+        ((x.hi as i64) << 32) | (x.lo as i64)
+    }
+}
+
+#[inline]
+fn wrap32(x: f64) -> f64 {
+    sb_mod(x + HALF, FULL) - HALF
+}
+
+#[inline]
+fn to_u32(x: f64) -> f64 {
+    sb_mod(x, FULL)
+}
+
+/// scratchではリストから引く
+#[inline]
+fn pow2(n: f64) -> f64 {
+    (1u64 << n as u32) as f64
+}
+
+/// 2^53以上ではf64で丸められるので、2^12未満のビットを2^11のstickyビットにまとめる
+///
+/// 和は正確になり、f32の丸めの境界は2^12の倍数なので結果は変わらない
+#[inline]
+fn sticky_lo(abs_hi: f64, lo: f64) -> f64 {
+    if abs_hi < 2097152f64 {
+        return lo;
+    }
+    let low = sb_mod(lo, 4096f64);
+    lo - low + (low / 4096f64).ceil() * 2048f64
+}
+
+impl DepictI64 {
+    /// 下位のあふれを上位に繰り上げる
+    #[inline]
+    fn new(hi: f64, lo: f64) -> DepictI64 {
+        DepictI64 {
+            hi: wrap32(hi + (lo / FULL).floor()),
+            lo: to_u32(lo),
+        }
+    }
+
+    #[inline]
+    fn uhi(self) -> f64 {
+        to_u32(self.hi)
+    }
+
+    pub fn is_zero(self) -> bool {
+        self.hi == 0f64 && self.lo == 0f64
+    }
+
+    // conversion ==============================================================
+
+    pub fn extend_i32_s(x: f64) -> DepictI64 {
+        DepictI64 {
+            hi: (x / FULL).floor(),
+            lo: to_u32(x),
+        }
+    }
+
+    pub fn extend_i32_u(x: f64) -> DepictI64 {
+        DepictI64 {
+            hi: 0f64,
+            lo: to_u32(x),
+        }
+    }
+
+    pub fn wrap_i32(self) -> f64 {
+        wrap32(self.lo)
+    }
+
+    /// `hi * 2^32` is exact, so this rounds only once
+    pub fn convert_s(self) -> f64 {
+        self.hi * FULL + self.lo
+    }
+
+    pub fn convert_u(self) -> f64 {
+        self.uhi() * FULL + self.lo
+    }
+
+    /// rounded once, through `convert_s` 2^53 + 2^29 + 1 would become a tie of f32 first
+    pub fn convert_f32_s(self) -> DepictF32 {
+        DepictF32::fround(self.hi * FULL + sticky_lo(self.hi.abs(), self.lo))
+    }
+
+    pub fn convert_f32_u(self) -> DepictF32 {
+        DepictF32::fround(self.uhi() * FULL + sticky_lo(self.uhi(), self.lo))
+    }
+
+    /// `x` must be already truncated and in range
+    pub fn from_integral(x: f64) -> DepictI64 {
+        DepictI64 {
+            hi: wrap32((x / FULL).floor()),
+            lo: to_u32(x),
+        }
+    }
+
+    // comparison ==============================================================
+
+    pub fn lt_s(self, rhs: DepictI64) -> bool {
+        self.hi < rhs.hi || (self.hi == rhs.hi && self.lo < rhs.lo)
+    }
+
+    pub fn lt_u(self, rhs: DepictI64) -> bool {
+        self.uhi() < rhs.uhi() || (self.hi == rhs.hi && self.lo < rhs.lo)
+    }
+
+    pub fn gt_s(self, rhs: DepictI64) -> bool {
+        rhs.lt_s(self)
+    }
+
+    pub fn gt_u(self, rhs: DepictI64) -> bool {
+        rhs.lt_u(self)
+    }
+
+    pub fn le_s(self, rhs: DepictI64) -> bool {
+        !self.gt_s(rhs)
+    }
+
+    pub fn le_u(self, rhs: DepictI64) -> bool {
+        !self.gt_u(rhs)
+    }
+
+    pub fn ge_s(self, rhs: DepictI64) -> bool {
+        !self.lt_s(rhs)
+    }
+
+    pub fn ge_u(self, rhs: DepictI64) -> bool {
+        !self.lt_u(rhs)
+    }
+
+    // division ================================================================
+
+    /// 1bitずつの筆算、上位と下位はどちらもu32で持つ
+    pub fn div_rem_u(self, rhs: DepictI64) -> (DepictI64, DepictI64) {
+        if self.hi == 0f64 && rhs.hi == 0f64 {
+            // u32同士ならf64で正確に割れる
+            let q = (self.lo / rhs.lo).floor();
+            return (
+                DepictI64 { hi: 0f64, lo: q },
+                DepictI64 {
+                    hi: 0f64,
+                    lo: self.lo - q * rhs.lo,
+                },
+            );
+        }
+
+        let (dh, dl) = (rhs.uhi(), rhs.lo);
+        let (mut qh, mut ql, mut rh, mut rl) = (0f64, 0f64, 0f64, 0f64);
+        for i in (0..64).rev() {
+            let i = i as f64;
+            let bit = if i >= 32f64 {
+                sb_mod((self.uhi() / pow2(i - 32f64)).floor(), 2f64)
+            } else {
+                sb_mod((self.lo / pow2(i)).floor(), 2f64)
+            };
+            // 2倍して2^64を超えるときは必ず引ける
+            let over = rh >= HALF;
+            rh = to_u32(rh * 2f64) + (rl / HALF).floor();
+            rl = to_u32(rl * 2f64) + bit;
+            qh = to_u32(qh * 2f64) + (ql / HALF).floor();
+            ql = to_u32(ql * 2f64);
+
+            if over || rh > dh || (rh == dh && rl >= dl) {
+                rh = to_u32(rh - dh + ((rl - dl) / FULL).floor());
+                rl = to_u32(rl - dl);
+                ql += 1f64;
+            }
+        }
+
+        (
+            DepictI64 {
+                hi: wrap32(qh),
+                lo: ql,
+            },
+            DepictI64 {
+                hi: wrap32(rh),
+                lo: rl,
+            },
+        )
+    }
+
+    pub fn div_u(self, rhs: DepictI64) -> DepictI64 {
+        self.div_rem_u(rhs).0
+    }
+
+    pub fn rem_u(self, rhs: DepictI64) -> DepictI64 {
+        self.div_rem_u(rhs).1
+    }
+
+    /// 絶対値で割ってから符号を戻す
+    pub fn div_rem_s(self, rhs: DepictI64) -> (DepictI64, DepictI64) {
+        let neg_lhs = self.hi < 0f64;
+        let neg_rhs = rhs.hi < 0f64;
+        let lhs = if neg_lhs { -self } else { self };
+        let rhs = if neg_rhs { -rhs } else { rhs };
+
+        let (q, r) = lhs.div_rem_u(rhs);
+        (
+            if neg_lhs != neg_rhs { -q } else { q },
+            if neg_lhs { -r } else { r },
+        )
+    }
+
+    pub fn div_s(self, rhs: DepictI64) -> DepictI64 {
+        self.div_rem_s(rhs).0
+    }
+
+    pub fn rem_s(self, rhs: DepictI64) -> DepictI64 {
+        self.div_rem_s(rhs).1
+    }
+
+    // shift ===================================================================
+
+    #[inline]
+    fn shift_amount(rhs: DepictI64) -> f64 {
+        sb_mod(rhs.lo, 64f64)
+    }
+
+    fn shift_left(self, n: f64) -> DepictI64 {
+        if n >= 32f64 {
+            DepictI64 {
+                hi: wrap32(to_u32(self.lo * pow2(n - 32f64))),
+                lo: 0f64,
+            }
+        } else {
+            DepictI64 {
+                hi: wrap32(to_u32(self.uhi() * pow2(n)) + (self.lo / pow2(32f64 - n)).floor()),
+                lo: to_u32(self.lo * pow2(n)),
+            }
+        }
+    }
+
+    pub fn shr_s(self, rhs: DepictI64) -> DepictI64 {
+        let n = Self::shift_amount(rhs);
+        if n >= 32f64 {
+            DepictI64 {
+                hi: (self.hi / FULL).floor(),
+                lo: to_u32((self.hi / pow2(n - 32f64)).floor()),
+            }
+        } else {
+            DepictI64 {
+                hi: (self.hi / pow2(n)).floor(),
+                lo: (self.lo / pow2(n)).floor() + to_u32(self.uhi() * pow2(32f64 - n)),
+            }
+        }
+    }
+
+    pub fn shr_u(self, rhs: DepictI64) -> DepictI64 {
+        let n = Self::shift_amount(rhs);
+        if n >= 32f64 {
+            DepictI64 {
+                hi: 0f64,
+                lo: (self.uhi() / pow2(n - 32f64)).floor(),
+            }
+        } else {
+            DepictI64 {
+                hi: wrap32((self.uhi() / pow2(n)).floor()),
+                lo: (self.lo / pow2(n)).floor() + to_u32(self.uhi() * pow2(32f64 - n)),
+            }
+        }
+    }
+
+    fn rotate_left(self, n: f64) -> DepictI64 {
+        // 32bit以上は上位と下位を入れ替える
+        let (h, l, n) = if n >= 32f64 {
+            (self.lo, self.uhi(), n - 32f64)
+        } else {
+            (self.uhi(), self.lo, n)
+        };
+        DepictI64 {
+            hi: wrap32(to_u32(h * pow2(n)) + (l / pow2(32f64 - n)).floor()),
+            lo: to_u32(l * pow2(n)) + (h / pow2(32f64 - n)).floor(),
+        }
+    }
+
+    pub fn rotl(self, rhs: DepictI64) -> DepictI64 {
+        self.rotate_left(Self::shift_amount(rhs))
+    }
+
+    pub fn rotr(self, rhs: DepictI64) -> DepictI64 {
+        self.rotate_left(sb_mod(64f64 - Self::shift_amount(rhs), 64f64))
+    }
+
+    // count ===================================================================
+
+    pub fn clz(self) -> DepictI64 {
+        let n = if self.hi != 0f64 {
+            DepictI32(self.hi).clz().0
+        } else {
+            DepictI32(self.lo).clz().0 + 32f64
+        };
+        DepictI64 { hi: 0f64, lo: n }
+    }
+
+    pub fn ctz(self) -> DepictI64 {
+        let n = if self.lo != 0f64 {
+            DepictI32(self.lo).ctz().0
+        } else {
+            DepictI32(self.hi).ctz().0 + 32f64
+        };
+        DepictI64 { hi: 0f64, lo: n }
+    }
+
+    pub fn popcnt(self) -> DepictI64 {
+        DepictI64 {
+            hi: 0f64,
+            lo: DepictI32(self.hi).popcnt().0 + DepictI32(self.lo).popcnt().0,
+        }
+    }
+}
+
+impl std::ops::Add for DepictI64 {
+    type Output = DepictI64;
+
+    fn add(self, rhs: DepictI64) -> Self::Output {
+        // 下位の和は2^33未満なので正確
+        DepictI64::new(self.hi + rhs.hi, self.lo + rhs.lo)
+    }
+}
+
+impl std::ops::Neg for DepictI64 {
+    type Output = DepictI64;
+
+    fn neg(self) -> Self::Output {
+        DepictI64::new(-self.hi, -self.lo)
+    }
+}
+
+impl std::ops::Sub for DepictI64 {
+    type Output = DepictI64;
+
+    fn sub(self, rhs: DepictI64) -> Self::Output {
+        DepictI64::new(self.hi - rhs.hi, self.lo - rhs.lo)
+    }
+}
+
+impl std::ops::Mul for DepictI64 {
+    type Output = DepictI64;
+
+    fn mul(self, rhs: DepictI64) -> Self::Output {
+        // 16bitずつに分けて、積が2^53を超えないようにする
+        let limbs = |x: DepictI64| {
+            [
+                sb_mod(x.lo, HALF_WORD),
+                (x.lo / HALF_WORD).floor(),
+                sb_mod(x.uhi(), HALF_WORD),
+                (x.uhi() / HALF_WORD).floor(),
+            ]
+        };
+        let a = limbs(self);
+        let b = limbs(rhs);
+
+        // 2^64以上の桁は捨てる
+        let r0 = a[0] * b[0];
+        let r1 = a[1] * b[0] + a[0] * b[1];
+        let r2 = a[2] * b[0] + a[1] * b[1] + a[0] * b[2];
+        let r3 = a[3] * b[0] + a[2] * b[1] + a[1] * b[2] + a[0] * b[3];
+
+        DepictI64::new(
+            (r1 / HALF_WORD).floor() + r2 + sb_mod(r3, HALF_WORD) * HALF_WORD,
+            r0 + sb_mod(r1, HALF_WORD) * HALF_WORD,
+        )
+    }
+}
+
+impl std::ops::BitAnd for DepictI64 {
+    type Output = DepictI64;
+
+    fn bitand(self, rhs: DepictI64) -> Self::Output {
+        DepictI64 {
+            hi: (DepictI32(self.hi) & DepictI32(rhs.hi)).0,
+            lo: to_u32((DepictI32(self.lo) & DepictI32(rhs.lo)).0),
+        }
+    }
+}
+
+impl std::ops::BitOr for DepictI64 {
+    type Output = DepictI64;
+
+    fn bitor(self, rhs: DepictI64) -> Self::Output {
+        DepictI64 {
+            hi: (DepictI32(self.hi) | DepictI32(rhs.hi)).0,
+            lo: to_u32((DepictI32(self.lo) | DepictI32(rhs.lo)).0),
+        }
+    }
+}
+
+impl std::ops::BitXor for DepictI64 {
+    type Output = DepictI64;
+
+    fn bitxor(self, rhs: DepictI64) -> Self::Output {
+        DepictI64 {
+            hi: (DepictI32(self.hi) ^ DepictI32(rhs.hi)).0,
+            lo: to_u32((DepictI32(self.lo) ^ DepictI32(rhs.lo)).0),
+        }
+    }
+}
+
+/// shift amount is taken modulo 64, as `i64::wrapping_shl`
+impl std::ops::Shl<u32> for DepictI64 {
+    type Output = DepictI64;
+
+    fn shl(self, rhs: u32) -> Self::Output {
+        self.shift_left(sb_mod(rhs as f64, 64f64))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::i64::DepictI64;
+
+    use rand::distributions::Uniform;
+    use rand::Rng;
+
+    const EDGE: [i64; 12] = [
+        0,
+        1,
+        -1,
+        2,
+        i64::MIN,
+        i64::MAX,
+        i32::MIN as i64,
+        i32::MAX as i64,
+        1 << 32,
+        (1 << 32) - 1,
+        -(1 << 32),
+        0x0123_4567_89ab_cdef,
+    ];
+
+    /// edge values first, then random ones
+    fn samples() -> Vec<(i64, i64)> {
+        let mut rng = rand::thread_rng();
+        let range = Uniform::new_inclusive(i64::MIN, i64::MAX);
+        // small values hit the fast paths
+        let small = Uniform::new_inclusive(-1000, 1000);
+
+        let mut samples = Vec::new();
+        for a in EDGE {
+            for b in EDGE {
+                samples.push((a, b));
+            }
+        }
+        for k in 0..100000 {
+            let (a, b) = match k % 4 {
+                0 => (rng.sample(small), rng.sample(small)),
+                1 => (rng.sample(range), rng.sample(small)),
+                _ => (rng.sample(range), rng.sample(range)),
+            };
+            samples.push((a, b));
+        }
+        samples
+    }
+
+    #[test]
+    fn test_conversion_round_trip() {
+        for a in EDGE {
+            assert_eq!(a, DepictI64::from(a).into());
+        }
+    }
+
+    #[test]
+    fn test_arithmetic_consistency_with_i64() {
+        for (a, b) in samples() {
+            let (x, y) = (DepictI64::from(a), DepictI64::from(b));
+            assert_eq!(a.wrapping_add(b), (x + y).into());
+            assert_eq!(a.wrapping_sub(b), (x - y).into());
+            assert_eq!(a.wrapping_mul(b), (x * y).into());
+            assert_eq!(a.wrapping_neg(), (-x).into());
+        }
+    }
+
+    #[test]
+    fn test_division_consistency_with_i64() {
+        for (a, b) in samples().into_iter().step_by(4) {
+            if b == 0 {
+                continue;
+            }
+            let (x, y) = (DepictI64::from(a), DepictI64::from(b));
+            assert_eq!(a.wrapping_div(b), x.div_s(y).into());
+            assert_eq!(a.wrapping_rem(b), x.rem_s(y).into());
+            assert_eq!(((a as u64) / (b as u64)) as i64, x.div_u(y).into());
+            assert_eq!(((a as u64) % (b as u64)) as i64, x.rem_u(y).into());
+        }
+    }
+
+    #[test]
+    fn test_bitwise_consistency_with_i64() {
+        for (a, b) in samples() {
+            let (x, y) = (DepictI64::from(a), DepictI64::from(b));
+            assert_eq!(a & b, (x & y).into());
+            assert_eq!(a | b, (x | y).into());
+            assert_eq!(a ^ b, (x ^ y).into());
+            assert_eq!(a.leading_zeros() as i64, x.clz().into());
+            assert_eq!(a.trailing_zeros() as i64, x.ctz().into());
+            assert_eq!(a.count_ones() as i64, x.popcnt().into());
+        }
+    }
+
+    #[test]
+    fn test_shift_consistency_with_i64() {
+        let mut rng = rand::thread_rng();
+        let range = Uniform::new_inclusive(i64::MIN, i64::MAX);
+        // amounts over 63 and negative ones are taken modulo 64
+        let amount = Uniform::new_inclusive(-70, 140);
+
+        for _ in 0..100000 {
+            let a = rng.sample(range);
+            let n = rng.sample(amount);
+            let (x, y) = (DepictI64::from(a), DepictI64::from(n));
+            assert_eq!(a.wrapping_shl(n as u32), (x << n as u32).into());
+            assert_eq!(a.wrapping_shr(n as u32), x.shr_s(y).into());
+            assert_eq!(
+                (a as u64).wrapping_shr(n as u32) as i64,
+                x.shr_u(y).into()
+            );
+            assert_eq!(a.rotate_left(n as u32 % 64), x.rotl(y).into());
+            assert_eq!(a.rotate_right(n as u32 % 64), x.rotr(y).into());
+        }
+    }
+
+    #[test]
+    fn test_comparison_consistency_with_i64() {
+        for (a, b) in samples().into_iter().chain(EDGE.iter().map(|&a| (a, a))) {
+            let (x, y) = (DepictI64::from(a), DepictI64::from(b));
+            let (ua, ub) = (a as u64, b as u64);
+            assert_eq!(a == b, x == y);
+            assert_eq!(a < b, x.lt_s(y));
+            assert_eq!(a > b, x.gt_s(y));
+            assert_eq!(a <= b, x.le_s(y));
+            assert_eq!(a >= b, x.ge_s(y));
+            assert_eq!(ua < ub, x.lt_u(y));
+            assert_eq!(ua > ub, x.gt_u(y));
+            assert_eq!(ua <= ub, x.le_u(y));
+            assert_eq!(ua >= ub, x.ge_u(y));
+        }
+    }
+
+    #[test]
+    fn test_i32_conversion_consistency() {
+        let mut rng = rand::thread_rng();
+        let range = Uniform::new_inclusive(i32::MIN, i32::MAX);
+
+        for _ in 0..100000 {
+            let a = rng.sample(range);
+            assert_eq!(a as i64, DepictI64::extend_i32_s(a as f64).into());
+            assert_eq!(
+                a as u32 as i64,
+                DepictI64::extend_i32_u(a as f64).into()
+            );
+            let x = DepictI64::from((a as i64) << 17 ^ 0x5555);
+            assert_eq!(((a as i64) << 17 ^ 0x5555) as i32 as f64, x.wrap_i32());
+        }
+    }
+
+    #[test]
+    fn test_f32_conversion_rounds_once() {
+        // 2^53 + 2^29 + 1 is just above the half of an f32 ulp
+        for a in [(1i64 << 53) + (1 << 29) + 1, -((1i64 << 53) + (1 << 29) + 1)] {
+            let x = DepictI64::from(a);
+            assert_eq!(a as f32, x.convert_f32_s().into());
+            assert_eq!(a as u64 as f32, x.convert_f32_u().into());
+        }
+    }
+
+    #[test]
+    fn test_float_conversion_consistency() {
+        let mut rng = rand::thread_rng();
+        let range = Uniform::new_inclusive(i64::MIN, i64::MAX);
+
+        for _ in 0..100000 {
+            let a = rng.sample(range);
+            let x = DepictI64::from(a);
+            assert_eq!(a as f64, x.convert_s());
+            assert_eq!(a as u64 as f64, x.convert_u());
+            assert_eq!(a as f32, x.convert_f32_s().into());
+            assert_eq!(a as u64 as f32, x.convert_f32_u().into());
+
+            let t = (a as f64).trunc();
+            if t < 9223372036854775808f64 {
+                assert_eq!(t as i64, DepictI64::from_integral(t).into());
+            }
+        }
+    }
+}
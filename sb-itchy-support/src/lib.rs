@@ -27,6 +27,31 @@ pub mod block_generator_into {
         }
     }
 
+    impl BlockGeneratorInto<Bib> for i64 {
+        #[inline]
+        fn to(self) -> Bib {
+            Bib::value(Biv::Number {
+                value: Value::Number(Number::Int(self)),
+            })
+        }
+    }
+
+    impl BlockGeneratorInto<Bib> for f64 {
+        #[inline]
+        fn to(self) -> Bib {
+            // json cannot hold NaN and Infinity, so let scratch cast them from text
+            if self.is_finite() {
+                Bib::value(Biv::Number {
+                    value: Value::Number(Number::Float(self)),
+                })
+            } else {
+                Bib::value(Biv::String {
+                    value: Value::Text(self.to_string().replace("inf", "Infinity").into()),
+                })
+            }
+        }
+    }
+
     impl BlockGeneratorInto<Bib> for &str {
         #[inline]
         fn to(self) -> Bib {
@@ -77,7 +102,7 @@ pub mod block_generator_into {
         };
     }
 
-    to_self!(Bib, Bfb, usize, i32, bool);
+    to_self!(Bib, Bfb, usize, i32, i64, f64, bool);
 
     macro_rules! ref_to {
         ($($typ:ty),*) => {
@@ -91,7 +116,7 @@ pub mod block_generator_into {
         };
     }
 
-    ref_to!(Bib, Bfb, usize, i32, bool);
+    ref_to!(Bib, Bfb, usize, i32, i64, f64, bool);
 
     #[macro_export]
     macro_rules! stack {
@@ -1,15 +1,16 @@
 use std::{
-    path::PathBuf,
+    path::{Path, PathBuf},
     process::{Command, Stdio},
-    vec,
 };
 
-use cargo_metadata::{CargoOpt, Message, MetadataCommand};
+use cargo_metadata::{CargoOpt, Message, Metadata, MetadataCommand};
 use clap::{Args, Parser, Subcommand};
 use colored::Colorize;
 use eyre::{Context, Result};
 use path_slash::PathBufExt as _;
 
+use crate::project_config::{OptPass, ProjectConfig, StackLayout, WasmOptPreset};
+
 /// Command line arguments
 #[derive(Parser, Debug)]
 // #[clap(name = "wasm2sb", author, version, about, long_about = None, arg_required_else_help(true))]
@@ -32,6 +33,12 @@ enum SubCommands {
         #[arg(short, long, default_value = "false")]
         debug: bool,
 
+        #[command(flatten)]
+        target: TargetSelection,
+
+        #[command(flatten)]
+        features: FeatureSelection,
+
         #[command(flatten)]
         common_args: Arg,
     },
@@ -45,6 +52,85 @@ enum SubCommands {
         #[command(flatten)]
         common_args: Arg,
     },
+
+    /// Print the signatures of the exported functions
+    Inspect {
+        /// Wasm file to inspect
+        wasm: String,
+
+        /// print json instead of the table
+        #[arg(long, default_value = "false")]
+        json: bool,
+    },
+}
+
+/// which target of the package is converted, the library by default
+#[derive(Args, Debug)]
+#[command(next_help_heading = "Target Selection")]
+pub struct TargetSelection {
+    /// Convert the library, it must have the cdylib crate type
+    #[arg(long, conflicts_with_all = ["bin", "example"])]
+    pub lib: bool,
+
+    /// Convert the specified binary
+    #[arg(long, value_name = "NAME", conflicts_with = "example")]
+    pub bin: Option<String>,
+
+    /// Convert the specified example
+    #[arg(long, value_name = "NAME")]
+    pub example: Option<String>,
+}
+
+impl TargetSelection {
+    fn cargo_args(&self) -> Vec<String> {
+        match (&self.bin, &self.example) {
+            (Some(bin), _) => vec!["--bin".into(), bin.clone()],
+            (_, Some(example)) => vec!["--example".into(), example.clone()],
+            _ => vec!["--lib".into()],
+        }
+    }
+
+    fn matches(&self, target: &cargo_metadata::Target) -> bool {
+        let is = |kind: &str| target.kind.iter().any(|k| k == kind);
+        match (&self.bin, &self.example) {
+            (Some(bin), _) => is("bin") && &target.name == bin,
+            (_, Some(example)) => is("example") && &target.name == example,
+            _ => is("cdylib"),
+        }
+    }
+}
+
+#[derive(Args, Debug)]
+#[command(next_help_heading = "Feature Selection")]
+pub struct FeatureSelection {
+    /// Space or comma separated list of features to activate
+    #[arg(short = 'F', long)]
+    pub features: Vec<String>,
+
+    /// Activate all available features
+    #[arg(long)]
+    pub all_features: bool,
+
+    /// Do not activate the `default` feature
+    #[arg(long)]
+    pub no_default_features: bool,
+}
+
+impl FeatureSelection {
+    fn cargo_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        if !self.features.is_empty() {
+            args.push("--features".into());
+            args.push(self.features.join(","));
+        }
+        if self.all_features {
+            args.push("--all-features".into());
+        }
+        if self.no_default_features {
+            args.push("--no-default-features".into());
+        }
+        args
+    }
 }
 
 #[derive(Args, Debug)]
@@ -64,16 +150,204 @@ pub struct Arg {
     /// input file
     #[arg(short, long)]
     pub input: Option<PathBuf>,
+
+    /// config file, `wasm2sb.toml` of the package or the current directory by default
+    #[arg(long)]
+    pub config: Option<PathBuf>,
+
+    /// wasm-opt preset
+    #[arg(long, value_enum)]
+    pub wasm_opt: Option<WasmOptPreset>,
+
+    /// wasm-opt pass run after the preset, can be repeated, replaces `passes` of the config
+    #[arg(long = "pass", value_enum, value_name = "PASS")]
+    pub passes: Vec<OptPass>,
+
+    /// removed from the `--pass` or config passes, can be repeated,
+    /// the passes of the preset are not affected, see `--no-default-passes`
+    #[arg(long = "remove-pass", value_enum, value_name = "PASS")]
+    pub removed_passes: Vec<OptPass>,
+
+    /// run only the passes given with `--pass`
+    #[arg(long)]
+    pub no_default_passes: bool,
+
+    /// write the optimized wasm to this path
+    #[arg(long, value_name = "PATH")]
+    pub emit_intermediate: Option<PathBuf>,
+
+    /// sprite the scripts are written to
+    #[arg(long)]
+    pub sprite: Option<String>,
+
+    /// initial pages of the linear memory
+    #[arg(long)]
+    pub memory_pages: Option<usize>,
+
+    /// lists can grow past 200000 items, as in some mods
+    #[arg(long)]
+    pub unlimited_lists: Option<bool>,
+
+    /// decode surrogate pairs in to_utf8
+    #[arg(long)]
+    pub surrogate_pair: Option<bool>,
+
+    /// how the scripts are placed on the canvas
+    #[arg(long, value_enum)]
+    pub layout: Option<StackLayout>,
+
+    /// generate the same ids for the same wasm, for reproducible output
+    #[arg(long)]
+    pub deterministic_ids: Option<bool>,
+
+    /// name the procedures after the functions, numbered if false
+    #[arg(long)]
+    pub readable_names: Option<bool>,
+
+    /// comment the procedures and the calls with their wasm offsets and source lines
+    #[arg(long)]
+    pub comments: Option<bool>,
+
+    /// trap on loads and stores out of the linear memory
+    #[arg(long)]
+    pub bounds_checks: Option<bool>,
+
+    /// keep the bindgen heap in 2^N slots of a buddy allocator
+    #[arg(long, value_name = "N")]
+    pub buddy_heap: Option<usize>,
+}
+
+impl Arg {
+    /// the flags given on the command line win
+    fn override_config(&self, config: &mut ProjectConfig) {
+        if let Some(input) = &self.input {
+            config.template = Some(input.clone());
+        }
+        if let Some(wasm_opt) = self.wasm_opt {
+            config.wasm_opt = wasm_opt;
+        }
+        if !self.passes.is_empty() {
+            config.passes = self.passes.clone();
+        }
+        config
+            .removed_passes
+            .extend(self.removed_passes.iter().copied());
+        if self.no_default_passes {
+            config.default_passes = false;
+        }
+        if let Some(path) = &self.emit_intermediate {
+            config.emit_intermediate = Some(path.clone());
+        }
+        if let Some(sprite) = &self.sprite {
+            config.sprite = Some(sprite.clone());
+        }
+        if let Some(memory_pages) = self.memory_pages {
+            config.memory_pages = Some(memory_pages);
+        }
+        if let Some(unlimited_lists) = self.unlimited_lists {
+            config.unlimited_lists = unlimited_lists;
+        }
+        if let Some(surrogate_pair) = self.surrogate_pair {
+            config.surrogate_pair = surrogate_pair;
+        }
+        if let Some(layout) = self.layout {
+            config.layout.strategy = layout;
+        }
+        if let Some(deterministic_ids) = self.deterministic_ids {
+            config.deterministic_ids = deterministic_ids;
+        }
+        if let Some(readable_names) = self.readable_names {
+            config.readable_names = readable_names;
+        }
+        if let Some(comments) = self.comments {
+            config.comments = comments;
+        }
+        if let Some(bounds_checks) = self.bounds_checks {
+            config.bounds_checks = bounds_checks;
+        }
+        if let Some(buddy_heap) = self.buddy_heap {
+            config.buddy_heap = Some(buddy_heap);
+        }
+    }
+}
+
+/// the package at the path, or for a virtual workspace the one default member
+/// with the target to convert
+fn select_package<'a>(
+    metadata: &'a Metadata,
+    target: &TargetSelection,
+) -> Result<&'a cargo_metadata::Package> {
+    if let Some(root) = metadata.root_package() {
+        return Ok(root);
+    }
+
+    // the default members need cargo 1.71, older than the rust-version of the workspace
+    let candidates = metadata
+        .workspace_default_packages()
+        .into_iter()
+        .filter(|package| package.targets.iter().any(|t| target.matches(t)))
+        .collect::<Vec<_>>();
+
+    match candidates[..] {
+        [package] => Ok(package),
+        [] => Err(eyre::eyre!(
+            "no member of the virtual workspace has a target for {:?}, \
+             pass the directory of the package with `-p`",
+            target
+        )),
+        _ => Err(eyre::eyre!(
+            "the virtual workspace has several members with a target for {:?}, \
+             pass the directory of one of them with `-p`: {}",
+            target,
+            candidates
+                .iter()
+                .map(|package| package.name.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        )),
+    }
 }
 
 impl CommandLineArgs {
-    pub fn parse_and_check() -> Result<(Self, PathBuf)> {
+    /// `None` for `inspect`, which does not convert
+    pub fn common_args(&self) -> Option<&Arg> {
+        match &self.command {
+            SubCommands::Package { common_args, .. } => Some(common_args),
+            SubCommands::Wasm { common_args, .. } => Some(common_args),
+            SubCommands::Inspect { .. } => None,
+        }
+    }
+
+    /// `Some(json)` for `inspect`
+    pub fn inspect(&self) -> Option<bool> {
+        match &self.command {
+            SubCommands::Inspect { json, .. } => Some(*json),
+            _ => None,
+        }
+    }
+
+    /// the options, the wasm file to convert and the config with the flags applied
+    pub fn parse_and_check() -> Result<(Self, PathBuf, ProjectConfig)> {
         let opt = CommandLineArgs::parse();
 
-        match &opt.command {
+        if let Some(input) = opt.common_args().and_then(|args| args.input.as_ref()) {
+            if !input.exists() {
+                return Err(eyre::eyre!("Input file not found: {:?}", input));
+            }
+        }
+
+        let explicit_config = match opt.common_args().and_then(|args| args.config.as_ref()) {
+            Some(path) => Some(ProjectConfig::from_file(path)?),
+            None => None,
+        };
+        let config;
+
+        let path: PathBuf = match &opt.command {
             SubCommands::Package {
                 package,
                 debug,
+                target,
+                features,
                 common_args,
             } => {
                 // let package = match PathBuf::from(package).canonicalize() {
@@ -88,67 +362,240 @@ impl CommandLineArgs {
                     .exec()
                     .wrap_err("failed to find Cargo.toml")?;
 
-                let mut options = vec!["build", "--message-format=json-render-diagnostics"];
+                let selected = select_package(&metadata, target)?;
+                let package_dir = selected
+                    .manifest_path
+                    .parent()
+                    .map_or_else(|| package.clone(), |dir| dir.as_std_path().to_path_buf());
+
+                // wasm2sb.toml wins over [package.metadata.wasm2sb]
+                config = match ProjectConfig::find(&package)? {
+                    Some(config) => Some(config),
+                    None => Some(ProjectConfig::from_metadata(
+                        &selected.metadata,
+                        &package_dir,
+                    )?),
+                };
+
+                let mut options = vec![
+                    "build".to_string(),
+                    "--message-format=json-render-diagnostics".into(),
+                ];
                 if !debug {
-                    options.push("--release");
+                    options.push("--release".into());
+                }
+                options.push("--target=wasm32-unknown-unknown".into());
+                if metadata.root_package().is_none() {
+                    options.push(format!("--package={}", selected.name));
                 }
-                options.push("--target=wasm32-unknown-unknown");
-
-                if common_args.quiet {
-                    let mut command = Command::new("cargo")
-                        .args(options)
-                        .current_dir(&package)
-                        .stdout(Stdio::piped())
-                        .stderr(Stdio::piped())
-                        .spawn()
-                        .unwrap();
-
-                    println!("{}{:?}", "Building package: ".green(), package);
-
-                    let reader = std::io::BufReader::new(command.stdout.take().unwrap());
-
-                    for message in cargo_metadata::Message::parse_stream(reader) {
-                        match message.unwrap() {
-                            Message::CompilerMessage(_) => {}
-                            Message::CompilerArtifact(_) => {}
-                            Message::BuildScriptExecuted(_) => {}
-                            Message::BuildFinished(f) => {
-                                if f.success {
-                                    println!("{}", "Build succeeded".green());
-                                } else {
-                                    println!("{}", "Build failed".red());
-                                    return Err(eyre::eyre!("Build failed"));
-                                }
+                options.extend(target.cargo_args());
+                options.extend(features.cargo_args());
+
+                // the diagnostics are rendered to stderr
+                let mut command = Command::new("cargo")
+                    .args(options)
+                    .current_dir(&package)
+                    .stdout(Stdio::piped())
+                    .stderr(if common_args.quiet {
+                        Stdio::null()
+                    } else {
+                        Stdio::inherit()
+                    })
+                    .spawn()
+                    .wrap_err("failed to run cargo build")?;
+
+                println!("{}{:?}", "Building package: ".green(), package);
+
+                let reader = std::io::BufReader::new(command.stdout.take().unwrap());
+
+                // the artifacts of dependencies are skipped
+                let root = &selected.id;
+                let mut artifact = None;
+                for message in cargo_metadata::Message::parse_stream(reader) {
+                    match message.wrap_err("failed to read cargo messages")? {
+                        Message::CompilerMessage(_) => {}
+                        Message::CompilerArtifact(compiled) => {
+                            if *root != compiled.package_id || !target.matches(&compiled.target) {
+                                continue;
+                            }
+                            if let Some(wasm) = compiled
+                                .filenames
+                                .iter()
+                                .find(|file| file.extension() == Some("wasm"))
+                            {
+                                artifact = Some(wasm.clone());
                             }
-                            _ => (), // Unknown message
                         }
+                        Message::BuildScriptExecuted(_) => {}
+                        Message::BuildFinished(f) => {
+                            if f.success {
+                                println!("{}", "Build succeeded".green());
+                            } else {
+                                println!("{}", "Build failed".red());
+                                return Err(eyre::eyre!("Build failed"));
+                            }
+                        }
+                        _ => (), // Unknown message
                     }
                 }
+                command.wait().wrap_err("failed to wait for cargo build")?;
 
                 println!("{}", "Build finished\n".green());
 
-                let path = if *debug {
-                    metadata
-                        .target_directory
-                        .join("wasm32-unknown-unknown/debug/wasm_sb_bindgen_testcode.wasm")
-                } else {
-                    metadata
-                        .target_directory
-                        .join("wasm32-unknown-unknown/release/wasm_sb_bindgen_testcode.wasm")
-                };
+                let path = artifact.ok_or_else(|| {
+                    eyre::eyre!(
+                        "no wasm artifact found for {:?}, the library needs the cdylib crate type",
+                        target
+                    )
+                })?;
 
-                Ok((opt, path.into()))
+                path.into()
             }
-            SubCommands::Wasm {
-                wasm,
-                common_args: _common_args,
-            } => {
+            SubCommands::Wasm { wasm, .. } | SubCommands::Inspect { wasm, .. } => {
                 let wasm_path = PathBuf::from(&wasm);
                 if !wasm_path.exists() {
                     return Err(eyre::eyre!("Wasm file not found: {:?}", wasm_path));
                 }
-                Ok((opt, wasm_path))
+                config = ProjectConfig::find(Path::new("."))?;
+                wasm_path
+            }
+        };
+
+        let mut config = explicit_config.or(config).unwrap_or_default();
+        if let Some(args) = opt.common_args() {
+            args.override_config(&mut config);
+        }
+
+        Ok((opt, path, config))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::CommandLineArgs;
+    use crate::project_config::{LayoutConfig, OptPass, ProjectConfig, StackLayout, WasmOptPreset};
+
+    /// `config` with the flags of `wasm2sb wasm module.wasm <flags>` applied
+    fn override_config(flags: &[&str], mut config: ProjectConfig) -> ProjectConfig {
+        let args = ["wasm2sb", "wasm", "module.wasm"].iter().chain(flags);
+        let opt = CommandLineArgs::try_parse_from(args).unwrap();
+        opt.common_args().unwrap().override_config(&mut config);
+        config
+    }
+
+    #[test]
+    fn test_override_config() {
+        for (flags, expected) in [
+            (vec![], ProjectConfig::default()),
+            (
+                vec!["--input", "template.sb3", "--wasm-opt", "o1"],
+                ProjectConfig {
+                    template: Some("template.sb3".into()),
+                    wasm_opt: WasmOptPreset::O1,
+                    ..ProjectConfig::default()
+                },
+            ),
+            (
+                vec!["--sprite", "Cat", "--memory-pages", "2"],
+                ProjectConfig {
+                    sprite: Some("Cat".into()),
+                    memory_pages: Some(2),
+                    ..ProjectConfig::default()
+                },
+            ),
+            (
+                vec!["--surrogate-pair", "false", "--unlimited-lists", "true"],
+                ProjectConfig {
+                    surrogate_pair: false,
+                    unlimited_lists: true,
+                    ..ProjectConfig::default()
+                },
+            ),
+            // `--pass` replaces the passes, `--remove-pass` adds to the removed ones
+            (
+                vec!["--pass", "dce", "--pass", "vacuum", "--remove-pass", "dce"],
+                ProjectConfig {
+                    passes: vec![OptPass::Dce, OptPass::Vacuum],
+                    removed_passes: vec![OptPass::Dce],
+                    ..ProjectConfig::default()
+                },
+            ),
+            (
+                vec!["--no-default-passes", "--emit-intermediate", "opt.wasm"],
+                ProjectConfig {
+                    default_passes: false,
+                    emit_intermediate: Some("opt.wasm".into()),
+                    ..ProjectConfig::default()
+                },
+            ),
+            (
+                vec!["--buddy-heap", "8"],
+                ProjectConfig {
+                    buddy_heap: Some(8),
+                    ..ProjectConfig::default()
+                },
+            ),
+            (
+                vec!["--layout", "grid"],
+                ProjectConfig {
+                    layout: LayoutConfig {
+                        strategy: StackLayout::Grid,
+                        ..LayoutConfig::default()
+                    },
+                    ..ProjectConfig::default()
+                },
+            ),
+        ] {
+            assert_eq!(
+                override_config(&flags, ProjectConfig::default()),
+                expected,
+                "{flags:?}"
+            );
+        }
+    }
+
+    /// an `Option<bool>` flag left out keeps the value of the config
+    #[test]
+    fn test_override_config_bools() {
+        type Get = fn(&ProjectConfig) -> bool;
+        for (flag, get) in [
+            ("--surrogate-pair", (|c| c.surrogate_pair) as Get),
+            ("--unlimited-lists", |c| c.unlimited_lists),
+            ("--deterministic-ids", |c| c.deterministic_ids),
+            ("--readable-names", |c| c.readable_names),
+            ("--comments", |c| c.comments),
+            ("--bounds-checks", |c| c.bounds_checks),
+        ] {
+            for value in [false, true] {
+                let value_str = value.to_string();
+                let config = override_config(&[flag, &value_str], ProjectConfig::default());
+                assert_eq!(get(&config), value, "{flag} {value}");
+                assert_eq!(get(&override_config(&[], config)), value, "{flag} {value}");
             }
         }
     }
+
+    #[test]
+    fn test_override_passes() {
+        let config = ProjectConfig {
+            passes: vec![OptPass::Dce, OptPass::Vacuum],
+            removed_passes: vec![OptPass::Vacuum],
+            ..ProjectConfig::default()
+        };
+        assert_eq!(override_config(&[], config.clone()), config);
+
+        let config = override_config(&["--remove-pass", "dce"], config);
+        assert_eq!(config.passes, vec![OptPass::Dce, OptPass::Vacuum]);
+        assert_eq!(config.removed_passes, vec![OptPass::Vacuum, OptPass::Dce]);
+    }
+
+    #[test]
+    fn test_bool_flags_take_a_value() {
+        for flags in [&["--surrogate-pair"][..], &["--surrogate-pair", "no"]] {
+            let args = ["wasm2sb", "wasm", "module.wasm"].iter().chain(flags);
+            assert!(CommandLineArgs::try_parse_from(args).is_err(), "{flags:?}");
+        }
+    }
 }
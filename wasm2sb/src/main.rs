@@ -4,14 +4,22 @@ use config::CommandLineArgs;
 use scratch::test_data::test_project;
 
 use crate::{
-    scratch::block::buddy_block::generate_buddy_block,
-    util::get_type_from_func,
-    wasm::adjust::{check_rm_import_fn, rm_export_fn, wasm_opt_module},
+    project_config::ProjectConfig,
+    scratch::{
+        block::reformat::names::readable_names,
+        rewrite_dependency::rewrite_list,
+        sb3::{ProjectZip, StackGroup},
+    },
+    wasm::{
+        adjust::{check_rm_import_fn, rm_export_fn, wasm_opt_module},
+        source_map::SourceMap,
+    },
 };
 use eyre::{Result, WrapErr};
 
 pub mod config;
 pub mod pre_name;
+pub mod project_config;
 pub mod scratch;
 pub mod test_exec;
 pub mod util;
@@ -25,15 +33,59 @@ fn main() -> Result<()> {
 
     // 🌠
 
-    let (_config, path) =
+    let (config, path, project_config) =
         CommandLineArgs::parse_and_check().wrap_err("failed to parse command line arguments")?;
 
-    let mut project = test_project().unwrap();
-    // println!("{:#?}", blocks);
+    if let Some(json) = config.inspect() {
+        let data = std::fs::read(&path).wrap_err(format!("failed to read file: {:?}", path))?;
+        let ty = wasm::get_ty(&data).wrap_err("failed to get type from wasm")?;
+        print!("{}", wasm::inspect::inspect(&ty, json)?);
+        return Ok(());
+    }
+    let args = config.common_args().unwrap();
 
     let data = std::fs::read(&path).wrap_err(format!("failed to read file: {:?}", path))?;
+    let project = convert(&data, &project_config)?;
+
+    println!("{}", "zipping project...".green().bold());
+
+    #[cfg(not(target_arch = "wasm32"))]
+    project
+        .zip_file(&args.output)
+        .wrap_err(format!("failed to write output: {:?}", args.output))?;
 
-    let ty = wasm::get_ty(&data).wrap_err(format!("failed to get type from wasm"))?;
+    println!("{}", "project zipped successfully!".green().bold());
+
+    // test_exec()?;
+
+    Ok(())
+}
+
+/// the project generated from the wasm module `data`, ready to be zipped
+fn convert(data: &[u8], project_config: &ProjectConfig) -> Result<ProjectZip> {
+    pre_name::set_prefixes(
+        project_config.func_prefix.clone(),
+        project_config.utf8_prefix.clone(),
+    );
+
+    // the template project, the empty one is embedded
+    let mut project = match &project_config.template {
+        Some(template) => ProjectZip::new(template.to_string_lossy().into_owned())
+            .wrap_err(format!("failed to load template project: {:?}", template))?,
+        None => test_project()?,
+    };
+    project.set_sprite_name(project_config.sprite.clone());
+    project.set_layout(project_config.layout);
+    // println!("{:#?}", blocks);
+
+    project.set_id_seed(
+        project_config
+            .deterministic_ids
+            .then(|| scratch::generate_id::id_seed(data)),
+    );
+
+    let ty = wasm::get_ty(data).wrap_err(format!("failed to get type from wasm"))?;
+    println!("{}", "schema version loaded successfully!".green().bold());
     println!(
         "{}",
         "original func type loaded successfully!".green().bold()
@@ -41,77 +93,132 @@ fn main() -> Result<()> {
 
     // println!("ty: {:?}", ty);
 
-    let mut module = walrus::Module::from_buffer(&data).unwrap();
+    // DWARF is kept through `rm_export_fn` for the comments
+    let mut module = walrus::ModuleConfig::new()
+        .generate_dwarf(project_config.comments)
+        .parse(data)
+        .unwrap();
     println!("{}", "module loaded successfully!".green().bold());
     rm_export_fn(&mut module, ty.keys().map(|k| k.to_string()).collect())?;
     println!(
         "{}",
         "describe function removed successfully!".green().bold()
     );
-    let module = wasm_opt_module(module)?;
+    let (module, data) = wasm_opt_module(module, project_config)?;
     println!("{}", "module optimized successfully!".green().bold());
     check_rm_import_fn(&module)?;
 
     // log::info!("module: {:#?}", module.imports);
     // log::info!("module: {:#?}", module.exports);
 
-    let function_types = &module.types;
-
     let mut ctx = GenCtx::new();
     ctx.functions_count = module.funcs.iter().count() + module.exports.iter().count();
-
-    scratch::block::to_utf8::generator::to_utf8_generator(&mut project);
-
-    // for function in module.funcs.iter() {
-    //     // println!("{:?}", function.idx);
-    //     // println!("{:?}", function.start);
-
-    //     let func_type = get_type_from_func(&function, function_types);
-    //     // println!("function: {:?}", function);
-    //     // println!("func_type: {:?}", func_type);
-
-    //     match &function.kind {
-    //         walrus::FunctionKind::Import(import) => {
-    //             println!("import {:?}", function.id());
-    //             println!("{:?}", import);
-    //         }
-    //         walrus::FunctionKind::Local(_locals) => {
-    //             println!("local {:?}", function.id());
-    //             // println!("{:?}", locals);
-    //             println!("{:?}", func_type);
-    //             println!("");
-    //         }
-    //         walrus::FunctionKind::Uninitialized(_) => todo!(),
-    //     };
-
-    //     let stack_builders = project.generate_func_block(function, func_type, &mut ctx);
-    //     project.add_stack_builders(stack_builders);
-
-    //     // println!("{}", serde_json::to_string(&blocks).unwrap());
-
-    //     // println!("func: {:#?}", function);
-
-    //     // if i == 1 {
-    //     //     break;
-    //     // }
-    // }
-
-    // let stack_builders = generate_buddy_block(&mut project, 16, 4)?;
-    // project.add_stack_builders(stack_builders);
+    if project_config.readable_names {
+        (ctx.func_names, ctx.param_names) = readable_names(&module);
+    }
+    if project_config.comments {
+        let source_map = SourceMap::new(&data).wrap_err("failed to read debug info")?;
+        if !source_map.has_lines() {
+            log::warn!("no DWARF in the module, the comments have only the wasm offsets");
+        }
+        ctx.source_map = Some(source_map);
+    }
+
+    project.set_group(StackGroup::Utf8);
+    scratch::block::to_utf8::generator::to_utf8_generator(
+        &mut project,
+        &mut ctx,
+        project_config.surrogate_pair,
+    );
+    project.set_group(StackGroup::Runtime);
+    scratch::block::trap::trap_generator(&mut project);
+    scratch::block::i32::generator::i32_generator(&mut project, &mut ctx);
+    scratch::block::i32::bitwise::i32_bitwise_generator(&mut project, &mut ctx);
+    scratch::block::i64::generator::i64_generator(&mut project, &mut ctx);
+    scratch::block::f32::generator::f32_generator(&mut project, &mut ctx);
+    scratch::block::memory::generator::memory_generator(
+        &mut project,
+        &module,
+        &mut ctx,
+        project_config.memory_pages,
+        project_config.unlimited_lists,
+        project_config.bounds_checks,
+    )
+    .wrap_err("failed to generate linear memory")?;
+    scratch::block::global::global_generator(
+        &mut project,
+        &module,
+        &mut ctx,
+        &project_config.globals,
+    )
+    .wrap_err("failed to generate globals")?;
+    scratch::block::bindgen::generator::bindgen_generator(
+        &mut project,
+        &module,
+        &mut ctx,
+        project_config.buddy_heap,
+    )
+    .wrap_err("failed to generate wasm-sb-bindgen imports")?;
+    scratch::block::bindgen::export::export_generator(&mut project, &module, &mut ctx, &ty)
+        .wrap_err("failed to generate exports")?;
+    scratch::block::table::table_generator(&mut project, &module, &ctx)
+        .wrap_err("failed to generate call_indirect dispatch")?;
+
+    project.set_group(StackGroup::User);
+    for function in module.funcs.iter() {
+        let stack_builders = project
+            .generate_func_block(&module, function, &mut ctx)
+            .wrap_err(format!(
+                "failed to generate function block: {:?} {:?}",
+                function.id(),
+                function.name
+            ))?;
+        project.add_stack_builders(stack_builders);
+    }
+
+    rewrite_list(&mut project, &ctx);
 
     println!("{}", "project building!".green().bold());
 
-    project.build();
+    project.build().wrap_err("failed to build project")?;
 
     println!("{}", "project generated successfully!".green().bold());
-    println!("{}", "zipping project...".green().bold());
 
-    #[cfg(not(target_arch = "wasm32"))]
-    project.zip_file("scratch/out.sb3")?;
-
-    println!("{}", "project zipped successfully!".green().bold());
-
-    // test_exec()?;
+    Ok(project)
+}
 
-    Ok(())
+#[cfg(test)]
+mod tests {
+    use super::convert;
+    use crate::{
+        project_config::{ProjectConfig, WasmOptPreset},
+        scratch::test_data::test_wasm_binary,
+    };
+
+    #[test]
+    fn test_deterministic_ids() {
+        let config = ProjectConfig {
+            wasm_opt: WasmOptPreset::None,
+            deterministic_ids: true,
+            ..ProjectConfig::default()
+        };
+        let data = test_wasm_binary();
+        let first = convert(&data, &config).unwrap().zip().unwrap();
+        let second = convert(&data, &config).unwrap().zip().unwrap();
+        // not assert_eq, the sb3 is too long to print
+        assert!(first == second, "the sb3 differs between two conversions");
+    }
+
+    #[test]
+    fn test_buddy_heap() {
+        let data = test_wasm_binary();
+        let config = |buddy_heap| ProjectConfig {
+            wasm_opt: WasmOptPreset::None,
+            buddy_heap,
+            ..ProjectConfig::default()
+        };
+        convert(&data, &config(Some(8))).unwrap();
+        // the tree does not fit in a list
+        assert!(convert(&data, &config(Some(20))).is_err());
+    }
 }
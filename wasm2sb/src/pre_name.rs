@@ -1 +1,40 @@
+use std::sync::OnceLock;
+
 pub const PRE_FUNC_NAME: &str = "__wasm_internal_func_";
+pub const PRE_UNICODE: &str = "to_utf8_";
+
+// the prefixes from the config, set once before generating
+static PREFIXES: OnceLock<(String, String)> = OnceLock::new();
+
+pub fn set_prefixes(func: String, unicode: String) {
+    if PREFIXES.set((func, unicode)).is_err() {
+        log::warn!("prefixes are already set");
+    }
+}
+
+pub fn pre_func_name() -> &'static str {
+    PREFIXES.get().map_or(PRE_FUNC_NAME, |(func, _)| func)
+}
+
+pub fn pre_unicode() -> &'static str {
+    PREFIXES.get().map_or(PRE_UNICODE, |(_, unicode)| unicode)
+}
+
+pub const GLOBAL_STACK_LIST: &str = "__wasm_global_stack";
+pub const LOCAL_STACK_LIST: &str = "__wasm_local_stack";
+pub const FUNCTION_STACK_LIST: &str = "__wasm_function_stack";
+
+/// the message of the last trap
+pub const TRAP_VAR: &str = "__wasm_trap";
+
+pub const MEMORY_LIST: &str = "__wasm_memory";
+pub const POW2_LIST: &str = "__wasm_pow2";
+
+pub const I32_AND_LIST: &str = "__wasm_i32_and";
+pub const I32_OR_LIST: &str = "__wasm_i32_or";
+pub const I32_XOR_LIST: &str = "__wasm_i32_xor";
+pub const I32_POPCNT_LIST: &str = "__wasm_i32_popcnt";
+
+pub const SB_HEAP_LIST: &str = "__wasm_sb_heap";
+pub const SB_HEAP_TYPE_LIST: &str = "__wasm_sb_heap_type";
+pub const SB_HEAP_FREE_LIST: &str = "__wasm_sb_heap_free";
@@ -0,0 +1,378 @@
+// conversion options from `wasm2sb.toml` or `[package.metadata.wasm2sb]`
+// the command line flags override them
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use clap::ValueEnum;
+use eyre::{Context, Result};
+use serde::Deserialize;
+use wasm_opt::Pass;
+
+use crate::pre_name::{PRE_FUNC_NAME, PRE_UNICODE};
+
+pub const CONFIG_FILE_NAME: &str = "wasm2sb.toml";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum WasmOptPreset {
+    /// do not run wasm-opt
+    None,
+    O1,
+    O2,
+    O3,
+    O4,
+    Os,
+    #[default]
+    Oz,
+}
+
+/// passes that change how the module lowers to blocks
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum OptPass {
+    /// sign extension ops to shifts
+    SignextLowering,
+    /// i64 params and results of imports and exports to i32 pairs
+    LegalizeJsInterface,
+    Inlining,
+    InliningOptimizing,
+    Precompute,
+    PrecomputePropagate,
+    LocalCse,
+    SimplifyLocals,
+    CoalesceLocals,
+    MergeBlocks,
+    RemoveUnusedBrs,
+    RemoveUnusedModuleElements,
+    Vacuum,
+    Dce,
+}
+
+impl OptPass {
+    pub fn pass(self) -> Pass {
+        match self {
+            OptPass::SignextLowering => Pass::SignextLowering,
+            OptPass::LegalizeJsInterface => Pass::LegalizeJsInterface,
+            OptPass::Inlining => Pass::Inlining,
+            OptPass::InliningOptimizing => Pass::InliningOptimizing,
+            OptPass::Precompute => Pass::Precompute,
+            OptPass::PrecomputePropagate => Pass::PrecomputePropagate,
+            OptPass::LocalCse => Pass::LocalCse,
+            OptPass::SimplifyLocals => Pass::SimplifyLocals,
+            OptPass::CoalesceLocals => Pass::CoalesceLocals,
+            OptPass::MergeBlocks => Pass::MergeBlocks,
+            OptPass::RemoveUnusedBrs => Pass::RemoveUnusedBrs,
+            OptPass::RemoveUnusedModuleElements => Pass::RemoveUnusedModuleElements,
+            OptPass::Vacuum => Pass::Vacuum,
+            OptPass::Dce => Pass::Dce,
+        }
+    }
+}
+
+/// how the scripts are placed on the canvas
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum StackLayout {
+    /// one column from the top of the existing scripts
+    #[default]
+    Column,
+    /// columns of `wrap_height`
+    Grid,
+    /// a grid for each of user, runtime and utf8 procedures
+    Grouped,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct LayoutConfig {
+    pub strategy: StackLayout,
+    /// a column is wrapped when it gets taller than this
+    pub wrap_height: i32,
+    pub column_width: i32,
+}
+
+impl Default for LayoutConfig {
+    fn default() -> Self {
+        LayoutConfig {
+            strategy: StackLayout::default(),
+            wrap_height: 8000,
+            column_width: 1600,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct ProjectConfig {
+    /// prefix of the generated procedures
+    pub func_prefix: String,
+    /// prefix of the to_utf8 procedures and lists
+    pub utf8_prefix: String,
+    pub wasm_opt: WasmOptPreset,
+    /// run the passes of the preset, `passes` are run after them
+    pub default_passes: bool,
+    /// `--pass` on the command line replaces this list
+    pub passes: Vec<OptPass>,
+    /// only removed from `passes`, wasm-opt can't drop a single pass of the preset,
+    /// it is all or nothing with `default_passes`
+    pub removed_passes: Vec<OptPass>,
+    /// where the optimized module is written, for debugging
+    pub emit_intermediate: Option<PathBuf>,
+    /// sb3 the project is generated into, the empty one is embedded
+    pub template: Option<PathBuf>,
+    /// without it, the characters out of the BMP become U+FFFD
+    pub surrogate_pair: bool,
+    /// sprite the scripts are written to, the first sprite by default
+    pub sprite: Option<String>,
+    /// initial pages of the linear memory, the module's by default
+    pub memory_pages: Option<usize>,
+    /// for mods without the 200000 items limit of lists, memory.grow is not capped by it
+    pub unlimited_lists: bool,
+    pub layout: LayoutConfig,
+    /// ids from the module and the block paths instead of random ones
+    pub deterministic_ids: bool,
+    /// labels from the name section and the rust symbols instead of numbers
+    pub readable_names: bool,
+    /// comments with the wasm offsets and the DWARF lines on the procedures and the calls
+    pub comments: bool,
+    /// trap on loads and stores out of the linear memory, they are ignored otherwise
+    pub bounds_checks: bool,
+    /// the bindgen heap is `2^n` slots of a buddy allocator, a free list that grows by default
+    pub buddy_heap: Option<usize>,
+    /// values of the imported globals by `module::name`
+    pub globals: BTreeMap<String, f64>,
+}
+
+impl Default for ProjectConfig {
+    fn default() -> Self {
+        ProjectConfig {
+            func_prefix: PRE_FUNC_NAME.into(),
+            utf8_prefix: PRE_UNICODE.into(),
+            wasm_opt: WasmOptPreset::default(),
+            default_passes: true,
+            passes: Vec::new(),
+            removed_passes: Vec::new(),
+            emit_intermediate: None,
+            template: None,
+            surrogate_pair: true,
+            sprite: None,
+            memory_pages: None,
+            unlimited_lists: false,
+            layout: LayoutConfig::default(),
+            deterministic_ids: false,
+            readable_names: true,
+            comments: false,
+            bounds_checks: false,
+            buddy_heap: None,
+            globals: BTreeMap::new(),
+        }
+    }
+}
+
+impl ProjectConfig {
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let text =
+            std::fs::read_to_string(path).wrap_err(format!("failed to read config: {:?}", path))?;
+        let mut config = toml::from_str::<ProjectConfig>(&text)
+            .wrap_err(format!("failed to parse config: {:?}", path))?;
+        config.resolve_paths(path.parent().unwrap_or(Path::new(".")));
+        Ok(config)
+    }
+
+    /// `[package.metadata.wasm2sb]`, paths are relative to the package
+    pub fn from_metadata(metadata: &serde_json::Value, package_dir: &Path) -> Result<Self> {
+        let mut config = match metadata.get("wasm2sb") {
+            Some(value) => serde_json::from_value::<ProjectConfig>(value.clone())
+                .wrap_err("failed to parse [package.metadata.wasm2sb]")?,
+            None => ProjectConfig::default(),
+        };
+        config.resolve_paths(package_dir);
+        Ok(config)
+    }
+
+    /// `wasm2sb.toml` in `dir` if it exists
+    pub fn find(dir: &Path) -> Result<Option<Self>> {
+        let path = dir.join(CONFIG_FILE_NAME);
+        if !path.exists() {
+            return Ok(None);
+        }
+        Self::from_file(&path).map(Some)
+    }
+
+    fn resolve_paths(&mut self, base: &Path) {
+        for path in [&mut self.emit_intermediate, &mut self.template]
+            .into_iter()
+            .flatten()
+        {
+            if path.is_relative() {
+                *path = base.join(&*path);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use super::{
+        LayoutConfig, OptPass, ProjectConfig, StackLayout, WasmOptPreset, CONFIG_FILE_NAME,
+    };
+
+    #[test]
+    fn test_parse_toml() {
+        let config = toml::from_str::<ProjectConfig>(
+            r#"
+            func-prefix = "f_"
+            wasm-opt = "o3"
+            template = "template.sb3"
+            surrogate-pair = false
+            sprite = "Cat"
+            memory-pages = 2
+            unlimited-lists = true
+            default-passes = false
+            passes = ["signext-lowering", "dce"]
+            removed-passes = ["dce"]
+            emit-intermediate = "opt.wasm"
+            buddy-heap = 8
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            config,
+            ProjectConfig {
+                func_prefix: "f_".into(),
+                wasm_opt: WasmOptPreset::O3,
+                template: Some("template.sb3".into()),
+                surrogate_pair: false,
+                sprite: Some("Cat".into()),
+                memory_pages: Some(2),
+                unlimited_lists: true,
+                default_passes: false,
+                passes: vec![OptPass::SignextLowering, OptPass::Dce],
+                removed_passes: vec![OptPass::Dce],
+                emit_intermediate: Some("opt.wasm".into()),
+                buddy_heap: Some(8),
+                ..ProjectConfig::default()
+            }
+        );
+
+        assert_eq!(
+            toml::from_str::<ProjectConfig>("").unwrap(),
+            ProjectConfig::default()
+        );
+    }
+
+    #[test]
+    fn test_parse_layout() {
+        let config = toml::from_str::<ProjectConfig>(
+            r#"
+            [layout]
+            strategy = "grouped"
+            wrap-height = 4000
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.layout,
+            LayoutConfig {
+                strategy: StackLayout::Grouped,
+                wrap_height: 4000,
+                ..LayoutConfig::default()
+            }
+        );
+
+        for text in [
+            "[layout]\nwrap_height = 1",
+            "[layout]\nstrategy = \"tree\"",
+            "layout = \"grid\"",
+        ] {
+            assert!(toml::from_str::<ProjectConfig>(text).is_err(), "{text}");
+        }
+    }
+
+    #[test]
+    fn test_parse_toml_errors() {
+        for text in [
+            // the keys are kebab-case
+            "memory_pages = 2",
+            "unknown = true",
+            "wasm-opt = \"o5\"",
+            "memory-pages = -1",
+            "buddy-heap = true",
+            "surrogate-pair = \"yes\"",
+            "bounds-checks = 1",
+            "comments = 1",
+            "readable-names = 1",
+            "deterministic-ids = 1",
+            "passes = [\"no-such-pass\"]",
+        ] {
+            assert!(toml::from_str::<ProjectConfig>(text).is_err(), "{text}");
+        }
+    }
+
+    #[test]
+    fn test_from_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(CONFIG_FILE_NAME);
+        std::fs::write(&path, "template = \"template.sb3\"\n").unwrap();
+
+        let config = ProjectConfig::find(dir.path()).unwrap().unwrap();
+        assert_eq!(config.template, Some(dir.path().join("template.sb3")));
+
+        std::fs::write(&path, "template = 1\n").unwrap();
+        assert!(ProjectConfig::find(dir.path()).is_err());
+
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(ProjectConfig::find(dir.path()).unwrap(), None);
+    }
+
+    #[test]
+    fn test_from_metadata() {
+        let dir = Path::new("/package");
+
+        let metadata = serde_json::json!({
+            "wasm2sb": { "template": "template.sb3", "sprite": "Cat" },
+            "other-tool": { "key": 1 },
+        });
+        let config = ProjectConfig::from_metadata(&metadata, dir).unwrap();
+        assert_eq!(config.template, Some(dir.join("template.sb3")));
+        assert_eq!(config.sprite.as_deref(), Some("Cat"));
+
+        for metadata in [
+            serde_json::Value::Null,
+            serde_json::json!({ "other-tool": {} }),
+        ] {
+            assert_eq!(
+                ProjectConfig::from_metadata(&metadata, dir).unwrap(),
+                ProjectConfig::default()
+            );
+        }
+
+        let metadata = serde_json::json!({ "wasm2sb": { "unknown": true } });
+        assert!(ProjectConfig::from_metadata(&metadata, dir).is_err());
+    }
+
+    #[test]
+    fn test_resolve_paths() {
+        let base = Path::new("/package");
+        for (template, resolved) in [
+            (None, None),
+            (Some("template.sb3"), Some("/package/template.sb3")),
+            (Some("../template.sb3"), Some("/package/../template.sb3")),
+            (Some("/templates/t.sb3"), Some("/templates/t.sb3")),
+        ] {
+            let mut config = ProjectConfig {
+                template: template.map(PathBuf::from),
+                emit_intermediate: template.map(PathBuf::from),
+                ..ProjectConfig::default()
+            };
+            config.resolve_paths(base);
+            assert_eq!(config.template, resolved.map(PathBuf::from), "{template:?}");
+            assert_eq!(config.emit_intermediate, config.template);
+        }
+    }
+}
@@ -0,0 +1,222 @@
+// the exported functions of `#[wasm_sb_bindgen]`, they return `WasmRet`
+// rustc returns a `WasmRet` of one primitive as is, and writes a larger one through a pointer
+// passed first, these exports get a procedure returning the primitives in __wasm_function_stack
+
+use std::collections::HashMap;
+
+use eyre::{eyre, Result};
+use sb_itchy::block::BlockInputBuilder;
+use sb_itchy_support::{block_generator_into::BlockGeneratorInto, blocks_wrapper::*};
+use walrus::{ExportItem, FunctionId, Global, Module, ValType};
+
+use crate::{
+    pre_name::pre_func_name,
+    scratch::{
+        block::{
+            define_procedure,
+            function_code::{concat, function_stack, global_stack, local_stack},
+            memory::memory_func_name,
+        },
+        sb3::ProjectZip,
+    },
+    wasm::{descriptor::Descriptor, inspect::abi_slots},
+    GenCtx,
+};
+
+type Bib = BlockInputBuilder;
+
+/// `WasmRet` has four primitives at most, each of them is an f64
+const RET_PRIMS: usize = 4;
+/// the return area on the shadow stack, kept 16 bytes aligned
+const RET_AREA: usize = 8 * RET_PRIMS;
+
+pub fn export_func_name(name: &str) -> String {
+    format!("{}export_{name}", pre_func_name())
+}
+
+/// `__stack_pointer`, or the first mutable i32 global without the name section like rustc emits
+pub fn stack_pointer(module: &Module) -> Option<&Global> {
+    module
+        .globals
+        .iter()
+        .find(|global| global.name.as_deref() == Some("__stack_pointer"))
+        .or_else(|| {
+            module
+                .globals
+                .iter()
+                .find(|global| global.mutable && global.ty == ValType::I32)
+        })
+}
+
+fn ret_descriptor(descriptor: &Descriptor) -> &Descriptor {
+    match descriptor {
+        Descriptor::Function(f) => &f.ret,
+        Descriptor::Closure(c) => &c.function.ret,
+        other => other,
+    }
+}
+
+/// the primitives of the `WasmRet` an export returns
+fn ret_prims(descriptor: &Descriptor) -> usize {
+    abi_slots(ret_descriptor(descriptor)).len()
+}
+
+/// the exports whose `WasmRet` is written through a pointer, with the number of primitives
+fn sret_exports(
+    module: &Module,
+    tys: &HashMap<String, Descriptor>,
+) -> Vec<(String, FunctionId, usize)> {
+    let mut exports = module
+        .exports
+        .iter()
+        .filter_map(|export| {
+            let ExportItem::Function(func) = export.item else {
+                return None;
+            };
+            let prims = ret_prims(tys.get(&export.name)?);
+            let ty = module.types.get(module.funcs.get(func).ty());
+            let sret =
+                prims > 1 && ty.results().is_empty() && ty.params().first() == Some(&ValType::I32);
+            sret.then(|| (export.name.clone(), func, prims))
+        })
+        .collect::<Vec<_>>();
+    exports.sort_by(|(a, _, _), (b, _, _)| a.cmp(b));
+    exports
+}
+
+/// `export_<name>` takes the arguments of the export without the pointer,
+/// and writes the primitives of the result to __wasm_function_stack 1..=4
+pub fn export_generator(
+    ctx: &mut ProjectZip,
+    module: &Module,
+    gen_ctx: &mut GenCtx,
+    tys: &HashMap<String, Descriptor>,
+) -> Result<()> {
+    let exports = sret_exports(module, tys);
+    if exports.is_empty() {
+        return Ok(());
+    }
+
+    let sp = stack_pointer(module)
+        .ok_or_else(|| eyre!("exports returning through a pointer need __stack_pointer"))?;
+    let sp_item = gen_ctx
+        .global_slots
+        .get(&sp.id().index())
+        .ok_or_else(|| eyre!("__stack_pointer is not generated"))?
+        + 1;
+
+    gen_ctx.reserve_function_stack(RET_PRIMS);
+    let base = gen_ctx.alloc_local_stack(RET_PRIMS);
+
+    let sp = || item_in_list(global_stack(), sp_item);
+    let set_sp = |value: Bib| replace_in_list(global_stack(), sp_item, value);
+    let reg = |k: usize| item_in_list(local_stack(), base + k + 1);
+    let set_reg = |k: usize, value: Bib| replace_in_list(local_stack(), base + k + 1, value);
+    let arg = |name: &str| custom_block_var_string_number(name);
+    let call =
+        |name: &str, args: Vec<(&str, Bib)>| call_custom_block(name, args.into_iter().collect());
+
+    for (name, func, prims) in exports {
+        let ty = module.types.get(module.funcs.get(func).ty());
+        let names = gen_ctx.gen_param_names(func.index(), ty.params())?;
+        let Some((ret_ptr, names)) = names.split_first() else {
+            continue;
+        };
+
+        let mut args = vec![(ret_ptr.as_str(), sp())];
+        args.extend(names.iter().map(|name| (name.as_str(), arg(name))));
+
+        // the primitives go through the registers, load_f64 uses the head of __wasm_function_stack
+        let mut body = vec![
+            set_sp(sub(sp(), RET_AREA)),
+            call(&gen_ctx.gen_func_name(func.index()), args),
+        ];
+        for k in 0..prims {
+            body.push(call(
+                &memory_func_name("load_f64"),
+                vec![("addr", add(sp(), 8 * k))],
+            ));
+            body.push(set_reg(k, item_in_list(function_stack(), 1)));
+        }
+        for k in 0..prims {
+            body.push(replace_in_list(function_stack(), k + 1, reg(k)));
+        }
+        body.push(set_sp(add(sp(), RET_AREA)));
+
+        let names = names.iter().map(String::as_str).collect::<Vec<_>>();
+        define_procedure(ctx, &export_func_name(&name), &names, concat(body));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::mem::size_of;
+
+    use wasm_sb_bindgen::{
+        convert::{ReturnWasmAbi, WasmAbi},
+        SbValue,
+    };
+
+    use super::{ret_prims, RET_PRIMS};
+    use crate::wasm::descriptor::{Descriptor, Function};
+
+    /// the f64s `WasmAbi::split` gives for the result of an export, `()` takes no room
+    fn split_prims<T: ReturnWasmAbi>() -> usize {
+        let sizes = [
+            size_of::<<T::Abi as WasmAbi>::Prim1>(),
+            size_of::<<T::Abi as WasmAbi>::Prim2>(),
+            size_of::<<T::Abi as WasmAbi>::Prim3>(),
+            size_of::<<T::Abi as WasmAbi>::Prim4>(),
+        ];
+        sizes.iter().sum::<usize>() / size_of::<f64>()
+    }
+
+    #[test]
+    fn test_ret_prims() {
+        use Descriptor as D;
+
+        let option = |d| D::Option(Box::new(d));
+        let result = |d| D::Result(Box::new(d));
+        let vector = |d| D::Vector(Box::new(d));
+        for (descriptor, prims) in [
+            (D::Unit, split_prims::<()>()),
+            (D::U32, split_prims::<u32>()),
+            (D::F64, split_prims::<f64>()),
+            (D::Boolean, split_prims::<bool>()),
+            (D::Char, split_prims::<char>()),
+            (D::Externref, split_prims::<SbValue>()),
+            (D::I64, split_prims::<i64>()),
+            (D::U64, split_prims::<u64>()),
+            (D::String, split_prims::<String>()),
+            (vector(D::U8), split_prims::<Vec<u8>>()),
+            (vector(D::F32), split_prims::<Box<[f32]>>()),
+            (option(D::F64), split_prims::<Option<f64>>()),
+            (option(D::U32), split_prims::<Option<u32>>()),
+            (option(D::Boolean), split_prims::<Option<bool>>()),
+            (option(D::I64), split_prims::<Option<i64>>()),
+            (option(D::String), split_prims::<Option<String>>()),
+            (option(vector(D::U8)), split_prims::<Option<Vec<u8>>>()),
+            (result(D::Unit), split_prims::<Result<(), SbValue>>()),
+            (result(D::U32), split_prims::<Result<u32, SbValue>>()),
+            (result(D::I64), split_prims::<Result<i64, SbValue>>()),
+            (result(D::String), split_prims::<Result<String, SbValue>>()),
+            (
+                result(option(D::F64)),
+                split_prims::<Result<Option<f64>, SbValue>>(),
+            ),
+        ] {
+            assert!(prims <= RET_PRIMS, "{descriptor:?}");
+            assert_eq!(ret_prims(&descriptor), prims, "{descriptor:?}");
+
+            // the result of a function is looked at, not the function
+            let function = D::Function(Box::new(Function {
+                arguments: vec![D::U32],
+                shim_idx: 0,
+                ret: descriptor.clone(),
+                inner_ret: None,
+            }));
+            assert_eq!(ret_prims(&function), prims, "{descriptor:?}");
+        }
+    }
+}
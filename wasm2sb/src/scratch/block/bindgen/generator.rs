@@ -0,0 +1,1024 @@
+use eyre::{eyre, Result};
+use sb_itchy::{block::BlockInputBuilder, data::ListBuilder, stack::StackBuilder};
+use sb_itchy_support::{block_generator_into::BlockGeneratorInto, blocks_wrapper::*, stack};
+use sb_sbity::value::{Number, ValueWithBool};
+use walrus::{ExportItem, ImportKind, Module};
+
+use crate::{
+    pre_name::{MEMORY_LIST, SB_HEAP_FREE_LIST, SB_HEAP_LIST, SB_HEAP_TYPE_LIST},
+    scratch::{
+        block::{
+            buddy_block::{buddy_block_func_name, generate_buddy_block},
+            define_procedure,
+            function_code::{bool_to_int, concat, function_stack, local_stack, to_u32, wrap_i32},
+            i32::i32_func_name,
+            memory::{memory_func_name, pow2},
+            reformat::procedures_definition::func_param_names,
+            to_utf8::{chars_list_name, utf8_func_name},
+            trap::trap,
+        },
+        sb3::ProjectZip,
+    },
+    GenCtx,
+};
+
+use super::{
+    bindgen_func_name, bindgen_import, Ret, SBIDX_FALSE, SBIDX_NULL, SBIDX_OFFSET, SBIDX_RESERVED,
+    SBIDX_TRUE,
+};
+
+type Bib = BlockInputBuilder;
+
+// registers in __wasm_local_stack
+const R0: usize = 0;
+const R1: usize = 1;
+const R2: usize = 2;
+const T: usize = 3;
+const S: usize = 4;
+const PTR: usize = 5;
+const LEN: usize = 6;
+const IDX: usize = 7;
+// the json procedures
+const C: usize = 8;
+const K: usize = 9;
+const N: usize = 10;
+const DEPTH: usize = 11;
+const QUOTE: usize = 12;
+const KEY: usize = 13;
+const REGISTERS_LEN: usize = 14;
+
+/// the digits of the `\u00XX` escapes
+const HEX_DIGITS: &str = "0123456789abcdef";
+
+/// the buddy allocator hands out one block per value, at the idx `base + offset`
+const BUDDY_HEAP_BASE: usize = SBIDX_RESERVED;
+const BUDDY_HEAP_BLOCK_SIZE: usize = 1;
+
+/// undefined, null, true and false, the value is what `debug_string` shows
+///
+/// with the buddy heap the `2^n` slots follow them, all free
+fn heap_init_data(buddy_heap: Option<usize>) -> (Vec<ValueWithBool>, Vec<ValueWithBool>) {
+    let text = |s: &str| ValueWithBool::Text(s.into());
+    let int = |n: i64| ValueWithBool::Number(Number::Int(n));
+    let mut values = vec![text("undefined"), text("null"), int(1), int(0)];
+    let mut types = vec![
+        text("undefined"),
+        text("null"),
+        text("boolean"),
+        text("boolean"),
+    ];
+    if let Some(n) = buddy_heap {
+        values.resize(values.len() + (1 << n), text(""));
+        types.resize(types.len() + (1 << n), text("free"));
+    }
+    (values, types)
+}
+
+/// procedures for the `__wasm_sb_bindgen_placeholder__` imports of `module`
+///
+/// the heap holds the types undefined, null, boolean, number, string, bigint, error and function,
+/// objects and arrays are kept as their JSON text
+///
+/// the slots are handed out by a buddy allocator of `2^n` blocks with `buddy_heap`,
+/// the heap can't grow past them, otherwise freed slots go to a free list
+pub fn bindgen_generator(
+    ctx: &mut ProjectZip,
+    module: &Module,
+    gen_ctx: &mut GenCtx,
+    buddy_heap: Option<usize>,
+) -> Result<()> {
+    // checks `n` before the slots are made
+    if let Some(n) = buddy_heap {
+        generate_buddy_block(ctx, gen_ctx, n, BUDDY_HEAP_BLOCK_SIZE)?;
+    }
+    let (values, types) = heap_init_data(buddy_heap);
+    ctx.add_list_builder(SB_HEAP_LIST.into(), ListBuilder::new(values));
+    ctx.add_list_builder(SB_HEAP_TYPE_LIST.into(), ListBuilder::new(types));
+    if buddy_heap.is_none() {
+        ctx.add_list_builder(SB_HEAP_FREE_LIST.into(), ListBuilder::new(vec![]));
+    }
+
+    // i64 results are returned as (hi, lo)
+    gen_ctx.reserve_function_stack(2);
+    let base = gen_ctx.alloc_local_stack(REGISTERS_LEN);
+
+    let heap = || global_list_menu(SB_HEAP_LIST);
+    let heap_type = || global_list_menu(SB_HEAP_TYPE_LIST);
+    let free = || global_list_menu(SB_HEAP_FREE_LIST);
+    let memory = || global_list_menu(MEMORY_LIST);
+    let chars_name = chars_list_name();
+    let chars = |k: i32| item_in_list(global_list_menu(&chars_name), k);
+    let reg = |k: usize| item_in_list(local_stack(), base + k + 1);
+    let set_reg = |k: usize, value: Bib| replace_in_list(local_stack(), base + k + 1, value);
+    let ret = |k: usize, value: Bib| replace_in_list(function_stack(), k, value);
+    let fs1 = || item_in_list(function_stack(), 1);
+    let arg = |name: &str| custom_block_var_string_number(name);
+    let call =
+        |name: &str, args: Vec<(&str, Bib)>| call_custom_block(name, args.into_iter().collect());
+    let buddy = |name: &str| {
+        let n = buddy_heap.expect("the buddy heap is enabled");
+        buddy_block_func_name(n, BUDDY_HEAP_BLOCK_SIZE, name)
+    };
+
+    let slot = |idx: &Bib| sub(idx, SBIDX_OFFSET - 1);
+    let value = |idx: &Bib| item_in_list(heap(), slot(idx));
+    let ty = |idx: &Bib| item_in_list(heap_type(), slot(idx));
+    let is = |idx: &Bib, name: &str| equals(ty(idx), name);
+    // scratch has no NaN, so undefined is 0
+    let number = |idx: &Bib| add(value(idx), 0);
+    let nullish = |idx: &Bib| or(is(idx, "undefined"), is(idx, "null"));
+    let primitive = |idx: &Bib| {
+        or(
+            or(nullish(idx), is(idx, "boolean")),
+            or(or(is(idx, "number"), is(idx, "string")), is(idx, "bigint")),
+        )
+    };
+    // errors, objects, arrays and functions
+    let is_object = |idx: &Bib| not(primitive(idx));
+    let falsy = |idx: &Bib| {
+        or(
+            nullish(idx),
+            or(
+                and(is(idx, "string"), equals(length_of(value(idx)), 0)),
+                and(
+                    not(or(is(idx, "string"), is(idx, "error"))),
+                    equals(value(idx), 0),
+                ),
+            ),
+        )
+    };
+
+    let heap_new = bindgen_func_name("heap_new");
+    let new_value = |ty: Bib, value: Bib| call(&heap_new, vec![("type", ty), ("value", value)]);
+
+    let set_slot = || {
+        stack![
+            replace_in_list(heap_type(), slot(&reg(IDX)), arg("type")),
+            replace_in_list(heap(), slot(&reg(IDX)), arg("value")),
+            ret(1, reg(IDX))
+        ]
+    };
+    let heap_new_stack = match buddy_heap {
+        // the tree is empty until the first value
+        Some(_) => stack![
+            if_(
+                equals(length_of_list(global_list_menu(&buddy("tree"))), 0),
+                call(&buddy("init"), vec![("base", BUDDY_HEAP_BASE.to())])
+            ),
+            call(&buddy("alloc"), vec![("size", BUDDY_HEAP_BLOCK_SIZE.to())]),
+            set_reg(IDX, fs1()),
+            if_(equals(reg(IDX), 0), trap("bindgen heap: no free slot")),
+            set_slot()
+        ],
+        // a freed slot is used first
+        None => if_else(
+            equals(length_of_list(free()), 0),
+            stack![
+                add_to_list(heap_type(), arg("type")),
+                add_to_list(heap(), arg("value")),
+                ret(1, add(length_of_list(heap()), SBIDX_OFFSET - 1))
+            ],
+            stack![
+                set_reg(IDX, item_in_list(free(), "last")),
+                delete_in_list(free(), "last"),
+                set_slot()
+            ],
+        ),
+    };
+    define_procedure(ctx, &heap_new, &["type", "value"], heap_new_stack);
+
+    // the arithmetic of bigints stays bigint
+    let numeric_type = |idx: &Bib| {
+        stack![
+            set_reg(T, "number".to()),
+            if_(is(idx, "bigint"), set_reg(T, "bigint".to()))
+        ]
+    };
+    // towards zero
+    let set_trunc = |k: usize, x: Bib| {
+        if_else(
+            less_than(&x, 0),
+            set_reg(k, math_op("ceiling", &x)),
+            set_reg(k, math_op("floor", x)),
+        )
+    };
+    // the text of a value, as `String(value)`
+    let display = |k: usize, idx: &Bib| {
+        stack![
+            set_reg(k, value(idx)),
+            if_(
+                is(idx, "boolean"),
+                if_else(
+                    equals(value(idx), 1),
+                    set_reg(k, "true".to()),
+                    set_reg(k, "false".to()),
+                ),
+            ),
+            if_(is(idx, "object"), set_reg(k, "[object Object]".to()))
+        ]
+    };
+    // the reserved values are never freed
+    let drop_ref = |idx: Bib| {
+        if_(
+            not(less_than(&idx, SBIDX_RESERVED)),
+            stack![
+                replace_in_list(heap_type(), slot(&idx), "free"),
+                replace_in_list(heap(), slot(&idx), ""),
+                match buddy_heap {
+                    Some(_) => call(&buddy("free"), vec![("addr", idx)]),
+                    None => add_to_list(free(), idx),
+                }
+            ],
+        )
+    };
+
+    let json = || reg(S);
+    let json_letter = |k: Bib| letter_of(k, json());
+    let is_space = |letter: Bib| contains(" \t\n\r", letter);
+    let push = |k: usize, letter: Bib| set_reg(k, join(reg(k), letter));
+    // the letter of the UTF-16 code unit R1, a surrogate is half of a pair in chars 2 or 3
+    let code_unit = || {
+        if_else(
+            or(less_than(reg(R1), 0xD800), greater_than(reg(R1), 0xDFFF)),
+            set_reg(C, letter_of(add(reg(R1), 1), chars(1))),
+            if_else(
+                less_than(reg(R1), 0xDC00),
+                set_reg(C, letter_of(add(mul(sub(reg(R1), 0xD800), 2), 1), chars(2))),
+                set_reg(C, letter_of(add(mul(sub(reg(R1), 0xDC00), 2), 2), chars(3))),
+            ),
+        )
+    };
+    // the JSON string at letter K of S into R2, K ends at the closing quote
+    let json_string = || {
+        let escapes = [
+            ("b", "\u{8}"),
+            ("f", "\u{C}"),
+            ("n", "\n"),
+            ("r", "\r"),
+            ("t", "\t"),
+        ]
+        .into_iter()
+        .map(|(escape, letter)| if_(equals(reg(C), escape), set_reg(C, letter.to())))
+        .collect::<Vec<_>>();
+        let hex = (1..=4).fold("0x".to(), |hex: Bib, k| {
+            join(hex, json_letter(add(reg(K), k)))
+        });
+        stack![
+            set_reg(R2, "".to()),
+            set_reg(K, add(reg(K), 1)),
+            repeat_until(
+                or(
+                    greater_than(reg(K), length_of(json())),
+                    equals(json_letter(reg(K)), "\"")
+                ),
+                stack![
+                    set_reg(C, json_letter(reg(K))),
+                    if_(
+                        equals(reg(C), "\\"),
+                        stack![
+                            set_reg(K, add(reg(K), 1)),
+                            set_reg(C, json_letter(reg(K))),
+                            if_else(
+                                equals(reg(C), "u"),
+                                stack![
+                                    set_reg(R1, add(hex, 0)),
+                                    code_unit(),
+                                    set_reg(K, add(reg(K), 4))
+                                ],
+                                concat(escapes),
+                            )
+                        ]
+                    ),
+                    push(R2, reg(C)),
+                    set_reg(K, add(reg(K), 1))
+                ]
+            )
+        ]
+    };
+    // `JSON.stringify` of a string into S, the letters below U+0020 are \u00XX
+    let quote = |str: Bib| {
+        let controls = (0..0x20).filter_map(char::from_u32).collect::<String>();
+        stack![
+            set_reg(R2, str),
+            set_reg(S, "\"".to()),
+            set_reg(K, 1.to()),
+            repeat(
+                length_of(reg(R2)),
+                stack![
+                    set_reg(C, letter_of(reg(K), reg(R2))),
+                    if_else(
+                        contains("\"\\", reg(C)),
+                        push(S, join("\\", reg(C))),
+                        if_else(
+                            less_than(reg(C), " "),
+                            stack![
+                                set_reg(R1, 0.to()),
+                                repeat_until(
+                                    equals(letter_of(add(reg(R1), 1), controls.as_str()), reg(C)),
+                                    set_reg(R1, add(reg(R1), 1))
+                                ),
+                                push(
+                                    S,
+                                    join(
+                                        join("\\u00", math_op("floor", div(reg(R1), 16))),
+                                        letter_of(add(modulo(reg(R1), 16), 1), HEX_DIGITS)
+                                    )
+                                )
+                            ],
+                            push(S, reg(C)),
+                        ),
+                    ),
+                    set_reg(K, add(reg(K), 1))
+                ]
+            ),
+            push(S, "\"".to())
+        ]
+    };
+    // `prop in obj` for the JSON text S of an object or an array, R0 is the result
+    //
+    // KEY is compared with the keys at depth 1 as they are written, escapes included,
+    // and an array has N commas at depth 1, R1 is whether it has an element
+    let json_in = |obj: &Bib| {
+        stack![
+            set_reg(R0, 0.to()),
+            set_reg(R1, 0.to()),
+            set_reg(N, 0.to()),
+            set_reg(T, 0.to()),
+            set_reg(DEPTH, 0.to()),
+            set_reg(QUOTE, 0.to()),
+            set_reg(K, 1.to()),
+            repeat(
+                length_of(json()),
+                stack![
+                    set_reg(C, json_letter(reg(K))),
+                    if_else(
+                        equals(reg(QUOTE), 0),
+                        stack![
+                            if_(
+                                and(equals(reg(DEPTH), 1), not(contains(" \t\n\r]}", reg(C)))),
+                                set_reg(R1, 1.to())
+                            ),
+                            if_else(
+                                equals(reg(C), "\""),
+                                stack![set_reg(QUOTE, 1.to()), set_reg(R2, "".to())],
+                                if_else(
+                                    contains("{[", reg(C)),
+                                    stack![
+                                        // T is whether the next string is a key
+                                        if_(
+                                            and(equals(reg(DEPTH), 0), is(obj, "object")),
+                                            set_reg(T, 1.to())
+                                        ),
+                                        set_reg(DEPTH, add(reg(DEPTH), 1))
+                                    ],
+                                    if_else(
+                                        contains("}]", reg(C)),
+                                        set_reg(DEPTH, sub(reg(DEPTH), 1)),
+                                        if_(
+                                            and(equals(reg(C), ","), equals(reg(DEPTH), 1)),
+                                            if_else(
+                                                is(obj, "object"),
+                                                set_reg(T, 1.to()),
+                                                set_reg(N, add(reg(N), 1)),
+                                            ),
+                                        ),
+                                    ),
+                                ),
+                            )
+                        ],
+                        // in a string, QUOTE is 2 after a backslash
+                        if_else(
+                            equals(reg(QUOTE), 2),
+                            stack![set_reg(QUOTE, 1.to()), push(R2, reg(C))],
+                            if_else(
+                                equals(reg(C), "\""),
+                                stack![
+                                    set_reg(QUOTE, 0.to()),
+                                    if_(
+                                        and(equals(reg(T), 1), equals(reg(R2), reg(KEY))),
+                                        set_reg(R0, 1.to())
+                                    ),
+                                    set_reg(T, 0.to())
+                                ],
+                                stack![
+                                    if_(equals(reg(C), "\\"), set_reg(QUOTE, 2.to())),
+                                    push(R2, reg(C))
+                                ],
+                            ),
+                        ),
+                    ),
+                    set_reg(K, add(reg(K), 1))
+                ]
+            ),
+            // an index below the number of the elements
+            if_(
+                is(obj, "array"),
+                set_reg(
+                    R0,
+                    bool_to_int(and(
+                        and(
+                            equals(reg(KEY), math_op("floor", reg(KEY))),
+                            not(less_than(reg(KEY), 0))
+                        ),
+                        less_than(reg(KEY), add(reg(N), reg(R1)))
+                    ))
+                )
+            )
+        ]
+    };
+    let to_utf8 = |str: Bib| {
+        stack![
+            call(&utf8_func_name("to_utf8"), vec![("str", str)]),
+            set_reg(LEN, fs1())
+        ]
+    };
+    let from_utf8 = |ptr: Bib, len: Bib| {
+        call(
+            &utf8_func_name("from_utf8"),
+            vec![("addr", ptr), ("len", len)],
+        )
+    };
+
+    // the bytes of the last `to_utf8` are copied into `__wasm_sb_bindgen_malloc(LEN, 1)`
+    let malloc = module
+        .exports
+        .iter()
+        .find(|export| export.name == "__wasm_sb_bindgen_malloc")
+        .and_then(|export| match export.item {
+            ExportItem::Function(func) => Some(func),
+            _ => None,
+        });
+    let store_string = |name: &str| -> Result<StackBuilder> {
+        let func = malloc.ok_or_else(|| {
+            eyre!("__wasm_sb_bindgen_{name} needs the export __wasm_sb_bindgen_malloc")
+        })?;
+        let names = gen_ctx.gen_param_names(
+            func.index(),
+            module.types.get(module.funcs.get(func).ty()).params(),
+        )?;
+        let [size, align] = names.as_slice() else {
+            return Err(eyre!("unexpected type of __wasm_sb_bindgen_malloc"));
+        };
+        Ok(stack![
+            call(
+                &gen_ctx.gen_func_name(func.index()),
+                vec![(size.as_str(), reg(LEN)), (align.as_str(), 1.to())]
+            ),
+            set_reg(PTR, fs1()),
+            call(&utf8_func_name("to_utf8_store"), vec![("addr", reg(PTR))])
+        ])
+    };
+
+    for import in module.imports.iter() {
+        let ImportKind::Function(_) = import.kind else {
+            continue;
+        };
+        let Some(entry) = bindgen_import(import) else {
+            continue;
+        };
+        let (params, _) = entry.wasm_type();
+        let names = func_param_names(&params)?;
+
+        // the arguments as declared, i64 is (hi, lo)
+        let mut inputs = names.iter().map(|name| arg(name));
+        let sret = match entry.ret {
+            Ret::Prims(_) => inputs.next(),
+            _ => None,
+        };
+        let args = inputs.collect::<Vec<_>>();
+        let a = || args[0].clone();
+        let b = || args[1].clone();
+        let c = || args[2].clone();
+
+        let binary_number = |op: fn(Bib, Bib) -> Bib| {
+            stack![
+                numeric_type(&a()),
+                new_value(reg(T), op(number(&a()), number(&b())))
+            ]
+        };
+        let bitwise = |name: &str| {
+            stack![
+                numeric_type(&a()),
+                call(
+                    &i32_func_name(name),
+                    vec![
+                        ("lhs", wrap_i32(number(&a()))),
+                        ("rhs", wrap_i32(number(&b())))
+                    ]
+                ),
+                new_value(reg(T), fs1())
+            ]
+        };
+        let bool_value = |value: Bib| (StackBuilder::new(), vec![bool_to_int(value)]);
+
+        let (body, results): (StackBuilder, Vec<Bib>) = match entry.name {
+            "object_clone_ref" => (new_value(ty(&a()), value(&a())), vec![fs1()]),
+            "object_drop_ref" => (drop_ref(a()), vec![]),
+            "string_new" => (
+                stack![from_utf8(a(), b()), new_value("string".to(), fs1())],
+                vec![fs1()],
+            ),
+            "number_new" => (new_value("number".to(), a()), vec![fs1()]),
+            "bigint_from_i64" => (
+                new_value("bigint".to(), add(mul(a(), pow2(32)), b())),
+                vec![fs1()],
+            ),
+            "bigint_from_u64" => (
+                new_value("bigint".to(), add(mul(to_u32(a()), pow2(32)), b())),
+                vec![fs1()],
+            ),
+            "externref_heap_live_count" => match buddy_heap {
+                // the slots are counted, the heap does not shrink
+                Some(n) => (
+                    stack![
+                        set_reg(R0, 0.to()),
+                        set_reg(IDX, SBIDX_RESERVED.to()),
+                        repeat(
+                            1usize << n,
+                            stack![
+                                if_(
+                                    not(is(&reg(IDX), "free")),
+                                    set_reg(R0, add(reg(R0), 1))
+                                ),
+                                set_reg(IDX, add(reg(IDX), 1))
+                            ]
+                        )
+                    ],
+                    vec![reg(R0)],
+                ),
+                None => (
+                    StackBuilder::new(),
+                    vec![sub(
+                        sub(length_of_list(heap()), length_of_list(free())),
+                        SBIDX_RESERVED - SBIDX_OFFSET,
+                    )],
+                ),
+            },
+
+            "is_null" => bool_value(is(&a(), "null")),
+            "is_undefined" => bool_value(is(&a(), "undefined")),
+            "is_string" => bool_value(is(&a(), "string")),
+            "is_bigint" => bool_value(is(&a(), "bigint")),
+            "is_object" => bool_value(is_object(&a())),
+            "is_array" => bool_value(is(&a(), "array")),
+            "is_function" => bool_value(is(&a(), "function")),
+            // there are no symbols on the heap
+            "is_symbol" => (StackBuilder::new(), vec![0.to()]),
+            "typeof" => (
+                if_else(
+                    or(
+                        is(&a(), "null"),
+                        and(is_object(&a()), not(is(&a(), "function"))),
+                    ),
+                    new_value("string".to(), "object".to()),
+                    new_value("string".to(), ty(&a())),
+                ),
+                vec![fs1()],
+            ),
+            // errors and functions have no keys here
+            "in" => (
+                stack![
+                    if_(
+                        primitive(&b()),
+                        trap("TypeError: cannot use 'in' operator to search for a key in a primitive"),
+                    ),
+                    set_reg(R0, 0.to()),
+                    if_(
+                        or(is(&b(), "object"), is(&b(), "array")),
+                        stack![display(KEY, &a()), set_reg(S, value(&b())), json_in(&b())],
+                    )
+                ],
+                vec![reg(R0)],
+            ),
+            "is_falsy" => bool_value(falsy(&a())),
+            "not" => (
+                if_else(
+                    falsy(&a()),
+                    set_reg(R0, SBIDX_TRUE.to()),
+                    set_reg(R0, SBIDX_FALSE.to()),
+                ),
+                vec![reg(R0)],
+            ),
+
+            "as_number" => (StackBuilder::new(), vec![number(&a())]),
+            "try_into_number" => (new_value("number".to(), number(&a())), vec![fs1()]),
+            "neg" => (
+                stack![numeric_type(&a()), new_value(reg(T), sub(0, number(&a())))],
+                vec![fs1()],
+            ),
+            "bit_and" => (bitwise("and"), vec![fs1()]),
+            "bit_or" => (bitwise("or"), vec![fs1()]),
+            "bit_xor" => (bitwise("xor"), vec![fs1()]),
+            "shl" => (bitwise("shl"), vec![fs1()]),
+            "shr" => (bitwise("shr_s"), vec![fs1()]),
+            "unsigned_shr" => (
+                stack![
+                    call(
+                        &i32_func_name("shr_u"),
+                        vec![
+                            ("lhs", wrap_i32(number(&a()))),
+                            ("rhs", wrap_i32(number(&b())))
+                        ]
+                    ),
+                    new_value("number".to(), to_u32(fs1()))
+                ],
+                vec![fs1()],
+            ),
+            "bit_not" => (
+                stack![
+                    numeric_type(&a()),
+                    new_value(reg(T), sub(-1, wrap_i32(number(&a()))))
+                ],
+                vec![fs1()],
+            ),
+            // a string on either side is concatenated
+            "add" => (
+                if_else(
+                    or(is(&a(), "string"), is(&b(), "string")),
+                    stack![
+                        display(S, &a()),
+                        display(R0, &b()),
+                        new_value("string".to(), join(reg(S), reg(R0)))
+                    ],
+                    binary_number(|lhs, rhs| add(lhs, rhs)),
+                ),
+                vec![fs1()],
+            ),
+            "sub" => (binary_number(|lhs, rhs| sub(lhs, rhs)), vec![fs1()]),
+            "mul" => (binary_number(|lhs, rhs| mul(lhs, rhs)), vec![fs1()]),
+            // the division of bigints is truncated, by 0 is a RangeError
+            "div" | "checked_div" => (
+                if_else(
+                    is(&a(), "bigint"),
+                    if_else(
+                        equals(number(&b()), 0),
+                        new_value("error".to(), "RangeError: Division by zero".to()),
+                        stack![
+                            set_trunc(R0, div(number(&a()), number(&b()))),
+                            new_value("bigint".to(), reg(R0))
+                        ],
+                    ),
+                    new_value("number".to(), div(number(&a()), number(&b()))),
+                ),
+                vec![fs1()],
+            ),
+            // the sign of the dividend
+            "rem" => (
+                stack![
+                    numeric_type(&a()),
+                    set_trunc(R0, div(number(&a()), number(&b()))),
+                    new_value(reg(T), sub(number(&a()), mul(number(&b()), reg(R0))))
+                ],
+                vec![fs1()],
+            ),
+            // integer exponents are exact
+            "pow" => (
+                stack![
+                    numeric_type(&a()),
+                    if_else(
+                        equals(number(&b()), math_op("floor", number(&b()))),
+                        stack![
+                            set_reg(R0, 1.to()),
+                            repeat(
+                                math_op("abs", number(&b())),
+                                set_reg(R0, mul(reg(R0), number(&a())))
+                            ),
+                            if_(less_than(number(&b()), 0), set_reg(R0, div(1, reg(R0))))
+                        ],
+                        set_reg(
+                            R0,
+                            math_op("e ^", mul(number(&b()), math_op("ln", number(&a()))))
+                        ),
+                    ),
+                    new_value(reg(T), reg(R0))
+                ],
+                vec![fs1()],
+            ),
+            "lt" => bool_value(less_than(value(&a()), value(&b()))),
+            "le" => bool_value(not(greater_than(value(&a()), value(&b())))),
+            "ge" => bool_value(not(less_than(value(&a()), value(&b())))),
+            "gt" => bool_value(greater_than(value(&a()), value(&b()))),
+            "sbval_eq" => bool_value(and(
+                equals(ty(&a()), ty(&b())),
+                equals(value(&a()), value(&b())),
+            )),
+            "sbval_loose_eq" => bool_value(or(
+                and(nullish(&a()), nullish(&b())),
+                and(
+                    not(or(nullish(&a()), nullish(&b()))),
+                    equals(value(&a()), value(&b())),
+                ),
+            )),
+            // the bytes become an array, the type of the elements is not known here
+            "copy_to_typed_array" => (
+                stack![
+                    set_reg(S, "[".to()),
+                    set_reg(K, 0.to()),
+                    repeat(
+                        b(),
+                        stack![
+                            if_(greater_than(reg(K), 0), push(S, ",".to())),
+                            push(S, item_in_list(memory(), add(add(a(), reg(K)), 1))),
+                            set_reg(K, add(reg(K), 1))
+                        ]
+                    ),
+                    replace_in_list(heap_type(), slot(&c()), "array"),
+                    replace_in_list(heap(), slot(&c()), join(reg(S), "]"))
+                ],
+                vec![],
+            ),
+
+            // Option<f64> is (is_some, value)
+            "number_get" => (
+                if_else(
+                    is(&a(), "number"),
+                    stack![set_reg(R0, 1.to()), set_reg(R1, number(&a()))],
+                    stack![set_reg(R0, 0.to()), set_reg(R1, 0.to())],
+                ),
+                vec![reg(R0), reg(R1)],
+            ),
+            // neither 0 nor 1 is None
+            "boolean_get" => (
+                if_else(
+                    is(&a(), "boolean"),
+                    set_reg(R0, value(&a())),
+                    set_reg(R0, 2.to()),
+                ),
+                vec![reg(R0)],
+            ),
+            // a null pointer is None
+            "string_get" => (
+                if_else(
+                    is(&a(), "string"),
+                    stack![
+                        to_utf8(value(&a())),
+                        store_string(entry.name)?,
+                        set_reg(R0, reg(PTR)),
+                        set_reg(R1, reg(LEN))
+                    ],
+                    stack![set_reg(R0, 0.to()), set_reg(R1, 0.to())],
+                ),
+                vec![reg(R0), reg(R1)],
+            ),
+            // Option<Wasm8Bytes> is (is_some, hi, lo)
+            "bigint_get_as_i64" => (
+                if_else(
+                    is(&a(), "bigint"),
+                    stack![
+                        set_reg(R0, 1.to()),
+                        set_reg(R1, wrap_i32(math_op("floor", div(number(&a()), pow2(32))))),
+                        set_reg(R2, modulo(number(&a()), pow2(32)))
+                    ],
+                    stack![
+                        set_reg(R0, 0.to()),
+                        set_reg(R1, 0.to()),
+                        set_reg(R2, 0.to())
+                    ],
+                ),
+                vec![reg(R0), reg(R1), reg(R2)],
+            ),
+            // [ptr, len] is written to the first argument
+            "debug_string" => (
+                stack![
+                    display(S, &b()),
+                    to_utf8(reg(S)),
+                    store_string(entry.name)?,
+                    call(
+                        &memory_func_name("store32"),
+                        vec![("addr", a()), ("value", reg(PTR))]
+                    ),
+                    call(
+                        &memory_func_name("store32"),
+                        vec![("addr", add(a(), 4)), ("value", reg(LEN))]
+                    )
+                ],
+                vec![],
+            ),
+            // a rust panic, the text of `throw(s)` is the message
+            "throw" => (stack![from_utf8(a(), b()), trap(fs1())], vec![]),
+            "rethrow" => (stack![display(S, &a()), trap(reg(S))], vec![]),
+            "error_new" => (
+                stack![from_utf8(a(), b()), new_value("error".to(), fs1())],
+                vec![fs1()],
+            ),
+            // the data of a `Closure`, scratch never calls it
+            "describe_closure" => (
+                new_value("function".to(), join(join(a(), ","), b())),
+                vec![fs1()],
+            ),
+            // 1 lets rust drop the data of the closure
+            "cb_drop" => (
+                if_else(
+                    is(&a(), "function"),
+                    stack![drop_ref(a()), set_reg(R0, 1.to())],
+                    set_reg(R0, 0.to()),
+                ),
+                vec![reg(R0)],
+            ),
+
+            // the letters before the first one out of the whitespace are skipped,
+            // objects and arrays are not validated
+            "json_parse" => (
+                stack![
+                    from_utf8(a(), b()),
+                    set_reg(S, fs1()),
+                    set_reg(K, 1.to()),
+                    repeat_until(
+                        or(
+                            greater_than(reg(K), length_of(json())),
+                            not(is_space(json_letter(reg(K))))
+                        ),
+                        set_reg(K, add(reg(K), 1))
+                    ),
+                    set_reg(C, json_letter(reg(K))),
+                    if_else(
+                        greater_than(reg(K), length_of(json())),
+                        trap("SyntaxError: Unexpected end of JSON input"),
+                        if_else(
+                            equals(reg(C), "\""),
+                            stack![
+                                json_string(),
+                                new_value("string".to(), reg(R2)),
+                                set_reg(R0, fs1())
+                            ],
+                            if_else(
+                                contains("{[", reg(C)),
+                                stack![
+                                    if_else(
+                                        equals(reg(C), "{"),
+                                        new_value("object".to(), json()),
+                                        new_value("array".to(), json()),
+                                    ),
+                                    set_reg(R0, fs1())
+                                ],
+                                if_else(
+                                    equals(reg(C), "t"),
+                                    set_reg(R0, SBIDX_TRUE.to()),
+                                    if_else(
+                                        equals(reg(C), "f"),
+                                        set_reg(R0, SBIDX_FALSE.to()),
+                                        if_else(
+                                            equals(reg(C), "n"),
+                                            set_reg(R0, SBIDX_NULL.to()),
+                                            stack![
+                                                new_value("number".to(), add(json(), 0)),
+                                                set_reg(R0, fs1())
+                                            ],
+                                        ),
+                                    ),
+                                ),
+                            ),
+                        ),
+                    )
+                ],
+                vec![reg(R0)],
+            ),
+            // `JSON.stringify`, undefined and functions are null as there is no None
+            "json_serialize" => (
+                stack![
+                    if_else(
+                        or(nullish(&a()), is(&a(), "function")),
+                        set_reg(S, "null".to()),
+                        if_else(
+                            is(&a(), "string"),
+                            quote(value(&a())),
+                            if_else(
+                                is(&a(), "bigint"),
+                                trap("TypeError: Do not know how to serialize a BigInt"),
+                                if_else(
+                                    is(&a(), "error"),
+                                    set_reg(S, "{}".to()),
+                                    stack![
+                                        display(S, &a()),
+                                        if_(
+                                            equals(math_op("abs", value(&a())), "Infinity"),
+                                            set_reg(S, "null".to())
+                                        ),
+                                        if_(
+                                            or(is(&a(), "object"), is(&a(), "array")),
+                                            set_reg(S, value(&a()))
+                                        )
+                                    ],
+                                ),
+                            ),
+                        ),
+                    ),
+                    to_utf8(reg(S)),
+                    store_string(entry.name)?
+                ],
+                vec![reg(PTR), reg(LEN)],
+            ),
+
+            // Wasm8Bytes is (hi, lo), hi of u64 is unsigned
+            "i64_split" => (StackBuilder::new(), vec![a(), b()]),
+            "u64_split" => (StackBuilder::new(), vec![to_u32(a()), b()]),
+            "i64_join" | "u64_join" => (
+                stack![
+                    call(&memory_func_name("load_f64"), vec![("addr", a())]),
+                    set_reg(R0, fs1()),
+                    call(&memory_func_name("load_f64"), vec![("addr", add(a(), 8))]),
+                    set_reg(R1, fs1())
+                ],
+                vec![wrap_i32(reg(R0)), reg(R1)],
+            ),
+            name => return Err(eyre!("no procedure for __wasm_sb_bindgen_{name}")),
+        };
+
+        // the results never read __wasm_function_stack after it is written
+        let write = match entry.ret {
+            Ret::Unit => StackBuilder::new(),
+            Ret::F64 | Ret::I64 => concat(
+                results
+                    .into_iter()
+                    .enumerate()
+                    .map(|(k, value)| ret(k + 1, value))
+                    .collect(),
+            ),
+            Ret::Prims(_) => {
+                let sret = sret.clone().unwrap();
+                concat(
+                    results
+                        .into_iter()
+                        .enumerate()
+                        .map(|(k, value)| {
+                            call(
+                                &memory_func_name("store_f64"),
+                                vec![("addr", add(&sret, 8 * k)), ("value", value)],
+                            )
+                        })
+                        .collect(),
+                )
+            }
+        };
+
+        let names = names.iter().map(|name| name.as_str()).collect::<Vec<_>>();
+        define_procedure(
+            ctx,
+            &bindgen_func_name(entry.name),
+            &names,
+            stack![body, write],
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use sb_buddy_block_base::BuddyBlock;
+    use sb_sbity::value::ValueWithBool;
+
+    use super::{heap_init_data, BUDDY_HEAP_BASE, BUDDY_HEAP_BLOCK_SIZE};
+    use crate::scratch::block::bindgen::{SBIDX_OFFSET, SBIDX_RESERVED};
+
+    /// the type of `idx` in the heap, the item `idx - SBIDX_OFFSET + 1`
+    fn slot_type(types: &[ValueWithBool], idx: usize) -> &str {
+        match &types[idx - SBIDX_OFFSET] {
+            ValueWithBool::Text(text) => text,
+            data => panic!("not a text: {data:?}"),
+        }
+    }
+
+    #[test]
+    fn test_heap_init_data() {
+        let (values, types) = heap_init_data(None);
+        assert_eq!(values.len(), SBIDX_RESERVED - SBIDX_OFFSET);
+        assert_eq!(types.len(), values.len());
+
+        let (values, types) = heap_init_data(Some(3));
+        assert_eq!(values.len(), SBIDX_RESERVED - SBIDX_OFFSET + 8);
+        assert_eq!(types.len(), values.len());
+        for idx in SBIDX_OFFSET..SBIDX_RESERVED {
+            assert_ne!(slot_type(&types, idx), "free", "{idx}");
+        }
+        for idx in SBIDX_RESERVED..SBIDX_RESERVED + 8 {
+            assert_eq!(slot_type(&types, idx), "free", "{idx}");
+        }
+    }
+
+    /// the allocator hands out every free slot once and never a reserved one
+    #[test]
+    fn test_buddy_heap_slots() {
+        for n in 0..6 {
+            let (_, types) = heap_init_data(Some(n));
+            let mut buddy = BuddyBlock::new(n, BUDDY_HEAP_BLOCK_SIZE);
+            let mut idxs = BTreeSet::new();
+            while let Some(offset) = buddy.alloc(BUDDY_HEAP_BLOCK_SIZE) {
+                let idx = BUDDY_HEAP_BASE + offset;
+                assert_eq!(slot_type(&types, idx), "free", "{n} {idx}");
+                assert!(idxs.insert(idx), "{n} {idx}");
+            }
+            assert_eq!(idxs.len(), 1 << n);
+            assert_eq!(buddy.free_len(), 0);
+
+            // a dropped value frees its slot for the next one
+            let idx = *idxs.last().unwrap();
+            buddy.free(idx - BUDDY_HEAP_BASE).unwrap();
+            assert!(buddy.free(idx - BUDDY_HEAP_BASE).is_err());
+            let offset = buddy.alloc(BUDDY_HEAP_BLOCK_SIZE).unwrap();
+            assert_eq!(BUDDY_HEAP_BASE + offset, idx);
+        }
+    }
+}
@@ -0,0 +1,181 @@
+// the imports of `wasm-sb-bindgen`, each of them is a procedure on the scratch side
+// values live in `__wasm_sb_heap`, `idx` is the item `idx - SBIDX_OFFSET + 1`
+
+pub mod export;
+pub mod generator;
+
+use walrus::{Import, ImportKind, Module, ValType};
+
+use crate::pre_name::pre_func_name;
+
+pub const BINDGEN_MODULE: &str = "__wasm_sb_bindgen_placeholder__";
+pub const BINDGEN_PREFIX: &str = "__wasm_sb_bindgen_";
+
+// keep in sync with wasm-sb-bindgen/src/lib.rs
+pub const SBIDX_OFFSET: usize = 128;
+pub const SBIDX_UNDEFINED: usize = SBIDX_OFFSET;
+pub const SBIDX_NULL: usize = SBIDX_OFFSET + 1;
+pub const SBIDX_TRUE: usize = SBIDX_OFFSET + 2;
+pub const SBIDX_FALSE: usize = SBIDX_OFFSET + 3;
+pub const SBIDX_RESERVED: usize = SBIDX_OFFSET + 4;
+
+/// parameter as declared in `externs!`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Param {
+    F64,
+    /// pointers, `usize` and structs passed by pointer
+    Ptr,
+    /// hi and lo
+    I64,
+}
+
+/// result as declared in `externs!`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ret {
+    Unit,
+    F64,
+    I64,
+    /// f64 fields of a `#[repr(C)]` struct, written through a pointer passed first
+    Prims(usize),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct BindgenImport {
+    /// name without `__wasm_sb_bindgen_`
+    pub name: &'static str,
+    pub params: &'static [Param],
+    pub ret: Ret,
+}
+
+impl BindgenImport {
+    const fn new(name: &'static str, params: &'static [Param], ret: Ret) -> Self {
+        BindgenImport { name, params, ret }
+    }
+
+    /// the wasm type rustc lowers the declaration to
+    pub fn wasm_type(&self) -> (Vec<ValType>, Vec<ValType>) {
+        let mut params = Vec::new();
+        if let Ret::Prims(_) = self.ret {
+            params.push(ValType::I32);
+        }
+        params.extend(self.params.iter().map(|param| match param {
+            Param::F64 => ValType::F64,
+            Param::Ptr => ValType::I32,
+            Param::I64 => ValType::I64,
+        }));
+        let results = match self.ret {
+            Ret::F64 => vec![ValType::F64],
+            Ret::I64 => vec![ValType::I64],
+            Ret::Unit | Ret::Prims(_) => vec![],
+        };
+        (params, results)
+    }
+}
+
+use Param::{Ptr, F64, I64};
+
+/// every import generated by `generator::bindgen_generator`
+pub const BINDGEN_IMPORTS: &[BindgenImport] = &[
+    BindgenImport::new("object_clone_ref", &[F64], Ret::F64),
+    BindgenImport::new("object_drop_ref", &[F64], Ret::Unit),
+    BindgenImport::new("string_new", &[Ptr, Ptr], Ret::F64),
+    BindgenImport::new("number_new", &[F64], Ret::F64),
+    BindgenImport::new("bigint_from_i64", &[I64], Ret::F64),
+    BindgenImport::new("bigint_from_u64", &[I64], Ret::F64),
+    BindgenImport::new("externref_heap_live_count", &[], Ret::F64),
+    BindgenImport::new("is_null", &[F64], Ret::F64),
+    BindgenImport::new("is_undefined", &[F64], Ret::F64),
+    BindgenImport::new("is_symbol", &[F64], Ret::F64),
+    BindgenImport::new("is_object", &[F64], Ret::F64),
+    BindgenImport::new("is_array", &[F64], Ret::F64),
+    BindgenImport::new("is_function", &[F64], Ret::F64),
+    BindgenImport::new("is_string", &[F64], Ret::F64),
+    BindgenImport::new("is_bigint", &[F64], Ret::F64),
+    BindgenImport::new("typeof", &[F64], Ret::F64),
+    BindgenImport::new("in", &[F64, F64], Ret::F64),
+    BindgenImport::new("is_falsy", &[F64], Ret::F64),
+    BindgenImport::new("as_number", &[F64], Ret::F64),
+    BindgenImport::new("try_into_number", &[F64], Ret::F64),
+    BindgenImport::new("neg", &[F64], Ret::F64),
+    BindgenImport::new("bit_and", &[F64, F64], Ret::F64),
+    BindgenImport::new("bit_or", &[F64, F64], Ret::F64),
+    BindgenImport::new("bit_xor", &[F64, F64], Ret::F64),
+    BindgenImport::new("bit_not", &[F64], Ret::F64),
+    BindgenImport::new("shl", &[F64, F64], Ret::F64),
+    BindgenImport::new("shr", &[F64, F64], Ret::F64),
+    BindgenImport::new("unsigned_shr", &[F64, F64], Ret::F64),
+    BindgenImport::new("add", &[F64, F64], Ret::F64),
+    BindgenImport::new("sub", &[F64, F64], Ret::F64),
+    BindgenImport::new("div", &[F64, F64], Ret::F64),
+    BindgenImport::new("checked_div", &[F64, F64], Ret::F64),
+    BindgenImport::new("mul", &[F64, F64], Ret::F64),
+    BindgenImport::new("rem", &[F64, F64], Ret::F64),
+    BindgenImport::new("pow", &[F64, F64], Ret::F64),
+    BindgenImport::new("lt", &[F64, F64], Ret::F64),
+    BindgenImport::new("le", &[F64, F64], Ret::F64),
+    BindgenImport::new("ge", &[F64, F64], Ret::F64),
+    BindgenImport::new("gt", &[F64, F64], Ret::F64),
+    BindgenImport::new("number_get", &[F64], Ret::Prims(2)),
+    BindgenImport::new("boolean_get", &[F64], Ret::F64),
+    BindgenImport::new("string_get", &[F64], Ret::Prims(2)),
+    BindgenImport::new("bigint_get_as_i64", &[F64], Ret::Prims(3)),
+    BindgenImport::new("debug_string", &[Ptr, F64], Ret::Unit),
+    BindgenImport::new("throw", &[Ptr, Ptr], Ret::Unit),
+    BindgenImport::new("rethrow", &[F64], Ret::Unit),
+    BindgenImport::new("error_new", &[Ptr, Ptr], Ret::F64),
+    BindgenImport::new("cb_drop", &[F64], Ret::F64),
+    BindgenImport::new("describe_closure", &[F64, F64, F64], Ret::F64),
+    BindgenImport::new("json_parse", &[Ptr, Ptr], Ret::F64),
+    BindgenImport::new("json_serialize", &[F64], Ret::Prims(2)),
+    BindgenImport::new("sbval_eq", &[F64, F64], Ret::F64),
+    BindgenImport::new("sbval_loose_eq", &[F64, F64], Ret::F64),
+    BindgenImport::new("copy_to_typed_array", &[Ptr, Ptr, F64], Ret::Unit),
+    BindgenImport::new("not", &[F64], Ret::F64),
+    BindgenImport::new("i64_split", &[I64], Ret::Prims(2)),
+    BindgenImport::new("i64_join", &[Ptr], Ret::I64),
+    BindgenImport::new("u64_split", &[I64], Ret::Prims(2)),
+    BindgenImport::new("u64_join", &[Ptr], Ret::I64),
+];
+
+pub fn bindgen_func_name(name: &str) -> String {
+    format!("{}bindgen_{name}", pre_func_name())
+}
+
+/// the entry of a function import, if it is generated
+pub fn bindgen_import(import: &Import) -> Option<&'static BindgenImport> {
+    if import.module != BINDGEN_MODULE {
+        return None;
+    }
+    let name = import.name.strip_prefix(BINDGEN_PREFIX)?;
+    BINDGEN_IMPORTS.iter().find(|entry| entry.name == name)
+}
+
+/// `module::name` of the function imports with no procedure, or with an unexpected type
+pub fn unsupported_imports(module: &Module) -> Vec<String> {
+    module
+        .imports
+        .iter()
+        .filter_map(|import| {
+            let ImportKind::Function(func) = import.kind else {
+                return None;
+            };
+            let ty = module.types.get(module.funcs.get(func).ty());
+            match bindgen_import(import) {
+                Some(entry) => {
+                    let (params, results) = entry.wasm_type();
+                    if ty.params() == params && ty.results() == results {
+                        return None;
+                    }
+                    Some(format!(
+                        "{}::{} (unexpected type {:?} -> {:?})",
+                        import.module,
+                        import.name,
+                        ty.params(),
+                        ty.results()
+                    ))
+                }
+                None => Some(format!("{}::{}", import.module, import.name)),
+            }
+        })
+        .collect()
+}
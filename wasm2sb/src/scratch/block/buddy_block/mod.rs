@@ -1,34 +1,272 @@
 // management memory system for buddy block
+// same as `sb_buddy_block_base::BuddyBlock`, the heap is `2^n` blocks of `block_size` bytes
+// the linear memory belongs to the allocator of the module, so it only backs
+// the slots of the bindgen heap, with `buddy-heap`
 
-use eyre::Result;
-use sb_itchy::blocks::*;
-use sb_itchy::custom_block::CustomBlockInputType;
-use sb_itchy::stack::StackBuilder;
+use eyre::{eyre, Result};
+use sb_itchy::{block::BlockInputBuilder, data::ListBuilder, stack::StackBuilder};
+use sb_itchy_support::{blocks_wrapper::*, stack};
 
-use crate::pre_name::PRE_FUNC_NAME;
-use crate::scratch::sb3::ProjectZip;
+use crate::{
+    pre_name::pre_func_name,
+    scratch::{
+        block::{
+            define_procedure,
+            function_code::{function_stack, local_stack},
+            memory::{memory_func_name, SCRATCH_LIST_LIMIT},
+            trap::trap,
+        },
+        sb3::ProjectZip,
+    },
+    GenCtx,
+};
 
+type Bib = BlockInputBuilder;
+
+// registers in __wasm_local_stack
+const BASE: usize = 0;
+const UNITS: usize = 1;
+const INDEX: usize = 2;
+const NODE: usize = 3;
+const OFFSET: usize = 4;
+const LEFT: usize = 5;
+const RIGHT: usize = 6;
+const OLD: usize = 7;
+const NEW: usize = 8;
+const REGISTERS_LEN: usize = 9;
+
+pub fn buddy_block_func_name(n: usize, block_size: usize, name: &str) -> String {
+    format!("{}buddy_block_{n}_{block_size}_{name}", pre_func_name())
+}
+
+/// procedures `init(base)`, `alloc(size)`, `free(addr)`, `size_of(addr)` and `realloc(addr, size)`
+///
+/// addresses are `base` plus the offset in the heap, 0 is the null pointer so `base` must not be 0
 pub fn generate_buddy_block(
     ctx: &mut ProjectZip,
+    gen_ctx: &mut GenCtx,
     n: usize,
     block_size: usize,
-) -> Result<Vec<StackBuilder>> {
-    let pre_name = format!("{PRE_FUNC_NAME}buddy_block_{n}{block_size}");
-    let stack_builders = generate_buddy_block_init(ctx, n, block_size, &pre_name);
+) -> Result<()> {
+    // the tree has 2^(n+1) - 1 nodes
+    let blocks_len = 1usize
+        .checked_shl(n as u32)
+        .filter(|len| 2 * len - 1 <= SCRATCH_LIST_LIMIT)
+        .ok_or_else(|| eyre!("too many blocks for a scratch list: 2^{n}"))?;
+    if block_size == 0 {
+        return Err(eyre!("block size must not be 0"));
+    }
 
-    Ok(stack_builders)
-}
+    let tree_name = buddy_block_func_name(n, block_size, "tree");
+    ctx.add_list_builder(tree_name.clone(), ListBuilder::new(vec![]));
 
-fn generate_buddy_block_init(
-    ctx: &mut ProjectZip,
-    _n: usize,
-    _block_size: usize,
-    pre_name: &str,
-) -> Vec<StackBuilder> {
-    let name = format!("{pre_name}init");
-    ctx.define_custom_block(vec![CustomBlockInputType::Text(name.clone())], true);
+    gen_ctx.reserve_function_stack(1);
+    let base = gen_ctx.alloc_local_stack(REGISTERS_LEN);
+
+    let name = |name: &str| buddy_block_func_name(n, block_size, name);
+    let arg = |name: &str| custom_block_var_string_number(name);
+    let reg = |k: usize| item_in_list(local_stack(), base + k + 1);
+    let set_reg = |k: usize, value: Bib| replace_in_list(local_stack(), base + k + 1, value);
+    let ret = |value: Bib| replace_in_list(function_stack(), 1, value);
+    let call =
+        |name: &str, args: Vec<(&str, Bib)>| call_custom_block(name, args.into_iter().collect());
+    // node `i` is the item `i + 1`
+    let tree = || global_list_menu(&tree_name);
+    let node = |index: Bib| item_in_list(tree(), add(index, 1));
+    let set_node = |index: Bib, value: Bib| replace_in_list(tree(), add(index, 1), value);
+    let left = || node(add(mul(reg(INDEX), 2), 1));
+    let right = || node(add(mul(reg(INDEX), 2), 2));
+    let parent = || set_reg(INDEX, math_op("floor", div(sub(reg(INDEX), 1), 2)));
+    let max = |a: Bib, b: Bib, write: &dyn Fn(Bib) -> StackBuilder| -> StackBuilder {
+        if_else(less_than(&a, &b), write(b), write(a))
+    };
+
+    define_procedure(
+        ctx,
+        &name("init"),
+        &["base"],
+        stack![
+            set_reg(BASE, arg("base")),
+            delete_all_in_list(tree()),
+            set_reg(NODE, blocks_len.to()),
+            repeat(
+                n + 1,
+                stack![
+                    repeat(div(blocks_len, reg(NODE)), add_to_list(tree(), reg(NODE))),
+                    set_reg(NODE, div(reg(NODE), 2))
+                ]
+            )
+        ],
+    );
 
-    let stack_builder = define_custom_block(name);
+    // blocks for `size` bytes, rounded up to a power of two
+    let units = || {
+        stack![
+            set_reg(UNITS, 1.to()),
+            repeat_until(
+                not(less_than(mul(reg(UNITS), block_size), arg("size"))),
+                set_reg(UNITS, mul(reg(UNITS), 2))
+            )
+        ]
+    };
+
+    define_procedure(
+        ctx,
+        &name("alloc"),
+        &["size"],
+        stack![
+            units(),
+            if_else(
+                less_than(node(0.to()), reg(UNITS)),
+                ret(0.to()),
+                stack![
+                    // go down to the first node of the size with enough room
+                    set_reg(INDEX, 0.to()),
+                    set_reg(NODE, blocks_len.to()),
+                    repeat_until(
+                        equals(reg(NODE), reg(UNITS)),
+                        stack![
+                            if_else(
+                                not(less_than(left(), reg(UNITS))),
+                                set_reg(INDEX, add(mul(reg(INDEX), 2), 1)),
+                                set_reg(INDEX, add(mul(reg(INDEX), 2), 2)),
+                            ),
+                            set_reg(NODE, div(reg(NODE), 2))
+                        ]
+                    ),
+                    set_node(reg(INDEX), 0.to()),
+                    set_reg(OFFSET, sub(mul(add(reg(INDEX), 1), reg(NODE)), blocks_len)),
+                    // the parents keep the larger run of their children
+                    repeat_until(
+                        equals(reg(INDEX), 0),
+                        stack![
+                            parent(),
+                            max(left(), right(), &|value: Bib| set_node(reg(INDEX), value))
+                        ]
+                    ),
+                    ret(add(reg(BASE), mul(reg(OFFSET), block_size)))
+                ],
+            )
+        ],
+    );
+
+    // INDEX and NODE of the node allocated at `addr`, an invalid address traps
+    let find = || {
+        stack![
+            set_reg(OFFSET, div(sub(arg("addr"), reg(BASE)), block_size)),
+            if_(
+                or(
+                    not(equals(reg(OFFSET), math_op("floor", reg(OFFSET)))),
+                    or(
+                        less_than(reg(OFFSET), 0),
+                        not(less_than(reg(OFFSET), blocks_len))
+                    )
+                ),
+                trap("buddy_block: invalid address")
+            ),
+            // the allocated node is the first one with no room above the leaf
+            set_reg(INDEX, add(reg(OFFSET), blocks_len - 1)),
+            set_reg(NODE, 1.to()),
+            repeat_until(
+                or(equals(node(reg(INDEX)), 0), equals(reg(INDEX), 0)),
+                stack![set_reg(NODE, mul(reg(NODE), 2)), parent()]
+            ),
+            if_(
+                or(
+                    not(equals(node(reg(INDEX)), 0)),
+                    not(equals(
+                        sub(mul(add(reg(INDEX), 1), reg(NODE)), blocks_len),
+                        reg(OFFSET)
+                    ))
+                ),
+                trap("buddy_block: invalid address")
+            )
+        ]
+    };
+
+    define_procedure(
+        ctx,
+        &name("size_of"),
+        &["addr"],
+        stack![find(), ret(mul(reg(NODE), block_size))],
+    );
+
+    define_procedure(
+        ctx,
+        &name("free"),
+        &["addr"],
+        if_(
+            not(equals(arg("addr"), 0)),
+            stack![
+                find(),
+                set_node(reg(INDEX), reg(NODE)),
+                // buddies both free are merged
+                repeat_until(
+                    equals(reg(INDEX), 0),
+                    stack![
+                        parent(),
+                        set_reg(NODE, mul(reg(NODE), 2)),
+                        set_reg(LEFT, left()),
+                        set_reg(RIGHT, right()),
+                        if_else(
+                            equals(add(reg(LEFT), reg(RIGHT)), reg(NODE)),
+                            set_node(reg(INDEX), reg(NODE)),
+                            max(reg(LEFT), reg(RIGHT), &|value: Bib| set_node(
+                                reg(INDEX),
+                                value
+                            )),
+                        )
+                    ]
+                )
+            ],
+        ),
+    );
+
+    // the old block is kept if there is no room
+    let alloc = name("alloc");
+    define_procedure(
+        ctx,
+        &name("realloc"),
+        &["addr", "size"],
+        if_else(
+            equals(arg("addr"), 0),
+            call(&alloc, vec![("size", arg("size"))]),
+            stack![
+                call(&name("size_of"), vec![("addr", arg("addr"))]),
+                set_reg(OLD, item_in_list(function_stack(), 1)),
+                units(),
+                if_else(
+                    equals(mul(reg(UNITS), block_size), reg(OLD)),
+                    ret(arg("addr")),
+                    stack![
+                        call(&alloc, vec![("size", arg("size"))]),
+                        set_reg(NEW, item_in_list(function_stack(), 1)),
+                        if_else(
+                            equals(reg(NEW), 0),
+                            ret(0.to()),
+                            stack![
+                                call(
+                                    &memory_func_name("copy"),
+                                    vec![
+                                        ("dst", reg(NEW)),
+                                        ("src", arg("addr")),
+                                        ("len", min(reg(OLD), arg("size")))
+                                    ]
+                                ),
+                                call(&name("free"), vec![("addr", arg("addr"))]),
+                                ret(reg(NEW))
+                            ],
+                        )
+                    ],
+                )
+            ],
+        ),
+    );
+
+    Ok(())
+}
 
-    vec![stack_builder]
+/// (a + b - |a - b|) / 2
+fn min(a: Bib, b: Bib) -> Bib {
+    div(sub(add(&a, &b), math_op("abs", sub(a, b))), 2)
 }
@@ -0,0 +1,92 @@
+use sb_itchy::block::BlockInputBuilder;
+use sb_itchy_support::{blocks_wrapper::*, stack};
+
+use crate::{
+    scratch::{
+        block::{
+            define_procedure,
+            function_code::{function_stack, local_stack},
+            memory::{pow2, pow2_item},
+        },
+        sb3::ProjectZip,
+    },
+    GenCtx,
+};
+
+use super::f32_func_name;
+
+type Bib = BlockInputBuilder;
+
+// registers in __wasm_local_stack
+const X: usize = 0;
+const E: usize = 1;
+const M: usize = 2;
+const FRAC: usize = 3;
+const REGISTERS_LEN: usize = 4;
+
+pub fn f32_generator(ctx: &mut ProjectZip, gen_ctx: &mut GenCtx) {
+    gen_ctx.reserve_function_stack(1);
+    let base = gen_ctx.alloc_local_stack(REGISTERS_LEN);
+
+    let value = || custom_block_var_string_number("value");
+    let reg = |k: usize| item_in_list(local_stack(), base + k + 1);
+    let set_reg = |k: usize, value: Bib| replace_in_list(local_stack(), base + k + 1, value);
+    let ret = |value: Bib| replace_in_list(function_stack(), 1, value);
+    let scale = || pow2_item(sub(reg(E), 23));
+
+    // round half to even, `round` of scratch rounds half up
+    define_procedure(
+        ctx,
+        &f32_func_name("fround"),
+        &["value"],
+        stack![
+            set_reg(X, math_op("abs", value())),
+            // 0, infinity and NaN are kept
+            if_else(
+                or(equals(reg(X), 0), not(less_than(reg(X), "Infinity"))),
+                ret(value()),
+                stack![
+                    if_else(
+                        less_than(reg(X), pow2(-126)),
+                        set_reg(E, (-126).to()),
+                        stack![
+                            // floor(log2(X)), corrected with the exact powers of two
+                            set_reg(
+                                E,
+                                math_op(
+                                    "floor",
+                                    div(math_op("ln", reg(X)), std::f64::consts::LN_2)
+                                )
+                            ),
+                            if_(
+                                greater_than(pow2_item(reg(E)), reg(X)),
+                                set_reg(E, sub(reg(E), 1))
+                            ),
+                            if_(
+                                not(greater_than(pow2_item(add(reg(E), 1)), reg(X))),
+                                set_reg(E, add(reg(E), 1))
+                            )
+                        ],
+                    ),
+                    // the mantissa as an integer of 24 bits
+                    set_reg(M, div(reg(X), scale())),
+                    set_reg(FRAC, sub(reg(M), math_op("floor", reg(M)))),
+                    set_reg(M, math_op("floor", reg(M))),
+                    if_(
+                        or(
+                            greater_than(reg(FRAC), 0.5),
+                            and(equals(reg(FRAC), 0.5), equals(modulo(reg(M), 2), 1))
+                        ),
+                        set_reg(M, add(reg(M), 1))
+                    ),
+                    set_reg(X, mul(reg(M), scale())),
+                    if_(
+                        not(less_than(reg(X), pow2(128))),
+                        set_reg(X, "Infinity".to())
+                    ),
+                    if_else(less_than(value(), 0), ret(mul(reg(X), -1)), ret(reg(X)),)
+                ],
+            )
+        ],
+    );
+}
@@ -0,0 +1,49 @@
+// scratch numbers are f64, so f32 results are rounded by `Math.fround`
+// same as `depict_nums::f32::DepictF32`
+
+pub mod generator;
+
+use sb_itchy::block::BlockInputBuilder;
+use sb_itchy_support::blocks_wrapper::*;
+use walrus::ir::{BinaryOp, UnaryOp};
+
+use crate::{pre_name::pre_func_name, scratch::block::function_code::bool_to_int};
+
+pub fn f32_func_name(name: &str) -> String {
+    format!("{}f32_{name}", pre_func_name())
+}
+
+/// computed in f64 and rounded once, which is exact for these operators
+pub fn f32_binop_rounded(op: &BinaryOp) -> bool {
+    matches!(
+        op,
+        BinaryOp::F32Add | BinaryOp::F32Sub | BinaryOp::F32Mul | BinaryOp::F32Div
+    )
+}
+
+pub fn f32_unop_rounded(op: &UnaryOp) -> bool {
+    matches!(
+        op,
+        UnaryOp::F32Sqrt
+            | UnaryOp::F32ConvertSI32
+            | UnaryOp::F32ConvertUI32
+            | UnaryOp::F32ConvertSI64
+            | UnaryOp::F32ConvertUI64
+            | UnaryOp::F32DemoteF64
+    )
+}
+
+/// `lo` of an i64 with the bits below 2^12 folded into a sticky bit at 2^11 over 2^53,
+/// so `hi * 2^32 + lo` is exact and rounded only once by fround, same as `DepictI64::convert_f32_s`
+pub fn sticky_lo(abs_hi: BlockInputBuilder, lo: BlockInputBuilder) -> BlockInputBuilder {
+    let low = modulo(&lo, 4096);
+    // 2^21
+    let large = bool_to_int(not(less_than(abs_hi, 2097152)));
+    sub(
+        lo,
+        mul(
+            large,
+            sub(&low, mul(math_op("ceiling", div(&low, 4096)), 2048)),
+        ),
+    )
+}
@@ -1,61 +1,1446 @@
-use sb_sbity::{block::BlockField, string_hashmap::StringHashMap};
-use wain_ast::Instruction;
+use std::collections::HashMap;
 
+use eyre::{eyre, Result};
+use sb_itchy::{block::BlockInputBuilder, blocks, stack::StackBuilder};
+use sb_itchy_support::{block_generator_into::BlockGeneratorInto, blocks_wrapper::*};
+use walrus::{
+    ir::{
+        BinaryOp, Binop, Block, Br, BrIf, BrTable, Call, CallIndirect, Const, GlobalGet, GlobalSet,
+        IfElse, Instr, InstrLocId, InstrSeqId, InstrSeqType, Load, LoadKind, LocalGet, LocalSet,
+        LocalTee, Loop, MemArg, Store, StoreKind, UnaryOp, Unop, Value,
+    },
+    FunctionId, FunctionKind, GlobalId, LocalFunction, LocalId, Module, Type, ValType,
+};
+
+use crate::{
+    pre_name::{FUNCTION_STACK_LIST, GLOBAL_STACK_LIST, LOCAL_STACK_LIST, MEMORY_LIST},
+    GenCtx,
+};
+
+use super::{
+    bindgen::{bindgen_func_name, bindgen_import},
+    f32::{f32_binop_rounded, f32_func_name, f32_unop_rounded, sticky_lo},
+    i32::{i32_binop_func_name, i32_unop_func_name},
+    i64::{i64_binop_func, i64_unop_func_name},
+    memory::{bits_func_name, load_func_name, memory_func_name, store_func_name, PAGE_SIZE},
+    reformat::procedures_definition::func_param_names,
+    table::call_indirect_func_name,
+    trap::wasm_trap,
+};
+
+type Bib = BlockInputBuilder;
+
+// 2^31
+const I32_HALF: f64 = 2147483648.0;
+// 2^32
+const I32_MOD: f64 = 4294967296.0;
+// 2^63
+const I64_HALF: f64 = 9223372036854775808.0;
+// 2^64
+const I64_MOD: f64 = 18446744073709551616.0;
+
+// https://webassembly.github.io/spec/core/exec/instructions.html
+
+/// slot of the break depth, the number of labels left to exit while a branch is taken
+///
+/// 0 while running normally, -1 only in the header of a loop
+const BREAK_DEPTH_SLOT: usize = 0;
+
+/// slot of the number of items in the frame, only a function with a frame has it
+///
+/// returns are lowered before the frame size is known, so the frame is popped by this
+const FRAME_LEN_SLOT: usize = 1;
+
+/// value on the wasm operand stack, an i64 takes two of them
+#[derive(Debug, Clone)]
+struct Operand {
+    /// constant, not written to the stack slot of its depth yet
+    lazy: Option<Bib>,
+    /// lower half of an i64, the upper half is just below
+    low_half: bool,
+}
+
+/// branch target of an enclosing block, loop or if
+#[derive(Debug, Clone)]
+struct Label {
+    seq: InstrSeqId,
+    /// operand stack height below the block parameters
+    height: usize,
+    /// number of slots carried by a branch to this label
+    arity: usize,
+    targeted: bool,
+}
+
+/// lower the body of `function` to scratch blocks
+///
+/// locals and operand stack slots of the function live in a frame pushed on `__wasm_local_stack`
+/// for each call, so a recursive call does not clobber them
+///
+/// a leaf function calls nothing, it can not be running twice at once and
+/// gets a fixed region of `__wasm_local_stack` instead
+///
+/// results are written to the head of `__wasm_function_stack`
+///
+/// with `ctx.source_map`, the comments of the calls are returned too
 pub fn generate_func_block_code(
-    instruction: &Vec<Instruction>,
-    _parent: String,
-) -> StringHashMap<BlockField> {
-    let fields = StringHashMap::default();
-    for (_i, item) in instruction.iter().enumerate() {
-        match &item.kind {
-            wain_ast::InsnKind::Block { ty: _, body: _ } => {
-                // let mut block = generate_func_block_code(&body, parent.clone());
-                // fields.0.extend(block.0);
-            }
-            wain_ast::InsnKind::Loop { ty: _, body: _ } => {
-                // let mut block = generate_func_block_code(&body, parent.clone());
-                // fields.0.extend(block.0);
-            }
-            wain_ast::InsnKind::I32Const(val) => {
-                // let field = BlockField::Value(val.to_string());
-                // fields.0.insert(format!("field_{}", i), field);
-                println!("I32Const: {}", val);
-            }
-            wain_ast::InsnKind::I64Load(offset) => {
-                // let field = BlockField::Value(offset.to_string());
-                // fields.0.insert(format!("field_{}", i), field);
-                println!("I64Load: {:?}", offset);
-            }
-            wain_ast::InsnKind::I64Const(val) => {
-                // let field = BlockField::Value(val.to_string());
-                // fields.0.insert(format!("field_{}", i), field);
-                println!("I64Const: {}", val);
-            }
-            wain_ast::InsnKind::I64Store(offset) => {
-                // let field = BlockField::Value(offset.to_string());
-                // fields.0.insert(format!("field_{}", i), field);
-                println!("I64Store: {:?}", offset);
-            }
-            wain_ast::InsnKind::I64Add => {
-                // let field = BlockField::Value("".to_string());
-                // fields.0.insert(format!("field_{}", i), field);
-                println!("I64Add");
-            }
-            wain_ast::InsnKind::I32Store16(offset) => {
-                // let field = BlockField::Value(offset.to_string());
-                // fields.0.insert(format!("field_{}", i), field);
-                println!("I32Store16: {:?}", offset);
-            }
-            wain_ast::InsnKind::LocalGet(index) => {
-                // let field = BlockField::Value(index.to_string());
-                // fields.0.insert(format!("field_{}", i), field);
-                println!("LocalGet: {:?}", index);
-            }
-            _ => {
-                todo!();
-            }
-        }
-    }
-
-    fields
+    module: &Module,
+    index: usize,
+    function: &LocalFunction,
+    func_type: &Type,
+    ctx: &mut GenCtx,
+) -> Result<(Vec<StackBuilder>, Vec<(String, String)>)> {
+    let mut generator = FuncCodeGen::new(module, index, function, func_type, ctx);
+
+    let mut out = Vec::new();
+    generator.prologue(&mut out)?;
+    if generator.lower_seq(function.entry_block(), &mut out)? {
+        generator.epilogue(&mut out)?;
+        if generator.frame {
+            out.push(generator.pop_frame());
+        }
+    }
+
+    let len = generator.region_len();
+    let frame = generator.frame;
+    if frame {
+        out.splice(0..0, generator.push_frame(len));
+    }
+    let results_len = slots_len(func_type.results());
+    let call_comments = std::mem::take(&mut generator.call_comments);
+    if !frame {
+        ctx.alloc_local_stack(len);
+    }
+    ctx.reserve_function_stack(results_len);
+
+    Ok((out, call_comments))
+}
+
+struct FuncCodeGen<'a> {
+    module: &'a Module,
+    /// index of the function in the module
+    index: usize,
+    function: &'a LocalFunction,
+    func_type: &'a Type,
+    ctx: &'a GenCtx,
+    /// the slots are in a frame at the end of `__wasm_local_stack`, false for a leaf function
+    frame: bool,
+    /// offset of this function's region in `__wasm_local_stack` without a frame
+    base: usize,
+    /// first slot of each local, relative to the locals
+    locals: HashMap<LocalId, usize>,
+    locals_len: usize,
+    stack: Vec<Operand>,
+    max_depth: usize,
+    /// the function itself is the outermost label
+    labels: Vec<Label>,
+    /// outermost label targeted by a branch which is not resolved yet
+    branch_target: Option<usize>,
+    /// offset of the instruction being lowered in the module
+    loc: usize,
+    /// (callee, text) of the calls for the comments
+    call_comments: Vec<(String, String)>,
+}
+
+impl<'a> FuncCodeGen<'a> {
+    fn new(
+        module: &'a Module,
+        index: usize,
+        function: &'a LocalFunction,
+        func_type: &'a Type,
+        ctx: &'a GenCtx,
+    ) -> Self {
+        let mut locals = HashMap::new();
+        let mut locals_len = 0;
+        for local in collect_locals(function) {
+            locals.insert(local, locals_len);
+            locals_len += slot_width(module.locals.get(local).ty());
+        }
+
+        Self {
+            module,
+            index,
+            function,
+            func_type,
+            frame: !is_leaf(function),
+            base: ctx.local_stack_len,
+            ctx,
+            locals,
+            locals_len,
+            stack: Vec::new(),
+            max_depth: 0,
+            labels: vec![Label {
+                seq: function.entry_block(),
+                height: 0,
+                arity: slots_len(func_type.results()),
+                targeted: false,
+            }],
+            branch_target: None,
+            loc: 0,
+            call_comments: Vec::new(),
+        }
+    }
+
+    fn region_len(&self) -> usize {
+        self.header_len() + self.locals_len + self.max_depth
+    }
+
+    // slot ====================================================================
+
+    /// slots before the locals
+    fn header_len(&self) -> usize {
+        match self.frame {
+            true => FRAME_LEN_SLOT + 1,
+            false => BREAK_DEPTH_SLOT + 1,
+        }
+    }
+
+    fn local_slot(&self, local: &LocalId) -> usize {
+        self.header_len() + self.locals[local]
+    }
+
+    fn stack_slot(&self, depth: usize) -> usize {
+        self.header_len() + self.locals_len + depth
+    }
+
+    fn local_is_i64(&self, local: &LocalId) -> bool {
+        self.module.locals.get(*local).ty() == ValType::I64
+    }
+
+    /// pop the operand stack into `local`
+    fn write_local(&mut self, local: &LocalId) -> Result<StackBuilder> {
+        let slot = self.local_slot(local);
+        Ok(match self.local_is_i64(local) {
+            true => {
+                let (hi, lo) = self.pop_i64()?;
+                self.write_pair(slot, hi, lo)
+            }
+            false => {
+                let value = self.pop()?;
+                self.write_slot(slot, value)
+            }
+        })
+    }
+
+    fn global_slot(&self, global: &GlobalId) -> Result<usize> {
+        self.ctx
+            .global_slots
+            .get(&global.index())
+            .copied()
+            .ok_or_else(|| eyre!("global {:?} is not generated", global))
+    }
+
+    fn global_is_i64(&self, global: &GlobalId) -> bool {
+        self.module.globals.get(*global).ty == ValType::I64
+    }
+
+    /// item of `slot` in `__wasm_local_stack`, a frame is addressed from the end of the list
+    /// since callees push and pop their frames above it
+    fn slot_item(&self, slot: usize) -> Bib {
+        match self.frame {
+            true => sub(length_of_list(local_stack()), slot),
+            false => (self.base + slot + 1).to(),
+        }
+    }
+
+    fn read_slot(&self, slot: usize) -> Bib {
+        item_in_list(local_stack(), self.slot_item(slot))
+    }
+
+    fn write_slot(&self, slot: usize, value: Bib) -> StackBuilder {
+        replace_in_list(local_stack(), self.slot_item(slot), value)
+    }
+
+    // operand stack ===========================================================
+
+    fn update_max_depth(&mut self) {
+        self.max_depth = self.max_depth.max(self.stack.len());
+    }
+
+    fn push_lazy(&mut self, value: Bib) {
+        self.stack.push(Operand {
+            lazy: Some(value),
+            low_half: false,
+        });
+        self.update_max_depth();
+    }
+
+    fn push_lazy_i64(&mut self, hi: Bib, lo: Bib) {
+        self.push_lazy(hi);
+        self.stack.push(Operand {
+            lazy: Some(lo),
+            low_half: true,
+        });
+        self.update_max_depth();
+    }
+
+    fn push(&mut self, out: &mut Vec<StackBuilder>, value: Bib) {
+        out.push(self.write_slot(self.stack_slot(self.stack.len()), value));
+        self.push_stored();
+    }
+
+    fn push_i64(&mut self, out: &mut Vec<StackBuilder>, hi: Bib, lo: Bib) {
+        out.push(self.write_pair(self.top_slot(), hi, lo));
+        self.push_stored_i64();
+    }
+
+    /// round an f32 result computed in f64
+    fn push_fround(&mut self, out: &mut Vec<StackBuilder>, value: Bib) {
+        out.push(call_custom_block(
+            &f32_func_name("fround"),
+            vec![("value", value)].into_iter().collect(),
+        ));
+        self.push(out, item_in_list(function_stack(), 1));
+    }
+
+    /// sign extend an i32 value to i64
+    fn push_extend_i64(&mut self, out: &mut Vec<StackBuilder>, value: Bib) {
+        self.push_i64(out, math_op("floor", div(&value, I32_MOD)), to_u32(value));
+    }
+
+    /// push a value which is already written to the next stack slot
+    fn push_stored(&mut self) {
+        self.stack.push(Operand {
+            lazy: None,
+            low_half: false,
+        });
+        self.update_max_depth();
+    }
+
+    fn push_stored_i64(&mut self) {
+        self.push_stored();
+        self.stack.push(Operand {
+            lazy: None,
+            low_half: true,
+        });
+        self.update_max_depth();
+    }
+
+    fn push_stored_ty(&mut self, ty: ValType) {
+        match ty {
+            ValType::I64 => self.push_stored_i64(),
+            _ => self.push_stored(),
+        }
+    }
+
+    fn pop(&mut self) -> Result<Bib> {
+        match self.stack.pop() {
+            Some(Operand {
+                lazy: Some(value), ..
+            }) => Ok(value),
+            Some(_) => Ok(self.read_slot(self.stack_slot(self.stack.len()))),
+            None => Err(eyre!("operand stack underflow")),
+        }
+    }
+
+    /// (hi, lo)
+    fn pop_i64(&mut self) -> Result<(Bib, Bib)> {
+        if !self.top_is_i64() {
+            return Err(eyre!("i64 is expected on the operand stack"));
+        }
+        let lo = self.pop()?;
+        let hi = self.pop()?;
+        Ok((hi, lo))
+    }
+
+    fn top_is_i64(&self) -> bool {
+        self.stack.last().map_or(false, |operand| operand.low_half)
+    }
+
+    /// slot of the value the next push writes to
+    fn top_slot(&self) -> usize {
+        self.stack_slot(self.stack.len())
+    }
+
+    /// lo is written first, `hi` may read the slot it replaces
+    fn write_pair(&self, slot: usize, hi: Bib, lo: Bib) -> StackBuilder {
+        concat(vec![
+            self.write_slot(slot + 1, lo),
+            self.write_slot(slot, hi),
+        ])
+    }
+
+    /// write every lazy operand to its stack slot
+    fn flush(&mut self, out: &mut Vec<StackBuilder>) {
+        for depth in 0..self.stack.len() {
+            if let Some(value) = self.stack[depth].lazy.take() {
+                out.push(self.write_slot(self.stack_slot(depth), value));
+            }
+        }
+    }
+
+    // function ================================================================
+
+    /// `len` items of 0 for the slots, the break depth starts at 0
+    fn push_frame(&self, len: usize) -> Vec<StackBuilder> {
+        vec![
+            repeat(len, add_to_list(local_stack(), 0)),
+            self.write_slot(FRAME_LEN_SLOT, len.to()),
+        ]
+    }
+
+    /// `repeat` reads the frame size once, before the items are deleted
+    fn pop_frame(&self) -> StackBuilder {
+        repeat(
+            self.read_slot(FRAME_LEN_SLOT),
+            delete_in_list(local_stack(), length_of_list(local_stack())),
+        )
+    }
+
+    /// blocks returning from the function, the results are already written
+    fn leave_function(&self) -> StackBuilder {
+        match self.frame {
+            true => concat(vec![self.pop_frame(), stop("this script", false)]),
+            false => stop("this script", false),
+        }
+    }
+
+    fn prologue(&mut self, out: &mut Vec<StackBuilder>) -> Result<()> {
+        let mut names = self
+            .ctx
+            .gen_param_names(self.index, self.func_type.params())?
+            .into_iter();
+        for arg in self.function.args.iter() {
+            let width = slot_width(self.module.locals.get(*arg).ty());
+            for k in 0..width {
+                let name = names
+                    .next()
+                    .ok_or_else(|| eyre!("parameters do not match the function type"))?;
+                out.push(self.write_slot(
+                    self.local_slot(arg) + k,
+                    custom_block_var_string_number(name),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn epilogue(&mut self, out: &mut Vec<StackBuilder>) -> Result<()> {
+        let results_len = slots_len(self.func_type.results());
+        for i in (0..results_len).rev() {
+            let value = self.pop()?;
+            out.push(replace_in_list(function_stack(), i + 1, value));
+        }
+        Ok(())
+    }
+
+    // instructions ============================================================
+
+    /// returns false if the end of the sequence is unreachable
+    fn lower_seq(&mut self, seq: InstrSeqId, out: &mut Vec<StackBuilder>) -> Result<bool> {
+        let function = self.function;
+        self.lower_instrs(&function.block(seq).instrs, out)
+    }
+
+    fn lower_instrs(
+        &mut self,
+        instrs: &[(Instr, InstrLocId)],
+        out: &mut Vec<StackBuilder>,
+    ) -> Result<bool> {
+        for (k, (instr, loc)) in instrs.iter().enumerate() {
+            self.loc = loc.data() as usize;
+            let pending = self.branch_target.take();
+            let reachable = self.lower_instr(instr, out)?;
+            let escaped = self.branch_target;
+            self.branch_target = pending.into_iter().chain(escaped).min();
+
+            if !reachable {
+                // the rest is dead code
+                return Ok(false);
+            }
+            if escaped.is_some() && k + 1 < instrs.len() {
+                // the rest runs only if no branch is taken
+                let mut rest = Vec::new();
+                let reachable = self.lower_instrs(&instrs[k + 1..], &mut rest)?;
+                out.push(blocks::if_(equals(self.break_depth(), 0), substack(rest)));
+                return Ok(reachable);
+            }
+        }
+        // branches must not be overwritten by the values of the fallthrough
+        self.flush(out);
+        Ok(true)
+    }
+
+    fn lower_instr(&mut self, instr: &Instr, out: &mut Vec<StackBuilder>) -> Result<bool> {
+        match instr {
+            Instr::Const(Const { value }) => {
+                let value = match value {
+                    Value::I32(v) => v.to(),
+                    Value::I64(v) => {
+                        self.push_lazy_i64((v >> 32).to(), (*v as u32 as i64).to());
+                        return Ok(true);
+                    }
+                    Value::F32(v) => (*v as f64).to(),
+                    Value::F64(v) => v.to(),
+                    Value::V128(_) => return Err(eyre!("v128 is not supported")),
+                };
+                self.push_lazy(value);
+            }
+            Instr::LocalGet(LocalGet { local }) => {
+                let slot = self.local_slot(local);
+                match self.local_is_i64(local) {
+                    true => self.push_i64(out, self.read_slot(slot), self.read_slot(slot + 1)),
+                    false => self.push(out, self.read_slot(slot)),
+                }
+            }
+            Instr::LocalSet(LocalSet { local }) => {
+                out.push(self.write_local(local)?);
+            }
+            Instr::LocalTee(LocalTee { local }) => {
+                out.push(self.write_local(local)?);
+                let slot = self.local_slot(local);
+                match self.local_is_i64(local) {
+                    true => self.push_i64(out, self.read_slot(slot), self.read_slot(slot + 1)),
+                    false => self.push(out, self.read_slot(slot)),
+                }
+            }
+            Instr::Drop(_) => {
+                if self.top_is_i64() {
+                    self.pop_i64()?;
+                } else {
+                    self.pop()?;
+                }
+            }
+            Instr::Select(_) => {
+                let condition = self.pop()?;
+                if self.top_is_i64() {
+                    let (false_hi, false_lo) = self.pop_i64()?;
+                    let (true_hi, true_lo) = self.pop_i64()?;
+                    let slot = self.top_slot();
+                    out.push(if_else(
+                        not_zero(condition),
+                        self.write_pair(slot, true_hi, true_lo),
+                        self.write_pair(slot, false_hi, false_lo),
+                    ));
+                    self.push_stored_i64();
+                } else {
+                    let if_false = self.pop()?;
+                    let if_true = self.pop()?;
+                    let slot = self.top_slot();
+                    out.push(if_else(
+                        not_zero(condition),
+                        self.write_slot(slot, if_true),
+                        self.write_slot(slot, if_false),
+                    ));
+                    self.push_stored();
+                }
+            }
+            Instr::Binop(Binop { op }) => self.lower_binop(op, out)?,
+            Instr::Unop(Unop { op }) => self.lower_unop(op, out)?,
+            Instr::Call(Call { func }) => self.lower_call(func, out)?,
+            Instr::CallIndirect(CallIndirect { ty, table }) => {
+                let index = self.pop()?;
+                let name = call_indirect_func_name(*table, *ty);
+                let callee_type = self.module.types.get(*ty);
+                let names = func_param_names(callee_type.params())?;
+                self.lower_call_with(&name, names, callee_type, Some(index), out)?;
+            }
+            Instr::Return(_) => {
+                self.epilogue(out)?;
+                out.push(self.leave_function());
+                return Ok(false);
+            }
+            Instr::Unreachable(_) => {
+                out.push(wasm_trap("unreachable"));
+                return Ok(false);
+            }
+            Instr::Block(Block { seq }) => return self.lower_block(*seq, out),
+            Instr::Loop(Loop { seq }) => return self.lower_loop(*seq, out),
+            Instr::IfElse(IfElse {
+                consequent,
+                alternative,
+            }) => {
+                let condition = self.pop()?;
+                return self.lower_if_else(condition, *consequent, *alternative, out);
+            }
+            Instr::Br(Br { block }) => {
+                let branch = self.lower_branch(*block)?;
+                out.push(branch);
+                return Ok(false);
+            }
+            Instr::BrIf(BrIf { block }) => {
+                let condition = self.pop()?;
+                self.flush(out);
+                let branch = self.lower_branch(*block)?;
+                out.push(if_(not_zero(condition), branch));
+            }
+            Instr::BrTable(BrTable { blocks, default }) => {
+                let index = to_u32(self.pop()?);
+                let default = self.lower_branch(*default)?;
+                let branches = blocks
+                    .iter()
+                    .map(|block| self.lower_branch(*block))
+                    .collect::<Result<Vec<_>>>()?;
+                out.push(match branches.len() {
+                    0 => default,
+                    len => if_else(
+                        less_than(&index, len),
+                        branch_tree(&index, 0, branches),
+                        default,
+                    ),
+                });
+                return Ok(false);
+            }
+            Instr::GlobalGet(GlobalGet { global }) => {
+                let slot = self.global_slot(global)?;
+                match self.global_is_i64(global) {
+                    true => self.push_i64(out, read_global(slot), read_global(slot + 1)),
+                    false => self.push(out, read_global(slot)),
+                }
+            }
+            Instr::GlobalSet(GlobalSet { global }) => {
+                let slot = self.global_slot(global)?;
+                out.push(match self.global_is_i64(global) {
+                    true => {
+                        let (hi, lo) = self.pop_i64()?;
+                        concat(vec![write_global(slot + 1, lo), write_global(slot, hi)])
+                    }
+                    false => write_global(slot, self.pop()?),
+                });
+            }
+            Instr::Load(Load { kind, arg, .. }) => {
+                let addr = self.pop()?;
+                out.push(call_custom_block(
+                    &load_func_name(kind)?,
+                    vec![("addr", effective_addr(addr, arg))]
+                        .into_iter()
+                        .collect(),
+                ));
+                let value = item_in_list(function_stack(), 1);
+                match kind {
+                    LoadKind::I64 { .. } => {
+                        self.push_i64(out, value, item_in_list(function_stack(), 2))
+                    }
+                    LoadKind::I64_8 { .. } | LoadKind::I64_16 { .. } | LoadKind::I64_32 { .. } => {
+                        self.push_extend_i64(out, value)
+                    }
+                    _ => self.push(out, value),
+                }
+            }
+            Instr::Store(Store { kind, arg, .. }) => {
+                let args = match kind {
+                    StoreKind::I64 { .. } => {
+                        let (hi, lo) = self.pop_i64()?;
+                        vec![("hi", hi), ("lo", lo)]
+                    }
+                    // the lower bytes are in lo
+                    StoreKind::I64_8 { .. }
+                    | StoreKind::I64_16 { .. }
+                    | StoreKind::I64_32 { .. } => {
+                        vec![("value", self.pop_i64()?.1)]
+                    }
+                    _ => vec![("value", self.pop()?)],
+                };
+                let addr = self.pop()?;
+                out.push(call_custom_block(
+                    &store_func_name(kind)?,
+                    std::iter::once(("addr", effective_addr(addr, arg)))
+                        .chain(args)
+                        .collect(),
+                ));
+            }
+            Instr::MemorySize(_) => {
+                self.push(
+                    out,
+                    div(length_of_list(global_list_menu(MEMORY_LIST)), PAGE_SIZE),
+                );
+            }
+            Instr::MemoryGrow(_) => {
+                let pages = self.pop()?;
+                out.push(call_custom_block(
+                    &memory_func_name("grow"),
+                    vec![("pages", to_u32(pages))].into_iter().collect(),
+                ));
+                self.push(out, item_in_list(function_stack(), 1));
+            }
+            Instr::MemoryFill(_) => {
+                let len = self.pop()?;
+                let value = self.pop()?;
+                let dst = self.pop()?;
+                out.push(call_custom_block(
+                    &memory_func_name("fill"),
+                    vec![("dst", to_u32(dst)), ("value", value), ("len", to_u32(len))]
+                        .into_iter()
+                        .collect(),
+                ));
+            }
+            Instr::MemoryCopy(_) => {
+                let len = self.pop()?;
+                let src = self.pop()?;
+                let dst = self.pop()?;
+                out.push(call_custom_block(
+                    &memory_func_name("copy"),
+                    vec![
+                        ("dst", to_u32(dst)),
+                        ("src", to_u32(src)),
+                        ("len", to_u32(len)),
+                    ]
+                    .into_iter()
+                    .collect(),
+                ));
+            }
+            Instr::MemoryInit(_) | Instr::DataDrop(_) => {
+                return Err(eyre!(
+                    "passive data segment is not supported yet: {:?}",
+                    instr
+                ));
+            }
+            _ => return Err(eyre!("unsupported instruction: {:?}", instr)),
+        }
+        Ok(true)
+    }
+
+    // control =================================================================
+
+    fn break_depth(&self) -> Bib {
+        self.read_slot(BREAK_DEPTH_SLOT)
+    }
+
+    fn set_break_depth<T: BlockGeneratorInto<Bib>>(&self, value: T) -> StackBuilder {
+        self.write_slot(BREAK_DEPTH_SLOT, value.to())
+    }
+
+    /// (height, params, results) of the block `seq` entered now
+    fn block_arity(&self, seq: InstrSeqId) -> Result<(usize, Vec<ValType>, Vec<ValType>)> {
+        let (params, results) = match self.function.block(seq).ty {
+            InstrSeqType::Simple(ty) => (vec![], ty.into_iter().collect()),
+            InstrSeqType::MultiValue(ty) => {
+                let ty = self.module.types.get(ty);
+                (ty.params().to_vec(), ty.results().to_vec())
+            }
+        };
+        let height = self
+            .stack
+            .len()
+            .checked_sub(slots_len(&params))
+            .ok_or_else(|| eyre!("operand stack underflow"))?;
+        Ok((height, params, results))
+    }
+
+    /// pop the label of a finished block and resolve the branches to it
+    fn leave(
+        &mut self,
+        out: &mut Vec<StackBuilder>,
+        height: usize,
+        results: &[ValType],
+        count: bool,
+    ) {
+        let index = self.labels.len();
+        if let Some(target) = self.branch_target {
+            if count {
+                // a branch to this label or an outer one passes here
+                out.push(if_(
+                    greater_than(self.break_depth(), 0),
+                    self.set_break_depth(sub(self.break_depth(), 1)),
+                ));
+            }
+            if target >= index {
+                self.branch_target = None;
+            }
+        }
+
+        self.stack.truncate(height);
+        for ty in results {
+            self.push_stored_ty(*ty);
+        }
+    }
+
+    fn lower_block(&mut self, seq: InstrSeqId, out: &mut Vec<StackBuilder>) -> Result<bool> {
+        self.flush(out);
+        let (height, _, results) = self.block_arity(seq)?;
+        self.labels.push(Label {
+            seq,
+            height,
+            arity: slots_len(&results),
+            targeted: false,
+        });
+
+        let reachable = self.lower_seq(seq, out)?;
+        let label = self.labels.pop().unwrap();
+        self.leave(out, height, &results, true);
+
+        Ok(reachable || label.targeted)
+    }
+
+    fn lower_loop(&mut self, seq: InstrSeqId, out: &mut Vec<StackBuilder>) -> Result<bool> {
+        self.flush(out);
+        let (height, params, results) = self.block_arity(seq)?;
+        self.labels.push(Label {
+            seq,
+            height,
+            arity: slots_len(&params),
+            targeted: false,
+        });
+
+        let mut body = vec![self.set_break_depth(0)];
+        let reachable = self.lower_seq(seq, &mut body)?;
+        let label = self.labels.pop().unwrap();
+
+        if label.targeted {
+            // a branch to the loop starts the next iteration
+            body.push(if_else(
+                equals(self.break_depth(), 1),
+                self.set_break_depth(-1),
+                if_(
+                    greater_than(self.break_depth(), 1),
+                    self.set_break_depth(sub(self.break_depth(), 1)),
+                ),
+            ));
+            out.push(self.set_break_depth(-1));
+            out.push(blocks::repeat_until(
+                not(equals(self.break_depth(), -1)),
+                substack(body),
+            ));
+            self.leave(out, height, &results, false);
+        } else {
+            // never repeated, same as a block
+            out.extend(body.into_iter().skip(1));
+            self.leave(out, height, &results, true);
+        }
+
+        Ok(reachable)
+    }
+
+    fn lower_if_else(
+        &mut self,
+        condition: Bib,
+        consequent: InstrSeqId,
+        alternative: InstrSeqId,
+        out: &mut Vec<StackBuilder>,
+    ) -> Result<bool> {
+        self.flush(out);
+        let (height, _, results) = self.block_arity(consequent)?;
+        let saved = self.stack.clone();
+
+        let mut lower_arm = |seq: InstrSeqId| -> Result<(Vec<StackBuilder>, bool)> {
+            self.stack = saved.clone();
+            self.labels.push(Label {
+                seq,
+                height,
+                arity: slots_len(&results),
+                targeted: false,
+            });
+            let mut arm = Vec::new();
+            let reachable = self.lower_seq(seq, &mut arm)?;
+            let label = self.labels.pop().unwrap();
+            Ok((arm, reachable || label.targeted))
+        };
+        let (consequent_out, consequent_reachable) = lower_arm(consequent)?;
+        let (alternative_out, alternative_reachable) = lower_arm(alternative)?;
+
+        out.push(blocks::if_else(
+            not_zero(condition),
+            substack(consequent_out),
+            substack(alternative_out),
+        ));
+        self.leave(out, height, &results, true);
+
+        Ok(consequent_reachable || alternative_reachable)
+    }
+
+    /// blocks taking the branch to the label of `seq`
+    fn lower_branch(&mut self, seq: InstrSeqId) -> Result<StackBuilder> {
+        let index = self
+            .labels
+            .iter()
+            .rposition(|label| label.seq == seq)
+            .ok_or_else(|| eyre!("branch to unknown label: {:?}", seq))?;
+        let Label { height, arity, .. } = self.labels[index];
+        let len = self.stack.len();
+        if len < arity {
+            return Err(eyre!("operand stack underflow"));
+        }
+
+        // the outermost label returns from the function
+        if index == 0 {
+            let mut out = Vec::new();
+            for (i, depth) in (len - arity..len).enumerate() {
+                let value = match &self.stack[depth].lazy {
+                    Some(value) => value.clone(),
+                    None => self.read_slot(self.stack_slot(depth)),
+                };
+                out.push(replace_in_list(function_stack(), i + 1, value));
+            }
+            out.push(self.leave_function());
+            return Ok(concat(out));
+        }
+
+        // move the carried values down to the slots the label expects
+        let mut out = Vec::new();
+        for (i, depth) in (len - arity..len).enumerate() {
+            let slot = self.stack_slot(height + i);
+            match &self.stack[depth].lazy {
+                Some(value) => out.push(self.write_slot(slot, value.clone())),
+                None if depth != height + i => {
+                    out.push(self.write_slot(slot, self.read_slot(self.stack_slot(depth))))
+                }
+                None => {}
+            }
+        }
+        out.push(self.set_break_depth(self.labels.len() - index));
+
+        self.labels[index].targeted = true;
+        self.branch_target = Some(self.branch_target.map_or(index, |target| target.min(index)));
+
+        Ok(concat(out))
+    }
+
+    fn lower_call(&mut self, func: &FunctionId, out: &mut Vec<StackBuilder>) -> Result<()> {
+        let name = callee_name(self.module, self.ctx, *func)?;
+        let callee_type = self.module.types.get(self.module.funcs.get(*func).ty());
+        let names = self
+            .ctx
+            .gen_param_names(func.index(), callee_type.params())?;
+        self.lower_call_with(&name, names, callee_type, None, out)
+    }
+
+    /// pop the arguments named `names` and call `name`, `index` is the first input of a dispatch
+    fn lower_call_with(
+        &mut self,
+        name: &str,
+        names: Vec<String>,
+        callee_type: &Type,
+        index: Option<Bib>,
+        out: &mut Vec<StackBuilder>,
+    ) -> Result<()> {
+        if let Some(source_map) = &self.ctx.source_map {
+            let text = format!("call {name}\n{}", source_map.describe(self.loc));
+            self.call_comments.push((name.to_string(), text));
+        }
+
+        let mut args = Vec::new();
+        for name in names.into_iter().rev() {
+            args.push((name, self.pop()?));
+        }
+        if let Some(index) = index {
+            args.push(("index".to_string(), to_u32(index)));
+        }
+
+        out.push(call_custom_block(
+            name,
+            args.iter()
+                .map(|(name, value)| (name.as_str(), value.clone()))
+                .collect(),
+        ));
+
+        // the results are at the head of __wasm_function_stack in order, copied before the next call
+        let mut item = 1;
+        for ty in callee_type.results() {
+            match ty {
+                ValType::I64 => self.push_i64(
+                    out,
+                    item_in_list(function_stack(), item),
+                    item_in_list(function_stack(), item + 1),
+                ),
+                _ => self.push(out, item_in_list(function_stack(), item)),
+            }
+            item += slot_width(*ty);
+        }
+        Ok(())
+    }
+
+    fn lower_binop(&mut self, op: &BinaryOp, out: &mut Vec<StackBuilder>) -> Result<()> {
+        if let Some((name, ty)) = i64_binop_func(op) {
+            let (rhs_hi, rhs_lo) = self.pop_i64()?;
+            let (lhs_hi, lhs_lo) = self.pop_i64()?;
+            out.push(call_custom_block(
+                &name,
+                vec![
+                    ("lhs_hi", lhs_hi),
+                    ("lhs_lo", lhs_lo),
+                    ("rhs_hi", rhs_hi),
+                    ("rhs_lo", rhs_lo),
+                ]
+                .into_iter()
+                .collect(),
+            ));
+            match ty {
+                ValType::I64 => self.push_i64(
+                    out,
+                    item_in_list(function_stack(), 1),
+                    item_in_list(function_stack(), 2),
+                ),
+                _ => self.push(out, item_in_list(function_stack(), 1)),
+            }
+            return Ok(());
+        }
+
+        let rhs = self.pop()?;
+        let lhs = self.pop()?;
+        if let Some(name) = i32_binop_func_name(op) {
+            out.push(call_custom_block(
+                &name,
+                vec![("lhs", lhs), ("rhs", rhs)].into_iter().collect(),
+            ));
+            self.push(out, item_in_list(function_stack(), 1));
+            return Ok(());
+        }
+        let value = match op {
+            BinaryOp::I32Add => wrap_i32(add(lhs, rhs)),
+            BinaryOp::I32Sub => wrap_i32(sub(lhs, rhs)),
+
+            BinaryOp::I32Eq | BinaryOp::F32Eq | BinaryOp::F64Eq => bool_to_int(equals(lhs, rhs)),
+            BinaryOp::I32Ne | BinaryOp::F32Ne | BinaryOp::F64Ne => {
+                bool_to_int(not(equals(lhs, rhs)))
+            }
+            BinaryOp::I32LtS | BinaryOp::F32Lt | BinaryOp::F64Lt => {
+                bool_to_int(less_than(lhs, rhs))
+            }
+            BinaryOp::I32GtS | BinaryOp::F32Gt | BinaryOp::F64Gt => {
+                bool_to_int(greater_than(lhs, rhs))
+            }
+            BinaryOp::I32LeS | BinaryOp::F32Le | BinaryOp::F64Le => {
+                bool_to_int(not(greater_than(lhs, rhs)))
+            }
+            BinaryOp::I32GeS | BinaryOp::F32Ge | BinaryOp::F64Ge => {
+                bool_to_int(not(less_than(lhs, rhs)))
+            }
+            BinaryOp::I32LtU => bool_to_int(less_than(to_u32(lhs), to_u32(rhs))),
+            BinaryOp::I32GtU => bool_to_int(greater_than(to_u32(lhs), to_u32(rhs))),
+            BinaryOp::I32LeU => bool_to_int(not(greater_than(to_u32(lhs), to_u32(rhs)))),
+            BinaryOp::I32GeU => bool_to_int(not(less_than(to_u32(lhs), to_u32(rhs)))),
+
+            BinaryOp::F32Add | BinaryOp::F64Add => add(lhs, rhs),
+            BinaryOp::F32Sub | BinaryOp::F64Sub => sub(lhs, rhs),
+            BinaryOp::F32Mul | BinaryOp::F64Mul => mul(lhs, rhs),
+            BinaryOp::F32Div | BinaryOp::F64Div => div(lhs, rhs),
+            BinaryOp::F32Min | BinaryOp::F64Min => {
+                let slot = self.top_slot();
+                out.push(if_else(
+                    less_than(&lhs, &rhs),
+                    self.write_slot(slot, lhs),
+                    self.write_slot(slot, rhs),
+                ));
+                self.push_stored();
+                return Ok(());
+            }
+            BinaryOp::F32Max | BinaryOp::F64Max => {
+                let slot = self.top_slot();
+                out.push(if_else(
+                    greater_than(&lhs, &rhs),
+                    self.write_slot(slot, lhs),
+                    self.write_slot(slot, rhs),
+                ));
+                self.push_stored();
+                return Ok(());
+            }
+            BinaryOp::F32Copysign | BinaryOp::F64Copysign => {
+                let slot = self.top_slot();
+                out.push(if_else(
+                    less_than(rhs, 0),
+                    self.write_slot(slot, mul(math_op("abs", &lhs), -1)),
+                    self.write_slot(slot, math_op("abs", lhs)),
+                ));
+                self.push_stored();
+                return Ok(());
+            }
+            _ => return Err(eyre!("unsupported binary operator: {:?}", op)),
+        };
+        if f32_binop_rounded(op) {
+            self.push_fround(out, value);
+        } else {
+            self.push(out, value);
+        }
+        Ok(())
+    }
+
+    fn lower_unop(&mut self, op: &UnaryOp, out: &mut Vec<StackBuilder>) -> Result<()> {
+        if let Some(name) = i64_unop_func_name(op) {
+            let (hi, lo) = self.pop_i64()?;
+            out.push(call_custom_block(
+                &name,
+                vec![("value_hi", hi), ("value_lo", lo)]
+                    .into_iter()
+                    .collect(),
+            ));
+            self.push_i64(
+                out,
+                item_in_list(function_stack(), 1),
+                item_in_list(function_stack(), 2),
+            );
+            return Ok(());
+        }
+        if self.top_is_i64() {
+            return self.lower_i64_unop(op, out);
+        }
+
+        let value = self.pop()?;
+        if let Some(name) = i32_unop_func_name(op) {
+            out.push(call_custom_block(
+                &name,
+                vec![("value", value)].into_iter().collect(),
+            ));
+            self.push(out, item_in_list(function_stack(), 1));
+            return Ok(());
+        }
+        let value = match op {
+            UnaryOp::I32Eqz => bool_to_int(equals(value, 0)),
+
+            UnaryOp::F32Abs | UnaryOp::F64Abs => math_op("abs", value),
+            UnaryOp::F32Neg | UnaryOp::F64Neg => mul(value, -1),
+            UnaryOp::F32Ceil | UnaryOp::F64Ceil => math_op("ceiling", value),
+            UnaryOp::F32Floor | UnaryOp::F64Floor => math_op("floor", value),
+            UnaryOp::F32Sqrt | UnaryOp::F64Sqrt => math_op("sqrt", value),
+            UnaryOp::F32Nearest | UnaryOp::F64Nearest => nearest(value),
+            UnaryOp::F32Trunc
+            | UnaryOp::F64Trunc
+            | UnaryOp::I32TruncSF32
+            | UnaryOp::I32TruncUF32
+            | UnaryOp::I32TruncSF64
+            | UnaryOp::I32TruncUF64 => {
+                let slot = self.top_slot();
+                out.push(self.write_trunc(slot, value));
+                self.push_stored();
+                return Ok(());
+            }
+            UnaryOp::I32TruncSSatF32 | UnaryOp::I32TruncSSatF64 => {
+                let slot = self.top_slot();
+                out.push(self.write_trunc_sat(slot, value, -I32_HALF, I32_HALF - 1.0));
+                self.push_stored();
+                return Ok(());
+            }
+            UnaryOp::I32TruncUSatF32 | UnaryOp::I32TruncUSatF64 => {
+                let slot = self.top_slot();
+                out.push(self.write_trunc_sat(slot, value, 0.0, I32_MOD - 1.0));
+                self.push_stored();
+                return Ok(());
+            }
+
+            UnaryOp::I64TruncSF32
+            | UnaryOp::I64TruncUF32
+            | UnaryOp::I64TruncSF64
+            | UnaryOp::I64TruncUF64 => {
+                let slot = self.top_slot();
+                out.push(self.write_trunc_i64(slot, value));
+                self.push_stored_i64();
+                return Ok(());
+            }
+            UnaryOp::I64TruncSSatF32 | UnaryOp::I64TruncSSatF64 => {
+                let slot = self.top_slot();
+                out.push(self.write_trunc_sat_i64(slot, value, true));
+                self.push_stored_i64();
+                return Ok(());
+            }
+            UnaryOp::I64TruncUSatF32 | UnaryOp::I64TruncUSatF64 => {
+                let slot = self.top_slot();
+                out.push(self.write_trunc_sat_i64(slot, value, false));
+                self.push_stored_i64();
+                return Ok(());
+            }
+
+            UnaryOp::I64ExtendSI32 => {
+                self.push_extend_i64(out, value);
+                return Ok(());
+            }
+            UnaryOp::I64ExtendUI32 => {
+                self.push_i64(out, 0.to(), to_u32(value));
+                return Ok(());
+            }
+            UnaryOp::I64ReinterpretF64 => {
+                // bits are returned as (lo, hi)
+                out.push(call_custom_block(
+                    &bits_func_name("f64_to_bits"),
+                    vec![("value", value)].into_iter().collect(),
+                ));
+                self.push_i64(
+                    out,
+                    wrap_i32(item_in_list(function_stack(), 2)),
+                    item_in_list(function_stack(), 1),
+                );
+                return Ok(());
+            }
+            UnaryOp::I32ReinterpretF32 => {
+                out.push(call_custom_block(
+                    &bits_func_name("f32_to_bits"),
+                    vec![("value", value)].into_iter().collect(),
+                ));
+                wrap_i32(item_in_list(function_stack(), 1))
+            }
+            UnaryOp::F32ReinterpretI32 => {
+                out.push(call_custom_block(
+                    &bits_func_name("f32_from_bits"),
+                    vec![("bits", value)].into_iter().collect(),
+                ));
+                item_in_list(function_stack(), 1)
+            }
+            UnaryOp::I32Extend8S => sub(modulo(add(value, 128), 256), 128),
+            UnaryOp::I32Extend16S => sub(modulo(add(value, 32768), 65536), 32768),
+
+            UnaryOp::F32ConvertSI32
+            | UnaryOp::F64ConvertSI32
+            | UnaryOp::F32DemoteF64
+            | UnaryOp::F64PromoteF32 => value,
+            UnaryOp::F32ConvertUI32 | UnaryOp::F64ConvertUI32 => to_u32(value),
+            _ => return Err(eyre!("unsupported unary operator: {:?}", op)),
+        };
+        if f32_unop_rounded(op) {
+            self.push_fround(out, value);
+        } else {
+            self.push(out, value);
+        }
+        Ok(())
+    }
+
+    /// operators taking an i64 operand
+    fn lower_i64_unop(&mut self, op: &UnaryOp, out: &mut Vec<StackBuilder>) -> Result<()> {
+        let (hi, lo) = self.pop_i64()?;
+        let value = match op {
+            UnaryOp::I64Eqz => bool_to_int(and(equals(hi, 0), equals(lo, 0))),
+            UnaryOp::I32WrapI64 => wrap_i32(lo),
+            // hi * 2^32 is exact, so this rounds only once
+            UnaryOp::F64ConvertSI64 => add(mul(hi, I32_MOD), lo),
+            UnaryOp::F64ConvertUI64 => add(mul(to_u32(hi), I32_MOD), lo),
+            // rounded to f64 first and to f32 by fround, unless lo is made sticky
+            UnaryOp::F32ConvertSI64 => add(mul(&hi, I32_MOD), sticky_lo(math_op("abs", hi), lo)),
+            UnaryOp::F32ConvertUI64 => {
+                let hi = to_u32(hi);
+                add(mul(&hi, I32_MOD), sticky_lo(hi, lo))
+            }
+            UnaryOp::F64ReinterpretI64 => {
+                out.push(call_custom_block(
+                    &bits_func_name("f64_from_bits"),
+                    vec![("lo", lo), ("hi", hi)].into_iter().collect(),
+                ));
+                item_in_list(function_stack(), 1)
+            }
+            UnaryOp::I64Extend8S | UnaryOp::I64Extend16S | UnaryOp::I64Extend32S => {
+                let value = match op {
+                    UnaryOp::I64Extend8S => sub(modulo(add(lo, 128), 256), 128),
+                    UnaryOp::I64Extend16S => sub(modulo(add(lo, 32768), 65536), 32768),
+                    _ => wrap_i32(lo),
+                };
+                // hi first, the value is read from the slot of lo
+                let slot = self.top_slot();
+                out.push(self.write_slot(slot, math_op("floor", div(&value, I32_MOD))));
+                out.push(self.write_slot(slot + 1, to_u32(value)));
+                self.push_stored_i64();
+                return Ok(());
+            }
+            _ => return Err(eyre!("unsupported unary operator: {:?}", op)),
+        };
+        if f32_unop_rounded(op) {
+            self.push_fround(out, value);
+        } else {
+            self.push(out, value);
+        }
+        Ok(())
+    }
+
+    fn write_trunc(&self, slot: usize, value: Bib) -> StackBuilder {
+        if_else(
+            less_than(&value, 0),
+            self.write_slot(slot, math_op("ceiling", &value)),
+            self.write_slot(slot, math_op("floor", value)),
+        )
+    }
+
+    /// NaN is read as 0 in scratch, so it needs no special case
+    fn write_trunc_sat(&self, slot: usize, value: Bib, min: f64, max: f64) -> StackBuilder {
+        if_else(
+            less_than(&value, min),
+            self.write_slot(slot, min.to()),
+            if_else(
+                greater_than(&value, max),
+                self.write_slot(slot, max.to()),
+                self.write_trunc(slot, value),
+            ),
+        )
+    }
+
+    /// truncated into lo first, then split at 2^32
+    fn write_trunc_i64(&self, slot: usize, value: Bib) -> StackBuilder {
+        let lo = || self.read_slot(slot + 1);
+        concat(vec![
+            self.write_trunc(slot + 1, value),
+            self.write_slot(slot, wrap_i32(math_op("floor", div(lo(), I32_MOD)))),
+            self.write_slot(slot + 1, to_u32(lo())),
+        ])
+    }
+
+    fn write_trunc_sat_i64(&self, slot: usize, value: Bib, signed: bool) -> StackBuilder {
+        // the upper bound itself is out of range
+        let (lower, upper, min, max) = match signed {
+            true => (-I64_HALF, I64_HALF, i64::MIN, i64::MAX),
+            false => (0.0, I64_MOD, 0, u64::MAX as i64),
+        };
+        let split = |x: i64| self.write_pair(slot, (x >> 32).to(), (x as u32 as i64).to());
+        if_else(
+            less_than(&value, lower),
+            split(min),
+            if_else(
+                not(less_than(&value, upper)),
+                split(max),
+                self.write_trunc_i64(slot, value),
+            ),
+        )
+    }
+}
+
+/// number of stack slots taken by a value of `ty`
+pub(crate) fn slot_width(ty: ValType) -> usize {
+    match ty {
+        ValType::I64 => 2,
+        _ => 1,
+    }
+}
+
+pub(crate) fn slots_len(tys: &[ValType]) -> usize {
+    tys.iter().map(|ty| slot_width(*ty)).sum()
+}
+
+/// every local of the function, parameters first
+fn collect_locals(function: &LocalFunction) -> Vec<LocalId> {
+    let mut locals = function.args.clone();
+    let mut seqs = vec![function.entry_block()];
+    while let Some(seq) = seqs.pop() {
+        for (instr, _) in function.block(seq).instrs.iter() {
+            match instr {
+                Instr::LocalGet(LocalGet { local })
+                | Instr::LocalSet(LocalSet { local })
+                | Instr::LocalTee(LocalTee { local }) => {
+                    if !locals.contains(local) {
+                        locals.push(*local);
+                    }
+                }
+                Instr::Block(Block { seq }) | Instr::Loop(Loop { seq }) => seqs.push(*seq),
+                Instr::IfElse(IfElse {
+                    consequent,
+                    alternative,
+                }) => {
+                    seqs.push(*consequent);
+                    seqs.push(*alternative);
+                }
+                _ => {}
+            }
+        }
+    }
+    locals
+}
+
+/// no call in the body, see `generate_func_block_code`
+///
+/// an import may call back into the module, so it is a call too
+fn is_leaf(function: &LocalFunction) -> bool {
+    let mut seqs = vec![function.entry_block()];
+    while let Some(seq) = seqs.pop() {
+        for (instr, _) in function.block(seq).instrs.iter() {
+            match instr {
+                Instr::Call(_) | Instr::CallIndirect(_) => return false,
+                Instr::Block(Block { seq }) | Instr::Loop(Loop { seq }) => seqs.push(*seq),
+                Instr::IfElse(IfElse {
+                    consequent,
+                    alternative,
+                }) => {
+                    seqs.push(*consequent);
+                    seqs.push(*alternative);
+                }
+                _ => {}
+            }
+        }
+    }
+    true
+}
+
+pub(crate) fn local_stack() -> sb_itchy::block::BlockFieldBuilder {
+    global_list_menu(LOCAL_STACK_LIST)
+}
+
+pub(crate) fn function_stack() -> sb_itchy::block::BlockFieldBuilder {
+    global_list_menu(FUNCTION_STACK_LIST)
+}
+
+pub(crate) fn global_stack() -> sb_itchy::block::BlockFieldBuilder {
+    global_list_menu(GLOBAL_STACK_LIST)
+}
+
+fn read_global(slot: usize) -> Bib {
+    item_in_list(global_stack(), slot + 1)
+}
+
+fn write_global(slot: usize, value: Bib) -> StackBuilder {
+    replace_in_list(global_stack(), slot + 1, value)
+}
+
+pub(crate) fn substack(stacks: Vec<StackBuilder>) -> Option<Bib> {
+    if stacks.is_empty() {
+        None
+    } else {
+        Some(Bib::stack(concat(stacks)))
+    }
+}
+
+pub(crate) fn concat(stacks: Vec<StackBuilder>) -> StackBuilder {
+    stacks
+        .into_iter()
+        .fold(StackBuilder::new(), |stack, next| stack.next(next))
+}
+
+/// wasm treats any non zero i32 as true
+pub(crate) fn not_zero(value: Bib) -> Bib {
+    not(equals(value, 0))
+}
+
+/// scratch casts true to 1 and false to 0
+pub(crate) fn bool_to_int(value: Bib) -> Bib {
+    add(value, 0)
+}
+
+/// same as `DepictI32` add
+pub(crate) fn wrap_i32(value: Bib) -> Bib {
+    sub(modulo(add(value, I32_HALF), I32_MOD), I32_HALF)
+}
+
+pub(crate) fn to_u32(value: Bib) -> Bib {
+    modulo(value, I32_MOD)
+}
+
+/// the procedure of a local function or a wasm-sb-bindgen import
+pub(crate) fn callee_name(module: &Module, ctx: &GenCtx, func: FunctionId) -> Result<String> {
+    Ok(match &module.funcs.get(func).kind {
+        FunctionKind::Import(imported) => {
+            let import = module.imports.get(imported.import);
+            let entry = bindgen_import(import).ok_or_else(|| {
+                eyre!(
+                    "call to unsupported imported function: {}::{}",
+                    import.module,
+                    import.name
+                )
+            })?;
+            bindgen_func_name(entry.name)
+        }
+        _ => ctx.gen_func_name(func.index()),
+    })
+}
+
+/// binary search on `index` for the branch to take
+fn branch_tree(index: &Bib, offset: usize, mut branches: Vec<StackBuilder>) -> StackBuilder {
+    match branches.len() {
+        1 => branches.pop().unwrap(),
+        len => {
+            let upper = branches.split_off(len / 2);
+            if_else(
+                less_than(index, offset + len / 2),
+                branch_tree(index, offset, branches),
+                branch_tree(index, offset + len / 2, upper),
+            )
+        }
+    }
+}
+
+/// the static offset is added to the unsigned address
+fn effective_addr(addr: Bib, arg: &MemArg) -> Bib {
+    match arg.offset {
+        0 => to_u32(addr),
+        offset => add(to_u32(addr), offset as i64),
+    }
+}
+
+/// round half to even
+fn nearest(value: Bib) -> Bib {
+    sub(
+        round(&value),
+        bool_to_int(and(
+            equals(sub(round(&value), &value), 0.5),
+            equals(modulo(round(&value), 2), 1),
+        )),
+    )
 }
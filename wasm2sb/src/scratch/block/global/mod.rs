@@ -0,0 +1,115 @@
+// wasm globals, `__stack_pointer` of rustc and the others
+// each global is an item of __wasm_global_stack, i64 is two items (hi, lo)
+
+use std::collections::{BTreeMap, HashMap};
+
+use eyre::{eyre, Result};
+use sb_itchy::data::ListBuilder;
+use sb_sbity::value::{Number, ValueWithBool};
+use walrus::{ir::Value, ExportItem, GlobalId, GlobalKind, InitExpr, Module, ValType};
+
+use crate::{pre_name::GLOBAL_STACK_LIST, scratch::sb3::ProjectZip, GenCtx};
+
+use super::function_code::slot_width;
+
+/// `module::name` of an imported global, the key of its value in the config
+pub fn global_import_name(module: &Module, global: GlobalId) -> Option<String> {
+    match &module.globals.get(global).kind {
+        GlobalKind::Import(import) => {
+            let import = module.imports.get(*import);
+            Some(format!("{}::{}", import.module, import.name))
+        }
+        GlobalKind::Local(_) => None,
+    }
+}
+
+/// the value of the initializer, evaluated at conversion time
+///
+/// imported globals take their values from `imports`
+fn eval_global(
+    module: &Module,
+    global: GlobalId,
+    imports: &BTreeMap<String, f64>,
+) -> Result<Value> {
+    let ty = module.globals.get(global).ty;
+    match &module.globals.get(global).kind {
+        GlobalKind::Import(_) => {
+            let name = global_import_name(module, global).unwrap_or_default();
+            let value = imports
+                .get(&name)
+                .ok_or_else(|| eyre!("no value is given to the imported global {}", name))?;
+            Ok(match ty {
+                ValType::I32 => Value::I32(*value as i32),
+                ValType::I64 => Value::I64(*value as i64),
+                ValType::F32 => Value::F32(*value as f32),
+                ValType::F64 => Value::F64(*value),
+                _ => return Err(eyre!("global of {:?} is not supported", ty)),
+            })
+        }
+        GlobalKind::Local(InitExpr::Value(value)) => Ok(*value),
+        // only an imported global can be referred
+        GlobalKind::Local(InitExpr::Global(other)) => eval_global(module, *other, imports),
+        GlobalKind::Local(init) => Err(eyre!("global initializer is not supported: {:?}", init)),
+    }
+}
+
+fn global_items(value: Value) -> Result<Vec<ValueWithBool>> {
+    let number = |n: Number| ValueWithBool::Number(n);
+    Ok(match value {
+        Value::I32(v) => vec![number(Number::Int(v as i64))],
+        Value::I64(v) => vec![
+            number(Number::Int(v >> 32)),
+            number(Number::Int(v as u32 as i64)),
+        ],
+        Value::F32(v) => vec![number(Number::Float(v as f64))],
+        Value::F64(v) => vec![number(Number::Float(v))],
+        Value::V128(_) => return Err(eyre!("v128 global is not supported")),
+    })
+}
+
+/// `__wasm_global_stack` with the initial values, the items are recorded in `gen_ctx`
+pub fn global_generator(
+    ctx: &mut ProjectZip,
+    module: &Module,
+    gen_ctx: &mut GenCtx,
+    imports: &BTreeMap<String, f64>,
+) -> Result<()> {
+    let mut items = Vec::new();
+    let mut slots = HashMap::new();
+    for global in module.globals.iter() {
+        slots.insert(global.id().index(), items.len());
+        let value = eval_global(module, global.id(), imports)?;
+        let value = global_items(value)?;
+        debug_assert_eq!(value.len(), slot_width(global.ty));
+        items.extend(value);
+    }
+
+    // the host can not read them, the items are shown for debugging
+    for export in module.exports.iter() {
+        if let ExportItem::Global(global) = export.item {
+            log::info!(
+                "exported global {} is item {} of {}",
+                export.name,
+                slots[&global.index()] + 1,
+                GLOBAL_STACK_LIST
+            );
+        }
+    }
+
+    let unused = imports
+        .keys()
+        .filter(|name| {
+            !module
+                .globals
+                .iter()
+                .any(|global| global_import_name(module, global.id()).as_ref() == Some(*name))
+        })
+        .collect::<Vec<_>>();
+    if !unused.is_empty() {
+        log::warn!("values of unknown imported globals: {:?}", unused);
+    }
+
+    gen_ctx.global_slots = slots;
+    ctx.add_list_builder(GLOBAL_STACK_LIST.into(), ListBuilder::new(items));
+    Ok(())
+}
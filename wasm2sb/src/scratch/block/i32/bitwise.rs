@@ -0,0 +1,195 @@
+// scratch has no bitwise operators, so they are built from floor, mod, multiply and lookup lists
+// same as `depict_nums::i32::DepictI32`
+
+use sb_itchy::{block::BlockInputBuilder, data::ListBuilder, stack::StackBuilder};
+use sb_itchy_support::{block_generator_into::BlockGeneratorInto, blocks_wrapper::*, stack};
+
+use crate::{
+    pre_name::{I32_AND_LIST, I32_OR_LIST, I32_POPCNT_LIST, I32_XOR_LIST},
+    scratch::{
+        block::{
+            define_procedure,
+            function_code::{function_stack, local_stack, to_u32, wrap_i32},
+            memory::pow2_item,
+        },
+        sb3::ProjectZip,
+    },
+    GenCtx,
+};
+
+use super::{i32_func_name, nibble_table, popcnt_table};
+
+type Bib = BlockInputBuilder;
+
+// 2^32
+const I32_MOD: f64 = 4294967296.0;
+
+// registers in __wasm_local_stack
+const N: usize = 0;
+const U: usize = 1;
+const REGISTERS_LEN: usize = 2;
+
+/// the k-th 4 bits, counted from the lowest
+fn nibble(value: Bib, k: i32) -> Bib {
+    match k {
+        0 => modulo(value, 16),
+        k => modulo(math_op("floor", div(value, 16f64.powi(k))), 16),
+    }
+}
+
+pub fn i32_bitwise_generator(ctx: &mut ProjectZip, gen_ctx: &mut GenCtx) {
+    ctx.add_list_builder(
+        I32_AND_LIST.into(),
+        ListBuilder::new(nibble_table(|a, b| a & b)),
+    );
+    ctx.add_list_builder(
+        I32_OR_LIST.into(),
+        ListBuilder::new(nibble_table(|a, b| a | b)),
+    );
+    ctx.add_list_builder(
+        I32_XOR_LIST.into(),
+        ListBuilder::new(nibble_table(|a, b| a ^ b)),
+    );
+    ctx.add_list_builder(I32_POPCNT_LIST.into(), ListBuilder::new(popcnt_table()));
+
+    gen_ctx.reserve_function_stack(1);
+    let base = gen_ctx.alloc_local_stack(REGISTERS_LEN);
+
+    let lhs = || custom_block_var_string_number("lhs");
+    let rhs = || custom_block_var_string_number("rhs");
+    let value = || custom_block_var_string_number("value");
+    let reg = |k: usize| item_in_list(local_stack(), base + k + 1);
+    let set_reg = |k: usize, value: Bib| replace_in_list(local_stack(), base + k + 1, value);
+    let ret = |value: Bib| replace_in_list(function_stack(), 1, value);
+    let define = |ctx: &mut ProjectZip, name: &str, body: StackBuilder| {
+        define_procedure(ctx, &i32_func_name(name), &["lhs", "rhs"], body)
+    };
+    let define_unary = |ctx: &mut ProjectZip, name: &str, body: StackBuilder| {
+        define_procedure(ctx, &i32_func_name(name), &["value"], body)
+    };
+
+    // and, or, xor ============================================================
+
+    let nibble_op = |list: &str| -> Bib {
+        let sum = (0..8)
+            .map(|k| {
+                let index = add(add(mul(nibble(lhs(), k), 16), nibble(rhs(), k)), 1);
+                let bits = item_in_list(global_list_menu(list), index);
+                match k {
+                    0 => bits,
+                    k => mul(bits, 16f64.powi(k)),
+                }
+            })
+            .reduce(|a, b| add(a, b))
+            .unwrap();
+        wrap_i32(sum)
+    };
+
+    define(ctx, "and", ret(nibble_op(I32_AND_LIST)));
+    define(ctx, "or", ret(nibble_op(I32_OR_LIST)));
+    define(ctx, "xor", ret(nibble_op(I32_XOR_LIST)));
+
+    // shift ===================================================================
+
+    let amount = || modulo(rhs(), 32);
+
+    define(
+        ctx,
+        "shl",
+        ret(wrap_i32(mul(to_u32(lhs()), pow2_item(amount())))),
+    );
+    define(
+        ctx,
+        "shr_s",
+        ret(math_op("floor", div(lhs(), pow2_item(amount())))),
+    );
+    define(
+        ctx,
+        "shr_u",
+        ret(wrap_i32(math_op(
+            "floor",
+            div(to_u32(lhs()), pow2_item(amount())),
+        ))),
+    );
+
+    // the bits shifted out by `left` come back from the other side
+    let rotate = |left: Bib, right: Bib| -> Bib {
+        wrap_i32(add(
+            modulo(mul(to_u32(lhs()), pow2_item(left)), I32_MOD),
+            math_op("floor", div(to_u32(lhs()), pow2_item(right))),
+        ))
+    };
+    define(ctx, "rotl", ret(rotate(amount(), sub(32, amount()))));
+    define(ctx, "rotr", ret(rotate(sub(32, amount()), amount())));
+
+    // count ===================================================================
+
+    // binary search, shifting the upper bits out
+    define_unary(
+        ctx,
+        "clz",
+        if_else(
+            equals(value(), 0),
+            ret(32.to()),
+            stack![
+                set_reg(N, 0.to()),
+                set_reg(U, to_u32(value())),
+                concat_steps(|bits| {
+                    if_(
+                        less_than(reg(U), 2f64.powi(32 - bits)),
+                        stack![
+                            set_reg(N, add(reg(N), bits)),
+                            set_reg(U, mul(reg(U), 2f64.powi(bits)))
+                        ],
+                    )
+                }),
+                ret(reg(N))
+            ],
+        ),
+    );
+
+    // binary search, shifting the lower bits out
+    define_unary(
+        ctx,
+        "ctz",
+        if_else(
+            equals(value(), 0),
+            ret(32.to()),
+            stack![
+                set_reg(N, 0.to()),
+                set_reg(U, to_u32(value())),
+                concat_steps(|bits| {
+                    if_(
+                        equals(modulo(reg(U), 2f64.powi(bits)), 0),
+                        stack![
+                            set_reg(N, add(reg(N), bits)),
+                            set_reg(U, math_op("floor", div(reg(U), 2f64.powi(bits))))
+                        ],
+                    )
+                }),
+                ret(reg(N))
+            ],
+        ),
+    );
+
+    define_unary(
+        ctx,
+        "popcnt",
+        ret((0..8)
+            .map(|k| {
+                item_in_list(
+                    global_list_menu(I32_POPCNT_LIST),
+                    add(nibble(value(), k), 1),
+                )
+            })
+            .reduce(|a, b| add(a, b))
+            .unwrap()),
+    );
+}
+
+/// one step of the binary search for each of 16, 8, 4, 2 and 1 bits
+fn concat_steps(step: impl Fn(i32) -> StackBuilder) -> StackBuilder {
+    [16, 8, 4, 2, 1]
+        .into_iter()
+        .fold(StackBuilder::new(), |stack, bits| stack.next(step(bits)))
+}
@@ -0,0 +1,138 @@
+use sb_itchy::{block::BlockInputBuilder, stack::StackBuilder};
+use sb_itchy_support::{blocks_wrapper::*, stack};
+
+use crate::{
+    scratch::{
+        block::{
+            define_procedure,
+            function_code::{bool_to_int, function_stack, to_u32, wrap_i32},
+            trap::wasm_trap,
+        },
+        sb3::ProjectZip,
+    },
+    GenCtx,
+};
+
+use super::i32_func_name;
+
+type Bib = BlockInputBuilder;
+
+// 2^16
+const HALF_WORD: f64 = 65536.0;
+// 2^31
+const I32_HALF: f64 = 2147483648.0;
+
+pub fn i32_generator(ctx: &mut ProjectZip, gen_ctx: &mut GenCtx) {
+    gen_ctx.reserve_function_stack(1);
+
+    let lhs = || custom_block_var_string_number("lhs");
+    let rhs = || custom_block_var_string_number("rhs");
+    let ret = |value: Bib| replace_in_list(function_stack(), 1, value);
+    let define = |ctx: &mut ProjectZip, name: &str, body: StackBuilder| {
+        define_procedure(ctx, &i32_func_name(name), &["lhs", "rhs"], body)
+    };
+    // wasm traps on division by zero
+    let check_zero = || if_(equals(rhs(), 0), wasm_trap("integer divide by zero"));
+    // rounded toward zero
+    let trunc_div = |write: &dyn Fn(Bib) -> StackBuilder| {
+        if_else(
+            less_than(div(lhs(), rhs()), 0),
+            write(math_op("ceiling", div(lhs(), rhs()))),
+            write(math_op("floor", div(lhs(), rhs()))),
+        )
+    };
+
+    define(ctx, "add", ret(wrap_i32(add(lhs(), rhs()))));
+    define(ctx, "sub", ret(wrap_i32(sub(lhs(), rhs()))));
+
+    // lhs = a * 2^16 + b, rhs = c * 2^16 + d
+    // a * c overflows entirely, a * d and b * c keep only their lower 16 bits
+    let low = |value: Bib| modulo(value, HALF_WORD);
+    let high = |value: Bib| div(sub(&value, low(value.clone())), HALF_WORD);
+    define(
+        ctx,
+        "mul",
+        ret(wrap_i32(add(
+            mul(
+                add(
+                    low(mul(high(lhs()), low(rhs()))),
+                    low(mul(low(lhs()), high(rhs()))),
+                ),
+                HALF_WORD,
+            ),
+            mul(low(lhs()), low(rhs())),
+        ))),
+    );
+
+    define(
+        ctx,
+        "div_s",
+        stack![
+            check_zero(),
+            // i32::MIN / -1 overflows
+            if_(
+                and(equals(lhs(), -I32_HALF), equals(rhs(), -1)),
+                wasm_trap("integer overflow")
+            ),
+            trunc_div(&|value: Bib| ret(value))
+        ],
+    );
+    define(
+        ctx,
+        "div_u",
+        stack![
+            check_zero(),
+            ret(wrap_i32(math_op(
+                "floor",
+                div(to_u32(lhs()), to_u32(rhs()))
+            )))
+        ],
+    );
+    define(
+        ctx,
+        "rem_s",
+        stack![
+            check_zero(),
+            trunc_div(&|value: Bib| ret(sub(lhs(), mul(rhs(), value))))
+        ],
+    );
+    define(
+        ctx,
+        "rem_u",
+        stack![
+            check_zero(),
+            ret(wrap_i32(modulo(to_u32(lhs()), to_u32(rhs()))))
+        ],
+    );
+
+    define(ctx, "eq", ret(bool_to_int(equals(lhs(), rhs()))));
+    define(ctx, "ne", ret(bool_to_int(not(equals(lhs(), rhs())))));
+    define(ctx, "lt_s", ret(bool_to_int(less_than(lhs(), rhs()))));
+    define(ctx, "gt_s", ret(bool_to_int(greater_than(lhs(), rhs()))));
+    define(
+        ctx,
+        "le_s",
+        ret(bool_to_int(not(greater_than(lhs(), rhs())))),
+    );
+    define(ctx, "ge_s", ret(bool_to_int(not(less_than(lhs(), rhs())))));
+    define(
+        ctx,
+        "lt_u",
+        ret(bool_to_int(less_than(to_u32(lhs()), to_u32(rhs())))),
+    );
+    define(
+        ctx,
+        "gt_u",
+        ret(bool_to_int(greater_than(to_u32(lhs()), to_u32(rhs())))),
+    );
+    define(
+        ctx,
+        "le_u",
+        ret(bool_to_int(not(greater_than(to_u32(lhs()), to_u32(rhs()))))),
+    );
+    define(
+        ctx,
+        "ge_u",
+        ret(bool_to_int(not(less_than(to_u32(lhs()), to_u32(rhs()))))),
+    );
+}
@@ -0,0 +1,58 @@
+// exact 32-bit integer arithmetic, same as `depict_nums::i32::DepictI32`
+
+pub mod bitwise;
+pub mod generator;
+
+use sb_sbity::value::{Number, ValueWithBool};
+use walrus::ir::{BinaryOp, UnaryOp};
+
+use crate::pre_name::pre_func_name;
+
+pub fn i32_func_name(name: &str) -> String {
+    format!("{}i32_{name}", pre_func_name())
+}
+
+/// operators too large to be inlined
+pub fn i32_binop_func_name(op: &BinaryOp) -> Option<String> {
+    let name = match op {
+        BinaryOp::I32Mul => "mul",
+        BinaryOp::I32DivS => "div_s",
+        BinaryOp::I32DivU => "div_u",
+        BinaryOp::I32RemS => "rem_s",
+        BinaryOp::I32RemU => "rem_u",
+        BinaryOp::I32And => "and",
+        BinaryOp::I32Or => "or",
+        BinaryOp::I32Xor => "xor",
+        BinaryOp::I32Shl => "shl",
+        BinaryOp::I32ShrS => "shr_s",
+        BinaryOp::I32ShrU => "shr_u",
+        BinaryOp::I32Rotl => "rotl",
+        BinaryOp::I32Rotr => "rotr",
+        _ => return None,
+    };
+    Some(i32_func_name(name))
+}
+
+pub fn i32_unop_func_name(op: &UnaryOp) -> Option<String> {
+    let name = match op {
+        UnaryOp::I32Clz => "clz",
+        UnaryOp::I32Ctz => "ctz",
+        UnaryOp::I32Popcnt => "popcnt",
+        _ => return None,
+    };
+    Some(i32_func_name(name))
+}
+
+/// `op` of every pair of 4 bits, looked up by `a * 16 + b + 1`
+pub fn nibble_table(op: fn(usize, usize) -> usize) -> Vec<ValueWithBool> {
+    (0..256)
+        .map(|i| ValueWithBool::Number(Number::Int(op(i / 16, i % 16) as i64)))
+        .collect()
+}
+
+/// number of ones in 4 bits, looked up by `a + 1`
+pub fn popcnt_table() -> Vec<ValueWithBool> {
+    (0..16usize)
+        .map(|i| ValueWithBool::Number(Number::Int(i.count_ones() as i64)))
+        .collect()
+}
@@ -0,0 +1,502 @@
+use sb_itchy::{block::BlockInputBuilder, stack::StackBuilder};
+use sb_itchy_support::{blocks_wrapper::*, stack};
+
+use crate::{
+    scratch::{
+        block::{
+            define_procedure,
+            function_code::{bool_to_int, function_stack, local_stack, to_u32, wrap_i32},
+            i32::i32_func_name,
+            memory::pow2_item,
+            trap::wasm_trap,
+        },
+        sb3::ProjectZip,
+    },
+    GenCtx,
+};
+
+use super::i64_func_name;
+
+type Bib = BlockInputBuilder;
+
+// 2^16
+const HALF_WORD: f64 = 65536.0;
+// 2^31
+const I32_HALF: f64 = 2147483648.0;
+// 2^32
+const I32_MOD: f64 = 4294967296.0;
+
+// registers in __wasm_local_stack
+// 16 bit limbs of mul
+const A0: usize = 0;
+const B0: usize = 4;
+const R1: usize = 8;
+const LO: usize = 9;
+// divmod_u
+const QH: usize = 10;
+const QL: usize = 11;
+const RH: usize = 12;
+const RL: usize = 13;
+const I: usize = 14;
+const BIT: usize = 15;
+const OVER: usize = 16;
+// absolute values of div_s and rem_s
+const AH: usize = 17;
+const AL: usize = 18;
+const BH: usize = 19;
+const BL: usize = 20;
+// shift, rotate and the halves of bitwise operators
+const N: usize = 21;
+const H: usize = 22;
+const L: usize = 23;
+const REGISTERS_LEN: usize = 24;
+
+const BINARY_ARGS: [&str; 4] = ["lhs_hi", "lhs_lo", "rhs_hi", "rhs_lo"];
+const UNARY_ARGS: [&str; 2] = ["value_hi", "value_lo"];
+
+fn floor(value: Bib) -> Bib {
+    math_op("floor", value)
+}
+
+pub fn i64_generator(ctx: &mut ProjectZip, gen_ctx: &mut GenCtx) {
+    // hi and lo
+    gen_ctx.reserve_function_stack(2);
+    let base = gen_ctx.alloc_local_stack(REGISTERS_LEN);
+
+    let arg = |name: &str| custom_block_var_string_number(name);
+    let lhs_hi = || arg("lhs_hi");
+    let lhs_lo = || arg("lhs_lo");
+    let rhs_hi = || arg("rhs_hi");
+    let rhs_lo = || arg("rhs_lo");
+    let value_hi = || arg("value_hi");
+    let value_lo = || arg("value_lo");
+    let reg = |k: usize| item_in_list(local_stack(), base + k + 1);
+    let set_reg = |k: usize, value: Bib| replace_in_list(local_stack(), base + k + 1, value);
+    let result = |k: usize| item_in_list(function_stack(), k);
+    let ret = |k: usize, value: Bib| replace_in_list(function_stack(), k, value);
+    // lo may be read from the result of an i32 procedure, so it is written first
+    let ret_pair = |hi: Bib, lo: Bib| stack![ret(2, lo), ret(1, hi)];
+    let call =
+        |name: &str, args: Vec<(&str, Bib)>| call_custom_block(name, args.into_iter().collect());
+    let call_i32 = |name: &str, lhs: Bib, rhs: Bib| {
+        call(&i32_func_name(name), vec![("lhs", lhs), ("rhs", rhs)])
+    };
+    let define = |ctx: &mut ProjectZip, name: &str, body: StackBuilder| {
+        define_procedure(ctx, &i64_func_name(name), &BINARY_ARGS, body)
+    };
+    let define_unary = |ctx: &mut ProjectZip, name: &str, body: StackBuilder| {
+        define_procedure(ctx, &i64_func_name(name), &UNARY_ARGS, body)
+    };
+
+    // the overflow of lo is carried into hi
+    let carry = |hi: Bib, lo: Bib| -> StackBuilder {
+        ret_pair(
+            wrap_i32(add(hi, floor(div(lo.clone(), I32_MOD)))),
+            to_u32(lo),
+        )
+    };
+    let negate = |hi: Bib, lo: Bib| -> (Bib, Bib) {
+        (
+            wrap_i32(add(sub(0, hi), floor(div(sub(0, lo.clone()), I32_MOD)))),
+            to_u32(sub(0, lo)),
+        )
+    };
+
+    // add, sub, mul ===========================================================
+
+    define(
+        ctx,
+        "add",
+        carry(add(lhs_hi(), rhs_hi()), add(lhs_lo(), rhs_lo())),
+    );
+    define(
+        ctx,
+        "sub",
+        carry(sub(lhs_hi(), rhs_hi()), sub(lhs_lo(), rhs_lo())),
+    );
+
+    // 16 bit limbs keep every product under 2^53, the digits over 2^64 are dropped
+    let limbs = |from: usize, hi: Bib, lo: Bib| -> StackBuilder {
+        stack![
+            set_reg(from, modulo(&lo, HALF_WORD)),
+            set_reg(from + 1, floor(div(lo, HALF_WORD))),
+            set_reg(from + 2, modulo(to_u32(&hi), HALF_WORD)),
+            set_reg(from + 3, floor(div(to_u32(hi), HALF_WORD)))
+        ]
+    };
+    let limb_mul = |a: usize, b: usize| mul(reg(A0 + a), reg(B0 + b));
+    let r2 = add(add(limb_mul(2, 0), limb_mul(1, 1)), limb_mul(0, 2));
+    let r3 = add(
+        add(add(limb_mul(3, 0), limb_mul(2, 1)), limb_mul(1, 2)),
+        limb_mul(0, 3),
+    );
+    define(
+        ctx,
+        "mul",
+        stack![
+            limbs(A0, lhs_hi(), lhs_lo()),
+            limbs(B0, rhs_hi(), rhs_lo()),
+            set_reg(R1, add(limb_mul(1, 0), limb_mul(0, 1))),
+            set_reg(
+                LO,
+                add(limb_mul(0, 0), mul(modulo(reg(R1), HALF_WORD), HALF_WORD))
+            ),
+            carry(
+                add(
+                    add(floor(div(reg(R1), HALF_WORD)), r2),
+                    mul(modulo(r3, HALF_WORD), HALF_WORD)
+                ),
+                reg(LO)
+            )
+        ],
+    );
+
+    // div, rem ================================================================
+
+    // QH, QL, RH and RL are set as u32, the divisor is not zero
+    let divmod_u = i64_func_name("divmod_u");
+    let divisor_hi = || to_u32(rhs_hi());
+    define(
+        ctx,
+        "divmod_u",
+        if_else(
+            and(equals(lhs_hi(), 0), equals(rhs_hi(), 0)),
+            // u32 / u32 is exact in f64
+            stack![
+                set_reg(QH, 0.to()),
+                set_reg(QL, floor(div(lhs_lo(), rhs_lo()))),
+                set_reg(RH, 0.to()),
+                set_reg(RL, sub(lhs_lo(), mul(reg(QL), rhs_lo())))
+            ],
+            // long division, one bit at a time
+            stack![
+                set_reg(QH, 0.to()),
+                set_reg(QL, 0.to()),
+                set_reg(RH, 0.to()),
+                set_reg(RL, 0.to()),
+                set_reg(I, 63.to()),
+                repeat(
+                    64,
+                    stack![
+                        if_else(
+                            not(less_than(reg(I), 32)),
+                            set_reg(
+                                BIT,
+                                modulo(floor(div(to_u32(lhs_hi()), pow2_item(sub(reg(I), 32)))), 2)
+                            ),
+                            set_reg(BIT, modulo(floor(div(lhs_lo(), pow2_item(reg(I)))), 2)),
+                        ),
+                        // the remainder over 2^64 can always be subtracted
+                        set_reg(OVER, bool_to_int(not(less_than(reg(RH), I32_HALF)))),
+                        set_reg(
+                            RH,
+                            add(to_u32(mul(reg(RH), 2)), floor(div(reg(RL), I32_HALF)))
+                        ),
+                        set_reg(RL, add(to_u32(mul(reg(RL), 2)), reg(BIT))),
+                        set_reg(
+                            QH,
+                            add(to_u32(mul(reg(QH), 2)), floor(div(reg(QL), I32_HALF)))
+                        ),
+                        set_reg(QL, to_u32(mul(reg(QL), 2))),
+                        if_(
+                            or(
+                                or(equals(reg(OVER), 1), greater_than(reg(RH), divisor_hi())),
+                                and(
+                                    equals(reg(RH), divisor_hi()),
+                                    not(less_than(reg(RL), rhs_lo()))
+                                )
+                            ),
+                            stack![
+                                set_reg(
+                                    RH,
+                                    to_u32(add(
+                                        sub(reg(RH), divisor_hi()),
+                                        floor(div(sub(reg(RL), rhs_lo()), I32_MOD))
+                                    ))
+                                ),
+                                set_reg(RL, to_u32(sub(reg(RL), rhs_lo()))),
+                                set_reg(QL, add(reg(QL), 1))
+                            ]
+                        ),
+                        set_reg(I, sub(reg(I), 1))
+                    ]
+                )
+            ],
+        ),
+    );
+
+    let call_divmod_u = |lhs_hi: Bib, lhs_lo: Bib, rhs_hi: Bib, rhs_lo: Bib| {
+        call(
+            &divmod_u,
+            vec![
+                ("lhs_hi", lhs_hi),
+                ("lhs_lo", lhs_lo),
+                ("rhs_hi", rhs_hi),
+                ("rhs_lo", rhs_lo),
+            ],
+        )
+    };
+    // wasm traps on division by zero
+    let check_zero = || {
+        if_(
+            and(equals(rhs_hi(), 0), equals(rhs_lo(), 0)),
+            wasm_trap("integer divide by zero"),
+        )
+    };
+
+    define(
+        ctx,
+        "div_u",
+        stack![
+            check_zero(),
+            call_divmod_u(lhs_hi(), lhs_lo(), rhs_hi(), rhs_lo()),
+            ret_pair(wrap_i32(reg(QH)), reg(QL))
+        ],
+    );
+    define(
+        ctx,
+        "rem_u",
+        stack![
+            check_zero(),
+            call_divmod_u(lhs_hi(), lhs_lo(), rhs_hi(), rhs_lo()),
+            ret_pair(wrap_i32(reg(RH)), reg(RL))
+        ],
+    );
+
+    // divides the absolute values, then restores the sign
+    let abs = |hi_reg: usize, lo_reg: usize, side: &str| {
+        let hi = || arg(&format!("{side}_hi"));
+        let lo = || arg(&format!("{side}_lo"));
+        let (neg_hi, neg_lo) = negate(hi(), lo());
+        if_else(
+            less_than(hi(), 0),
+            stack![set_reg(hi_reg, neg_hi), set_reg(lo_reg, neg_lo)],
+            stack![set_reg(hi_reg, hi()), set_reg(lo_reg, lo())],
+        )
+    };
+    let signed_divmod = || {
+        stack![
+            check_zero(),
+            abs(AH, AL, "lhs"),
+            abs(BH, BL, "rhs"),
+            call_divmod_u(reg(AH), reg(AL), reg(BH), reg(BL))
+        ]
+    };
+    let signed_ret = |negative: Bib, hi: usize, lo: usize| {
+        let (neg_hi, neg_lo) = negate(reg(hi), reg(lo));
+        if_else(
+            negative,
+            ret_pair(neg_hi, neg_lo),
+            ret_pair(wrap_i32(reg(hi)), reg(lo)),
+        )
+    };
+
+    define(
+        ctx,
+        "div_s",
+        stack![
+            // i64::MIN / -1 overflows
+            if_(
+                and(
+                    and(equals(lhs_hi(), -I32_HALF), equals(lhs_lo(), 0)),
+                    and(equals(rhs_hi(), -1), equals(rhs_lo(), I32_MOD - 1.0))
+                ),
+                wasm_trap("integer overflow")
+            ),
+            signed_divmod(),
+            signed_ret(
+                not(equals(less_than(lhs_hi(), 0), less_than(rhs_hi(), 0))),
+                QH,
+                QL
+            )
+        ],
+    );
+    define(
+        ctx,
+        "rem_s",
+        stack![signed_divmod(), signed_ret(less_than(lhs_hi(), 0), RH, RL)],
+    );
+
+    // comparison ==============================================================
+
+    let lt = |signed: bool, a_hi: Bib, a_lo: Bib, b_hi: Bib, b_lo: Bib| -> Bib {
+        let hi_lt = match signed {
+            true => less_than(&a_hi, &b_hi),
+            false => less_than(to_u32(&a_hi), to_u32(&b_hi)),
+        };
+        or(hi_lt, and(equals(a_hi, b_hi), less_than(a_lo, b_lo)))
+    };
+    let eq = || and(equals(lhs_hi(), rhs_hi()), equals(lhs_lo(), rhs_lo()));
+
+    define(ctx, "eq", ret(1, bool_to_int(eq())));
+    define(ctx, "ne", ret(1, bool_to_int(not(eq()))));
+    for (suffix, signed) in [("s", true), ("u", false)] {
+        let lhs_lt = || lt(signed, lhs_hi(), lhs_lo(), rhs_hi(), rhs_lo());
+        let rhs_lt = || lt(signed, rhs_hi(), rhs_lo(), lhs_hi(), lhs_lo());
+        define(ctx, &format!("lt_{suffix}"), ret(1, bool_to_int(lhs_lt())));
+        define(ctx, &format!("gt_{suffix}"), ret(1, bool_to_int(rhs_lt())));
+        define(
+            ctx,
+            &format!("le_{suffix}"),
+            ret(1, bool_to_int(not(rhs_lt()))),
+        );
+        define(
+            ctx,
+            &format!("ge_{suffix}"),
+            ret(1, bool_to_int(not(lhs_lt()))),
+        );
+    }
+
+    // and, or, xor ============================================================
+
+    // each half by the i32 procedure
+    for name in ["and", "or", "xor"] {
+        define(
+            ctx,
+            name,
+            stack![
+                call_i32(name, lhs_hi(), rhs_hi()),
+                set_reg(H, result(1)),
+                call_i32(name, lhs_lo(), rhs_lo()),
+                ret_pair(reg(H), to_u32(result(1)))
+            ],
+        );
+    }
+
+    // shift ===================================================================
+
+    let amount = || modulo(rhs_lo(), 64);
+    let uhi = || to_u32(lhs_hi());
+    let n = || reg(N);
+
+    define(
+        ctx,
+        "shl",
+        stack![
+            set_reg(N, amount()),
+            if_else(
+                not(less_than(n(), 32)),
+                ret_pair(
+                    wrap_i32(to_u32(mul(lhs_lo(), pow2_item(sub(n(), 32))))),
+                    0.to()
+                ),
+                ret_pair(
+                    wrap_i32(add(
+                        to_u32(mul(uhi(), pow2_item(n()))),
+                        floor(div(lhs_lo(), pow2_item(sub(32, n()))))
+                    )),
+                    to_u32(mul(lhs_lo(), pow2_item(n())))
+                ),
+            )
+        ],
+    );
+
+    // the lower bits of hi shifted into lo
+    let shifted_lo = || {
+        add(
+            floor(div(lhs_lo(), pow2_item(n()))),
+            to_u32(mul(uhi(), pow2_item(sub(32, n())))),
+        )
+    };
+    define(
+        ctx,
+        "shr_s",
+        stack![
+            set_reg(N, amount()),
+            if_else(
+                not(less_than(n(), 32)),
+                ret_pair(
+                    floor(div(lhs_hi(), I32_MOD)),
+                    to_u32(floor(div(lhs_hi(), pow2_item(sub(n(), 32)))))
+                ),
+                ret_pair(floor(div(lhs_hi(), pow2_item(n()))), shifted_lo()),
+            )
+        ],
+    );
+    define(
+        ctx,
+        "shr_u",
+        stack![
+            set_reg(N, amount()),
+            if_else(
+                not(less_than(n(), 32)),
+                ret_pair(0.to(), floor(div(uhi(), pow2_item(sub(n(), 32))))),
+                ret_pair(wrap_i32(floor(div(uhi(), pow2_item(n())))), shifted_lo()),
+            )
+        ],
+    );
+
+    // rotating 32 bits or more swaps the halves first
+    let rotate_left = |amount: Bib| {
+        stack![
+            set_reg(N, amount),
+            if_else(
+                not(less_than(n(), 32)),
+                stack![
+                    set_reg(H, lhs_lo()),
+                    set_reg(L, uhi()),
+                    set_reg(N, sub(n(), 32))
+                ],
+                stack![set_reg(H, uhi()), set_reg(L, lhs_lo())],
+            ),
+            ret_pair(
+                wrap_i32(add(
+                    to_u32(mul(reg(H), pow2_item(n()))),
+                    floor(div(reg(L), pow2_item(sub(32, n()))))
+                )),
+                add(
+                    to_u32(mul(reg(L), pow2_item(n()))),
+                    floor(div(reg(H), pow2_item(sub(32, n()))))
+                )
+            )
+        ]
+    };
+    define(ctx, "rotl", rotate_left(amount()));
+    define(ctx, "rotr", rotate_left(modulo(sub(64, amount()), 64)));
+
+    // count ===================================================================
+
+    let call_i32_unary =
+        |name: &str, value: Bib| call(&i32_func_name(name), vec![("value", value)]);
+
+    define_unary(
+        ctx,
+        "clz",
+        if_else(
+            equals(value_hi(), 0),
+            stack![
+                call_i32_unary("clz", value_lo()),
+                ret_pair(0.to(), add(result(1), 32))
+            ],
+            stack![
+                call_i32_unary("clz", value_hi()),
+                ret_pair(0.to(), result(1))
+            ],
+        ),
+    );
+    define_unary(
+        ctx,
+        "ctz",
+        if_else(
+            equals(value_lo(), 0),
+            stack![
+                call_i32_unary("ctz", value_hi()),
+                ret_pair(0.to(), add(result(1), 32))
+            ],
+            stack![
+                call_i32_unary("ctz", value_lo()),
+                ret_pair(0.to(), result(1))
+            ],
+        ),
+    );
+    define_unary(
+        ctx,
+        "popcnt",
+        stack![
+            call_i32_unary("popcnt", value_hi()),
+            set_reg(H, result(1)),
+            call_i32_unary("popcnt", value_lo()),
+            ret_pair(0.to(), add(result(1), reg(H)))
+        ],
+    );
+}
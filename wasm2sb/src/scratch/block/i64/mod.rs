@@ -0,0 +1,58 @@
+// i64 as a pair of slots, same as `depict_nums::i64::DepictI64`
+// hi is the upper 32 bits as i32, lo is the lower 32 bits as u32
+
+pub mod generator;
+
+use walrus::{
+    ir::{BinaryOp, UnaryOp},
+    ValType,
+};
+
+use crate::pre_name::pre_func_name;
+
+pub fn i64_func_name(name: &str) -> String {
+    format!("{}i64_{name}", pre_func_name())
+}
+
+/// every i64 operator except eqz is a procedure, with its result type
+pub fn i64_binop_func(op: &BinaryOp) -> Option<(String, ValType)> {
+    let (name, ty) = match op {
+        BinaryOp::I64Add => ("add", ValType::I64),
+        BinaryOp::I64Sub => ("sub", ValType::I64),
+        BinaryOp::I64Mul => ("mul", ValType::I64),
+        BinaryOp::I64DivS => ("div_s", ValType::I64),
+        BinaryOp::I64DivU => ("div_u", ValType::I64),
+        BinaryOp::I64RemS => ("rem_s", ValType::I64),
+        BinaryOp::I64RemU => ("rem_u", ValType::I64),
+        BinaryOp::I64And => ("and", ValType::I64),
+        BinaryOp::I64Or => ("or", ValType::I64),
+        BinaryOp::I64Xor => ("xor", ValType::I64),
+        BinaryOp::I64Shl => ("shl", ValType::I64),
+        BinaryOp::I64ShrS => ("shr_s", ValType::I64),
+        BinaryOp::I64ShrU => ("shr_u", ValType::I64),
+        BinaryOp::I64Rotl => ("rotl", ValType::I64),
+        BinaryOp::I64Rotr => ("rotr", ValType::I64),
+        BinaryOp::I64Eq => ("eq", ValType::I32),
+        BinaryOp::I64Ne => ("ne", ValType::I32),
+        BinaryOp::I64LtS => ("lt_s", ValType::I32),
+        BinaryOp::I64LtU => ("lt_u", ValType::I32),
+        BinaryOp::I64GtS => ("gt_s", ValType::I32),
+        BinaryOp::I64GtU => ("gt_u", ValType::I32),
+        BinaryOp::I64LeS => ("le_s", ValType::I32),
+        BinaryOp::I64LeU => ("le_u", ValType::I32),
+        BinaryOp::I64GeS => ("ge_s", ValType::I32),
+        BinaryOp::I64GeU => ("ge_u", ValType::I32),
+        _ => return None,
+    };
+    Some((i64_func_name(name), ty))
+}
+
+pub fn i64_unop_func_name(op: &UnaryOp) -> Option<String> {
+    let name = match op {
+        UnaryOp::I64Clz => "clz",
+        UnaryOp::I64Ctz => "ctz",
+        UnaryOp::I64Popcnt => "popcnt",
+        _ => return None,
+    };
+    Some(i64_func_name(name))
+}
@@ -0,0 +1,518 @@
+use eyre::Result;
+use sb_itchy::{block::BlockInputBuilder, data::ListBuilder, stack::StackBuilder};
+use sb_itchy_support::{block_generator_into::BlockGeneratorInto, blocks_wrapper::*, stack};
+use walrus::Module;
+
+use crate::{
+    pre_name::{MEMORY_LIST, POW2_LIST},
+    scratch::{
+        block::{
+            define_procedure,
+            function_code::{bool_to_int, concat, function_stack, local_stack},
+            trap::wasm_trap,
+        },
+        sb3::ProjectZip,
+    },
+    GenCtx,
+};
+
+use super::{
+    bits_func_name, memory_func_name, memory_init_data, memory_max_pages, pow2, pow2_init_data,
+    pow2_item, PAGE_SIZE, SCRATCH_LIST_LIMIT,
+};
+
+type Bib = BlockInputBuilder;
+
+// registers in __wasm_local_stack
+const SIGN: usize = 0;
+const X: usize = 1;
+const E: usize = 2;
+const M: usize = 3;
+const I: usize = 4;
+const REGISTERS_LEN: usize = 5;
+
+/// `memory_pages` overrides the initial size of the linear memory,
+/// with `unlimited_lists` memory.grow is limited only by the maximum of the module
+///
+/// with `bounds_checks` an access out of the memory traps,
+/// otherwise scratch reads it as 0 and ignores the write
+pub fn memory_generator(
+    ctx: &mut ProjectZip,
+    module: &Module,
+    gen_ctx: &mut GenCtx,
+    memory_pages: Option<usize>,
+    unlimited_lists: bool,
+    bounds_checks: bool,
+) -> Result<()> {
+    ctx.add_list_builder(
+        MEMORY_LIST.into(),
+        ListBuilder::new(memory_init_data(module, memory_pages, unlimited_lists)?),
+    );
+    ctx.add_list_builder(POW2_LIST.into(), ListBuilder::new(pow2_init_data()));
+
+    // f64 bits are returned as (lo, hi)
+    gen_ctx.reserve_function_stack(2);
+    let base = gen_ctx.alloc_local_stack(REGISTERS_LEN);
+
+    let memory = || global_list_menu(MEMORY_LIST);
+    let reg = |k: usize| item_in_list(local_stack(), base + k + 1);
+    let set_reg = |k: usize, value: Bib| replace_in_list(local_stack(), base + k + 1, value);
+    let ret = |k: usize, value: Bib| replace_in_list(function_stack(), k, value);
+    let arg = |name: &str| custom_block_var_string_number(name);
+    let call =
+        |name: &str, args: Vec<(&str, Bib)>| call_custom_block(name, args.into_iter().collect());
+    // `len` bytes from `addr` must be in the memory
+    let checked = |addr: Bib, len: Bib, body: StackBuilder| -> StackBuilder {
+        match bounds_checks {
+            true => stack![
+                if_(
+                    greater_than(add(addr, len), length_of_list(memory())),
+                    wasm_trap("out of bounds memory access")
+                ),
+                body
+            ],
+            false => body,
+        }
+    };
+
+    // load ====================================================================
+
+    let byte = |k: usize| item_in_list(memory(), add(arg("addr"), k + 1));
+    let unsigned = |from: usize, len: usize| -> Bib {
+        (from..from + len)
+            .map(|k| match k - from {
+                0 => byte(k),
+                shift => mul(byte(k), 1usize << (8 * shift)),
+            })
+            .reduce(|a, b| add(a, b))
+            .unwrap()
+    };
+    let signed = |value: Bib, bits: i32| -> Bib {
+        sub(
+            modulo(add(value, pow2(bits - 1)), pow2(bits)),
+            pow2(bits - 1),
+        )
+    };
+
+    for (len, bits) in [(1, 8), (2, 16), (4, 32)] {
+        define_procedure(
+            ctx,
+            &memory_func_name(&format!("load{bits}_u")),
+            &["addr"],
+            checked(arg("addr"), len.to(), ret(1, unsigned(0, len))),
+        );
+        define_procedure(
+            ctx,
+            &memory_func_name(&format!("load{bits}_s")),
+            &["addr"],
+            checked(
+                arg("addr"),
+                len.to(),
+                ret(1, signed(unsigned(0, len), bits)),
+            ),
+        );
+    }
+
+    // i64 is returned as (hi, lo)
+    define_procedure(
+        ctx,
+        &memory_func_name("load64"),
+        &["addr"],
+        checked(
+            arg("addr"),
+            8.to(),
+            stack![ret(1, signed(unsigned(4, 4), 32)), ret(2, unsigned(0, 4))],
+        ),
+    );
+
+    define_procedure(
+        ctx,
+        &memory_func_name("load_f32"),
+        &["addr"],
+        checked(
+            arg("addr"),
+            4.to(),
+            call(
+                &bits_func_name("f32_from_bits"),
+                vec![("bits", unsigned(0, 4))],
+            ),
+        ),
+    );
+
+    define_procedure(
+        ctx,
+        &memory_func_name("load_f64"),
+        &["addr"],
+        checked(
+            arg("addr"),
+            8.to(),
+            call(
+                &bits_func_name("f64_from_bits"),
+                vec![("lo", unsigned(0, 4)), ("hi", unsigned(4, 4))],
+            ),
+        ),
+    );
+
+    // store ===================================================================
+
+    let store_bytes = |len: usize| -> StackBuilder {
+        concat(
+            (0..len)
+                .map(|k| {
+                    let value = match k {
+                        0 => arg("value"),
+                        k => math_op("floor", div(arg("value"), pow2(8 * k as i32))),
+                    };
+                    replace_in_list(memory(), add(arg("addr"), k + 1), modulo(value, 256))
+                })
+                .collect(),
+        )
+    };
+
+    for (len, bits) in [(1, 8), (2, 16), (4, 32)] {
+        define_procedure(
+            ctx,
+            &memory_func_name(&format!("store{bits}")),
+            &["addr", "value"],
+            checked(arg("addr"), len.to(), store_bytes(len)),
+        );
+    }
+
+    let store32 = memory_func_name("store32");
+
+    define_procedure(
+        ctx,
+        &memory_func_name("store64"),
+        &["addr", "hi", "lo"],
+        // nothing is written unless both halves are in the memory
+        checked(
+            arg("addr"),
+            8.to(),
+            stack![
+                call(&store32, vec![("addr", arg("addr")), ("value", arg("lo"))]),
+                call(
+                    &store32,
+                    vec![("addr", add(arg("addr"), 4)), ("value", arg("hi"))]
+                )
+            ],
+        ),
+    );
+
+    define_procedure(
+        ctx,
+        &memory_func_name("store_f32"),
+        &["addr", "value"],
+        checked(
+            arg("addr"),
+            4.to(),
+            stack![
+                call(
+                    &bits_func_name("f32_to_bits"),
+                    vec![("value", arg("value"))]
+                ),
+                call(
+                    &store32,
+                    vec![
+                        ("addr", arg("addr")),
+                        ("value", item_in_list(function_stack(), 1))
+                    ]
+                )
+            ],
+        ),
+    );
+
+    define_procedure(
+        ctx,
+        &memory_func_name("store_f64"),
+        &["addr", "value"],
+        checked(
+            arg("addr"),
+            8.to(),
+            stack![
+                call(
+                    &bits_func_name("f64_to_bits"),
+                    vec![("value", arg("value"))]
+                ),
+                call(
+                    &store32,
+                    vec![
+                        ("addr", arg("addr")),
+                        ("value", item_in_list(function_stack(), 1))
+                    ]
+                ),
+                call(
+                    &store32,
+                    vec![
+                        ("addr", add(arg("addr"), 4)),
+                        ("value", item_in_list(function_stack(), 2))
+                    ]
+                )
+            ],
+        ),
+    );
+
+    // size ====================================================================
+
+    let pages = || div(length_of_list(memory()), PAGE_SIZE);
+
+    // `add to list` stops silently at the limit of scratch lists, it fails like the maximum
+    let over_maximum = greater_than(add(pages(), arg("pages")), memory_max_pages(module));
+    let over_limit = match unlimited_lists {
+        true => over_maximum,
+        false => or(
+            over_maximum,
+            greater_than(
+                mul(add(pages(), arg("pages")), PAGE_SIZE),
+                SCRATCH_LIST_LIMIT,
+            ),
+        ),
+    };
+    define_procedure(
+        ctx,
+        &memory_func_name("grow"),
+        &["pages"],
+        if_else(
+            over_limit,
+            ret(1, (-1).to()),
+            stack![
+                ret(1, pages()),
+                repeat(mul(arg("pages"), PAGE_SIZE), add_to_list(memory(), 0))
+            ],
+        ),
+    );
+
+    // bulk ====================================================================
+
+    define_procedure(
+        ctx,
+        &memory_func_name("fill"),
+        &["dst", "value", "len"],
+        checked(
+            arg("dst"),
+            arg("len"),
+            stack![
+                set_reg(I, 0.to()),
+                repeat(
+                    arg("len"),
+                    stack![
+                        replace_in_list(
+                            memory(),
+                            add(add(arg("dst"), reg(I)), 1),
+                            modulo(arg("value"), 256)
+                        ),
+                        set_reg(I, add(reg(I), 1))
+                    ]
+                )
+            ],
+        ),
+    );
+
+    define_procedure(
+        ctx,
+        &memory_func_name("copy"),
+        &["dst", "src", "len"],
+        checked(
+            arg("dst"),
+            arg("len"),
+            checked(
+                arg("src"),
+                arg("len"),
+                if_else(
+                    less_than(arg("dst"), arg("src")),
+                    stack![
+                        set_reg(I, 1.to()),
+                        repeat(
+                            arg("len"),
+                            stack![
+                                replace_in_list(
+                                    memory(),
+                                    add(arg("dst"), reg(I)),
+                                    item_in_list(memory(), add(arg("src"), reg(I)))
+                                ),
+                                set_reg(I, add(reg(I), 1))
+                            ]
+                        )
+                    ],
+                    // copy backwards, the ranges may overlap
+                    stack![
+                        set_reg(I, arg("len")),
+                        repeat(
+                            arg("len"),
+                            stack![
+                                replace_in_list(
+                                    memory(),
+                                    add(arg("dst"), reg(I)),
+                                    item_in_list(memory(), add(arg("src"), reg(I)))
+                                ),
+                                set_reg(I, sub(reg(I), 1))
+                            ]
+                        )
+                    ],
+                ),
+            ),
+        ),
+    );
+
+    // float bits ==============================================================
+
+    // sign and magnitude of `value`, -0 is negative
+    let split_sign = || {
+        stack![
+            set_reg(
+                SIGN,
+                bool_to_int(or(
+                    less_than(arg("value"), 0),
+                    less_than(div(1, arg("value")), 0)
+                ))
+            ),
+            set_reg(X, math_op("abs", arg("value")))
+        ]
+    };
+    // floor(log2(X)), corrected with the exact powers of two
+    let exponent = || {
+        stack![
+            set_reg(
+                E,
+                math_op("floor", div(math_op("ln", reg(X)), std::f64::consts::LN_2))
+            ),
+            if_(
+                greater_than(pow2_item(reg(E)), reg(X)),
+                set_reg(E, sub(reg(E), 1))
+            ),
+            if_(
+                not(greater_than(pow2_item(add(reg(E), 1)), reg(X))),
+                set_reg(E, add(reg(E), 1))
+            )
+        ]
+    };
+    let apply_sign = |negative: Bib| if_else(negative, ret(1, mul(reg(X), -1)), ret(1, reg(X)));
+
+    define_procedure(
+        ctx,
+        &bits_func_name("f64_to_bits"),
+        &["value"],
+        stack![
+            split_sign(),
+            if_else(
+                equals(reg(X), 0),
+                stack![set_reg(E, 0.to()), set_reg(M, 0.to())],
+                if_else(
+                    not(less_than(reg(X), "Infinity")),
+                    stack![set_reg(E, 2047.to()), set_reg(M, 0.to())],
+                    if_else(
+                        less_than(reg(X), pow2(-1022)),
+                        stack![set_reg(E, 0.to()), set_reg(M, div(reg(X), pow2(-1074)))],
+                        stack![
+                            exponent(),
+                            set_reg(M, mul(sub(div(reg(X), pow2_item(reg(E))), 1), pow2(52))),
+                            set_reg(E, add(reg(E), 1023))
+                        ],
+                    ),
+                ),
+            ),
+            ret(1, modulo(reg(M), pow2(32))),
+            ret(
+                2,
+                add(
+                    add(mul(reg(SIGN), pow2(31)), mul(reg(E), pow2(20))),
+                    math_op("floor", div(reg(M), pow2(32)))
+                )
+            )
+        ],
+    );
+
+    define_procedure(
+        ctx,
+        &bits_func_name("f64_from_bits"),
+        &["lo", "hi"],
+        stack![
+            set_reg(E, modulo(math_op("floor", div(arg("hi"), pow2(20))), 2048)),
+            set_reg(
+                M,
+                add(
+                    mul(modulo(arg("hi"), pow2(20)), pow2(32)),
+                    modulo(arg("lo"), pow2(32))
+                )
+            ),
+            if_else(
+                equals(reg(E), 2047),
+                if_else(
+                    equals(reg(M), 0),
+                    set_reg(X, "Infinity".to()),
+                    set_reg(X, "NaN".to()),
+                ),
+                if_else(
+                    equals(reg(E), 0),
+                    set_reg(X, mul(reg(M), pow2(-1074))),
+                    set_reg(X, mul(add(reg(M), pow2(52)), pow2_item(sub(reg(E), 1075)))),
+                ),
+            ),
+            apply_sign(not(less_than(modulo(arg("hi"), pow2(32)), pow2(31))))
+        ],
+    );
+
+    define_procedure(
+        ctx,
+        &bits_func_name("f32_to_bits"),
+        &["value"],
+        stack![
+            split_sign(),
+            if_else(
+                equals(reg(X), 0),
+                stack![set_reg(E, 0.to()), set_reg(M, 0.to())],
+                if_else(
+                    not(less_than(reg(X), pow2(128))),
+                    stack![set_reg(E, 255.to()), set_reg(M, 0.to())],
+                    if_else(
+                        less_than(reg(X), pow2(-126)),
+                        stack![
+                            set_reg(E, 0.to()),
+                            set_reg(M, round(div(reg(X), pow2(-149))))
+                        ],
+                        stack![
+                            exponent(),
+                            // a carry out of the mantissa moves into the exponent
+                            set_reg(
+                                M,
+                                round(mul(sub(div(reg(X), pow2_item(reg(E))), 1), pow2(23)))
+                            ),
+                            set_reg(E, add(reg(E), 127))
+                        ],
+                    ),
+                ),
+            ),
+            ret(
+                1,
+                add(add(mul(reg(SIGN), pow2(31)), mul(reg(E), pow2(23))), reg(M))
+            )
+        ],
+    );
+
+    define_procedure(
+        ctx,
+        &bits_func_name("f32_from_bits"),
+        &["bits"],
+        stack![
+            set_reg(M, modulo(arg("bits"), pow2(32))),
+            set_reg(E, modulo(math_op("floor", div(reg(M), pow2(23))), 256)),
+            set_reg(SIGN, not(less_than(reg(M), pow2(31)))),
+            set_reg(M, modulo(reg(M), pow2(23))),
+            if_else(
+                equals(reg(E), 255),
+                if_else(
+                    equals(reg(M), 0),
+                    set_reg(X, "Infinity".to()),
+                    set_reg(X, "NaN".to()),
+                ),
+                if_else(
+                    equals(reg(E), 0),
+                    set_reg(X, mul(reg(M), pow2(-149))),
+                    set_reg(X, mul(add(reg(M), pow2(23)), pow2_item(sub(reg(E), 150)))),
+                ),
+            ),
+            apply_sign(equals(reg(SIGN), "true"))
+        ],
+    );
+
+    Ok(())
+}
@@ -0,0 +1,180 @@
+// linear memory emulated by a scratch list, one byte per item
+
+pub mod generator;
+
+use eyre::{eyre, Result};
+use sb_itchy::block::BlockInputBuilder;
+use sb_itchy_support::{block_generator_into::BlockGeneratorInto, blocks_wrapper::*};
+use sb_sbity::value::{Number, ValueWithBool};
+use walrus::{
+    ir::{ExtendedLoad, LoadKind, StoreKind, Value},
+    ActiveDataLocation, DataKind, GlobalKind, InitExpr, Module,
+};
+
+use crate::pre_name::{pre_func_name, POW2_LIST};
+
+pub const PAGE_SIZE: usize = 65536;
+
+/// scratch refuses to add items to a list longer than this
+pub const SCRATCH_LIST_LIMIT: usize = 200000;
+
+/// `__wasm_pow2` holds 2^-1074 ..= 2^1023, then Infinity
+pub const POW2_MIN: i32 = -1074;
+pub const POW2_MAX: i32 = 1023;
+
+pub fn memory_func_name(name: &str) -> String {
+    format!("{}memory_{name}", pre_func_name())
+}
+
+/// conversions between floats and their bit patterns
+pub fn bits_func_name(name: &str) -> String {
+    format!("{}{name}", pre_func_name())
+}
+
+pub fn load_func_name(kind: &LoadKind) -> Result<String> {
+    let signed = |kind: &ExtendedLoad| match kind {
+        ExtendedLoad::SignExtend => "s",
+        _ => "u",
+    };
+    let name = match kind {
+        LoadKind::I32_8 { kind } | LoadKind::I64_8 { kind } => format!("load8_{}", signed(kind)),
+        LoadKind::I32_16 { kind } | LoadKind::I64_16 { kind } => {
+            format!("load16_{}", signed(kind))
+        }
+        LoadKind::I32 { .. } => "load32_s".into(),
+        LoadKind::I64_32 { kind } => format!("load32_{}", signed(kind)),
+        LoadKind::I64 { .. } => "load64".into(),
+        LoadKind::F32 => "load_f32".into(),
+        LoadKind::F64 => "load_f64".into(),
+        LoadKind::V128 => return Err(eyre!("v128 is not supported")),
+    };
+    Ok(memory_func_name(&name))
+}
+
+pub fn store_func_name(kind: &StoreKind) -> Result<String> {
+    let name = match kind {
+        StoreKind::I32_8 { .. } | StoreKind::I64_8 { .. } => "store8",
+        StoreKind::I32_16 { .. } | StoreKind::I64_16 { .. } => "store16",
+        StoreKind::I32 { .. } | StoreKind::I64_32 { .. } => "store32",
+        StoreKind::I64 { .. } => "store64",
+        StoreKind::F32 => "store_f32",
+        StoreKind::F64 => "store_f64",
+        StoreKind::V128 => return Err(eyre!("v128 is not supported")),
+    };
+    Ok(memory_func_name(name))
+}
+
+/// exact power of two, built from bits so that subnormals do not underflow
+pub fn pow2(e: i32) -> f64 {
+    if e < -1022 {
+        f64::from_bits(1u64 << (e - POW2_MIN))
+    } else {
+        f64::from_bits(((e + 1023) as u64) << 52)
+    }
+}
+
+/// 2^n looked up from `__wasm_pow2`
+pub fn pow2_item<T: BlockGeneratorInto<BlockInputBuilder>>(n: T) -> BlockInputBuilder {
+    item_in_list(global_list_menu(POW2_LIST), add(n, 1 - POW2_MIN))
+}
+
+pub fn pow2_init_data() -> Vec<ValueWithBool> {
+    let mut data = (POW2_MIN..=POW2_MAX)
+        .map(|e| ValueWithBool::Number(Number::Float(pow2(e))))
+        .collect::<Vec<_>>();
+    data.push(ValueWithBool::Text("Infinity".into()));
+    data
+}
+
+/// bytes of the first memory with every active data segment written
+///
+/// `pages` overrides the initial size, it can not be smaller than the module's
+pub fn memory_init_data(
+    module: &Module,
+    pages: Option<usize>,
+    unlimited_lists: bool,
+) -> Result<Vec<ValueWithBool>> {
+    let memory = match module.memories.iter().next() {
+        Some(memory) => memory,
+        None => return Ok(vec![]),
+    };
+
+    let initial = memory.initial as usize;
+    let pages = match pages {
+        Some(pages) if pages < initial => {
+            return Err(eyre!(
+                "memory pages {} is less than the initial pages {} of the module",
+                pages,
+                initial
+            ))
+        }
+        Some(pages)
+            if memory
+                .maximum
+                .is_some_and(|maximum| pages > maximum as usize) =>
+        {
+            return Err(eyre!(
+                "memory pages {} is more than the maximum pages {:?} of the module",
+                pages,
+                memory.maximum
+            ))
+        }
+        Some(pages) => pages,
+        None => initial,
+    };
+    let len = pages * PAGE_SIZE;
+    // a longer list still loads, only `add to list` stops at the limit
+    if len > SCRATCH_LIST_LIMIT && !unlimited_lists {
+        log::warn!(
+            "memory is {} bytes, scratch lists grow only to {} items so memory.grow will fail, \
+             lower the initial memory with `-C link-arg=--initial-memory=<bytes>`",
+            len,
+            SCRATCH_LIST_LIMIT
+        );
+    }
+
+    let mut bytes = vec![0u8; len];
+    for data in module.data.iter() {
+        let offset = match &data.kind {
+            DataKind::Active(active) => match active.location {
+                ActiveDataLocation::Absolute(offset) => offset as usize,
+                // the offset is an u32 address
+                ActiveDataLocation::Relative(global) => match &module.globals.get(global).kind {
+                    GlobalKind::Local(InitExpr::Value(Value::I32(offset))) => {
+                        *offset as u32 as usize
+                    }
+                    _ => return Err(eyre!("data segment offset must be a constant")),
+                },
+            },
+            DataKind::Passive => continue,
+        };
+        let end = offset
+            .checked_add(data.value.len())
+            .filter(|end| *end <= len)
+            .ok_or_else(|| {
+                eyre!(
+                    "data segment at {} of {} bytes is out of memory ({} bytes)",
+                    offset,
+                    data.value.len(),
+                    len
+                )
+            })?;
+        bytes[offset..end].copy_from_slice(&data.value);
+    }
+
+    Ok(bytes
+        .into_iter()
+        .map(|b| ValueWithBool::Number(Number::Int(b as i64)))
+        .collect())
+}
+
+/// the first memory's limit in pages
+pub fn memory_max_pages(module: &Module) -> usize {
+    module
+        .memories
+        .iter()
+        .next()
+        .and_then(|memory| memory.maximum)
+        .map(|maximum| maximum as usize)
+        .unwrap_or(PAGE_SIZE)
+}
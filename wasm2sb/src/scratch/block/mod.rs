@@ -1,5 +1,31 @@
 pub mod function_code;
 pub mod reformat;
 pub use reformat::*;
+pub mod bindgen;
 pub mod buddy_block;
+pub mod f32;
+pub mod global;
+pub mod i32;
+pub mod i64;
+pub mod memory;
+pub mod table;
 pub mod to_utf8;
+pub mod trap;
+
+use sb_itchy::{
+    blocks::define_custom_block, custom_block::CustomBlockInputType, stack::StackBuilder,
+};
+
+use super::sb3::ProjectZip;
+
+/// define a warp custom block with number inputs and add its definition script
+pub fn define_procedure(ctx: &mut ProjectZip, name: &str, args: &[&str], body: StackBuilder) {
+    let mut inputs = vec![CustomBlockInputType::Text(name.into())];
+    inputs.extend(
+        args.iter()
+            .map(|arg| CustomBlockInputType::StringOrNumber(arg.to_string())),
+    );
+    ctx.define_custom_block(inputs, true);
+
+    ctx.add_stack_builder(define_custom_block(name).next(body));
+}
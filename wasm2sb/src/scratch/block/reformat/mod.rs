@@ -1 +1,2 @@
+pub mod names;
 pub mod procedures_definition;
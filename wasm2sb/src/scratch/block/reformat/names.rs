@@ -0,0 +1,273 @@
+// readable labels of the custom blocks, from the name section and the rust symbols
+
+use std::collections::{HashMap, HashSet};
+
+use walrus::{ExportItem, FunctionId, FunctionKind, Module, ValType};
+
+use crate::pre_name::{pre_func_name, pre_unicode};
+
+/// `%s` `%b` `%n` in a label would become inputs, `%` is replaced with the fullwidth one
+pub fn escape_proccode(label: &str) -> String {
+    label
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .replace('%', "％")
+}
+
+/// `mycrate::parse` from `_ZN7mycrate5parse17h0123456789abcdefE`, the others as is
+pub fn demangle(name: &str) -> String {
+    match rustc_demangle::try_demangle(name) {
+        Ok(demangled) => format!("{:#}", demangled),
+        Err(_) => name.to_string(),
+    }
+}
+
+/// the runtime procedures and data are named with these
+fn is_reserved(label: &str) -> bool {
+    ["__wasm_", pre_func_name(), pre_unicode()]
+        .iter()
+        .any(|prefix| label.starts_with(prefix))
+}
+
+/// labels of the local functions, the name section first then the export name
+///
+/// the same label is given to two functions, `#<index>` is added to both,
+/// and to a label starting like the runtime procedures
+pub fn readable_func_names(module: &Module) -> HashMap<usize, String> {
+    let mut exports = HashMap::new();
+    for export in module.exports.iter() {
+        if let ExportItem::Function(func) = export.item {
+            exports.entry(func.index()).or_insert(export.name.as_str());
+        }
+    }
+
+    let names = module
+        .funcs
+        .iter()
+        .filter(|function| matches!(function.kind, FunctionKind::Local(_)))
+        .filter_map(|function| {
+            let index = function.id().index();
+            let name = function
+                .name
+                .as_deref()
+                .or_else(|| exports.get(&index).copied())?;
+            let label = escape_proccode(&demangle(name));
+            (!label.is_empty()).then_some((index, label))
+        })
+        .collect::<Vec<_>>();
+
+    let mut count = HashMap::new();
+    for (_, label) in &names {
+        *count.entry(label.clone()).or_insert(0) += 1;
+    }
+    let unique = |label: &String| count[label] == 1 && !is_reserved(label);
+
+    // the unique labels are kept, a suffixed one may still meet another label
+    let mut used = names
+        .iter()
+        .filter(|(_, label)| unique(label))
+        .map(|(_, label)| label.clone())
+        .collect::<HashSet<_>>();
+    names
+        .into_iter()
+        .map(|(index, label)| {
+            if unique(&label) {
+                return (index, label);
+            }
+            let mut suffixed = format!("{label} #{index:x}");
+            let mut n = 0;
+            while !used.insert(suffixed.clone()) {
+                n += 1;
+                suffixed = format!("{label} #{index:x}.{n}");
+            }
+            (index, suffixed)
+        })
+        .collect()
+}
+
+/// `a: i32` for the parameters with a local name, i64 is `a: i64_hi` and `a: i64_lo`
+///
+/// `None` unless every parameter is named and the names are unique
+pub fn readable_param_names(module: &Module, id: FunctionId) -> Option<Vec<String>> {
+    let FunctionKind::Local(local) = &module.funcs.get(id).kind else {
+        return None;
+    };
+
+    let mut names = Vec::new();
+    for arg in local.args.iter() {
+        let local = module.locals.get(*arg);
+        let name = escape_proccode(local.name.as_deref()?);
+        if name.is_empty() {
+            return None;
+        }
+        match local.ty() {
+            ValType::I32 => names.push(format!("{name}: i32")),
+            ValType::F32 => names.push(format!("{name}: f32")),
+            ValType::F64 => names.push(format!("{name}: f64")),
+            ValType::I64 => {
+                names.push(format!("{name}: i64_hi"));
+                names.push(format!("{name}: i64_lo"));
+            }
+            _ => return None,
+        }
+    }
+
+    let mut sorted = names.clone();
+    sorted.sort();
+    sorted.dedup();
+    (sorted.len() == names.len()).then_some(names)
+}
+
+/// labels of the functions and their parameters for `GenCtx`
+pub fn readable_names(module: &Module) -> (HashMap<usize, String>, HashMap<usize, Vec<String>>) {
+    let func_names = readable_func_names(module);
+    let param_names = module
+        .funcs
+        .iter()
+        .filter_map(|function| {
+            let names = readable_param_names(module, function.id())?;
+            Some((function.id().index(), names))
+        })
+        .collect();
+    (func_names, param_names)
+}
+
+#[cfg(test)]
+mod tests {
+    use walrus::{FunctionBuilder, Module, ValType};
+
+    use super::{escape_proccode, readable_func_names, readable_param_names};
+
+    /// a local function taking `params`, the locals are named as given
+    fn add_func(
+        module: &mut Module,
+        name: Option<&str>,
+        params: &[(Option<&str>, ValType)],
+    ) -> walrus::FunctionId {
+        let types = params.iter().map(|(_, ty)| *ty).collect::<Vec<_>>();
+        let builder = FunctionBuilder::new(&mut module.types, &types, &[]);
+        let args = params
+            .iter()
+            .map(|(name, ty)| {
+                let local = module.locals.add(*ty);
+                module.locals.get_mut(local).name = name.map(str::to_string);
+                local
+            })
+            .collect();
+        let id = builder.finish(args, &mut module.funcs);
+        module.funcs.get_mut(id).name = name.map(str::to_string);
+        id
+    }
+
+    #[test]
+    fn test_escape_proccode() {
+        for (label, escaped) in [
+            ("parse", "parse"),
+            ("a  b\n\tc ", "a b c"),
+            ("100%", "100％"),
+            ("%s %b %n", "％s ％b ％n"),
+            ("", ""),
+        ] {
+            assert_eq!(escape_proccode(label), escaped, "{label:?}");
+        }
+    }
+
+    #[test]
+    fn test_readable_func_names() {
+        for (names, exports, expected) in [
+            (
+                vec![Some("add"), Some("sub")],
+                vec![],
+                vec![Some("add"), Some("sub")],
+            ),
+            // both of a clash are suffixed with the index
+            (
+                vec![Some("add"), Some("add")],
+                vec![],
+                vec![Some("add #0"), Some("add #1")],
+            ),
+            // the suffixed label meets a unique one
+            (
+                vec![Some("f"), Some("f #0"), Some("f")],
+                vec![],
+                vec![Some("f #0.1"), Some("f #0"), Some("f #2")],
+            ),
+            (
+                vec![
+                    Some("50% done"),
+                    Some("_ZN7mycrate5parse17h0123456789abcdefE"),
+                ],
+                vec![],
+                vec![Some("50％ done"), Some("mycrate::parse")],
+            ),
+            // the runtime procedures are never shadowed
+            (
+                vec![Some("__wasm_internal_func_add"), Some("to_utf8_push")],
+                vec![],
+                vec![Some("__wasm_internal_func_add #0"), Some("to_utf8_push #1")],
+            ),
+            // the export name without a name section, nothing without both
+            (
+                vec![None, None, Some("named")],
+                vec![(0, "exported"), (2, "ignored")],
+                vec![Some("exported"), None, Some("named")],
+            ),
+        ] {
+            let mut module = Module::default();
+            let ids = names
+                .iter()
+                .map(|name| add_func(&mut module, *name, &[]))
+                .collect::<Vec<_>>();
+            for (k, export) in exports {
+                module.exports.add(export, ids[k]);
+            }
+
+            let labels = readable_func_names(&module);
+            for (id, expected) in ids.iter().zip(&expected) {
+                assert_eq!(
+                    labels.get(&id.index()).map(String::as_str),
+                    *expected,
+                    "{names:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_readable_param_names() {
+        for (params, expected) in [
+            (vec![], Some(vec![])),
+            (
+                vec![(Some("a"), ValType::I32), (Some("b"), ValType::F64)],
+                Some(vec!["a: i32", "b: f64"]),
+            ),
+            (
+                vec![(Some("x"), ValType::I64), (Some("y"), ValType::F32)],
+                Some(vec!["x: i64_hi", "x: i64_lo", "y: f32"]),
+            ),
+            (vec![(Some("50%"), ValType::I32)], Some(vec!["50％: i32"])),
+            // every parameter needs a name
+            (vec![(Some("a"), ValType::I32), (None, ValType::I32)], None),
+            (vec![(Some(" "), ValType::I32)], None),
+            // and the names must be unique
+            (
+                vec![(Some("a"), ValType::I32), (Some("a"), ValType::I32)],
+                None,
+            ),
+            (
+                vec![(Some("a b"), ValType::I32), (Some("a  b"), ValType::I32)],
+                None,
+            ),
+            (vec![(Some("v"), ValType::V128)], None),
+        ] {
+            let mut module = Module::default();
+            let id = add_func(&mut module, Some("f"), &params);
+            assert_eq!(
+                readable_param_names(&module, id),
+                expected.map(|names| names.into_iter().map(String::from).collect()),
+                "{params:?}"
+            );
+        }
+    }
+}
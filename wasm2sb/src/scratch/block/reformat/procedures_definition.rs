@@ -1,72 +1,91 @@
-use crate::{scratch::sb3::ProjectZip, util::wrap_by_len, GenCtx};
-
-use sb_itchy::{
-    block::{BlockFieldBuilder, BlockInputBuilder},
-    blocks::*,
-    custom_block::CustomBlockInputType,
-    stack::StackBuilder,
+use crate::{
+    scratch::sb3::{ProcedureComment, ProjectZip},
+    util::wrap_by_len,
+    GenCtx,
 };
 
-use crate::pre_name::PRE_FUNC_NAME;
-use sb_sbity::block::BlockInputValue;
-use walrus::{Function, Type, ValType};
+use eyre::{eyre, Result};
+use sb_itchy::{blocks::*, custom_block::CustomBlockInputType, stack::StackBuilder};
+
+use walrus::{Function, FunctionKind, Module, ValType};
+
+use crate::scratch::block::function_code::generate_func_block_code;
 
 // https://developer.mozilla.org/ja/docs/WebAssembly/Understanding_the_text_format
 
+/// names of the custom block inputs for the parameters
+///
+/// i64 is passed as two inputs, hi then lo
+pub fn func_param_names(params: &[ValType]) -> Result<Vec<String>> {
+    let params_len = params.len();
+    let mut names = Vec::new();
+    for (k, f) in params.iter().enumerate() {
+        let ty = match f {
+            ValType::I32 => "i32",
+            ValType::I64 => "i64",
+            ValType::F32 => "f32",
+            ValType::F64 => "f64",
+            ValType::Externref => return Err(eyre!("Externref is not supported")),
+            ValType::Funcref => return Err(eyre!("FuncRef is not supported")),
+            ValType::V128 => return Err(eyre!("V128 is not supported")),
+        };
+        let name = format!("{}_{ty}", wrap_by_len(k, params_len));
+        match f {
+            ValType::I64 => {
+                names.push(format!("{name}_hi"));
+                names.push(format!("{name}_lo"));
+            }
+            _ => names.push(name),
+        }
+    }
+    Ok(names)
+}
+
 impl ProjectZip {
     pub fn generate_func_block(
         &mut self,
-        _function: &Function,
-        func_type: &Type,
+        module: &Module,
+        function: &Function,
         ctx: &mut GenCtx,
-    ) -> Vec<StackBuilder> {
-        let params_len = func_type.params().len();
-        let name = format!("{PRE_FUNC_NAME}{}", ctx.gen_pre_name());
-        let mut func_type = func_type
-            .params()
-            .iter()
-            .enumerate()
-            .flat_map(|(k, f)| {
-                let mut types = vec![];
-                types.push(match f {
-                    ValType::I32 => CustomBlockInputType::StringOrNumber(format!(
-                        "{}_i32",
-                        wrap_by_len(k, params_len)
-                    )),
-                    ValType::I64 => CustomBlockInputType::StringOrNumber(format!(
-                        "{}_i64",
-                        wrap_by_len(k, params_len)
-                    )),
-                    ValType::F32 => CustomBlockInputType::StringOrNumber(format!(
-                        "{}_f32",
-                        wrap_by_len(k, params_len)
-                    )),
-                    ValType::F64 => CustomBlockInputType::StringOrNumber(format!(
-                        "{}_f64",
-                        wrap_by_len(k, params_len)
-                    )),
-                    ValType::Externref => unimplemented!("Externref"),
-                    ValType::Funcref => unimplemented!("FuncRef"),
-                    ValType::V128 => unimplemented!("V128"),
-                });
-                types
-            })
+    ) -> Result<Vec<StackBuilder>> {
+        let local_function = match &function.kind {
+            FunctionKind::Local(local_function) => local_function,
+            // imported functions are provided by the runtime
+            _ => return Ok(vec![]),
+        };
+        let func_type = module.types.get(function.ty());
+
+        let name = ctx.gen_func_name(function.id().index());
+        let mut inputs = ctx
+            .gen_param_names(function.id().index(), func_type.params())?
+            .into_iter()
+            .map(CustomBlockInputType::StringOrNumber)
             .collect::<Vec<CustomBlockInputType>>();
 
-        func_type.insert(0, CustomBlockInputType::Text(name.clone()));
-        self.define_custom_block(func_type, true);
+        inputs.insert(0, CustomBlockInputType::Text(name.clone()));
+        self.define_custom_block(inputs, true);
 
-        // let mut inner_builder = define_custom_block(name);
-        // ctx.update_func_block();
+        let (body, calls) = generate_func_block_code(
+            module,
+            function.id().index(),
+            local_function,
+            func_type,
+            ctx,
+        )?;
+        if let Some(source_map) = &ctx.source_map {
+            let index = function.id().index();
+            let entry = local_function.block(local_function.entry_block());
+            let mut text = format!("{name}\nwasm function {index}");
+            if let Some((_, loc)) = entry.instrs.first() {
+                text += &format!(" {}", source_map.describe(loc.data() as usize));
+            }
+            self.add_procedure_comment(name.clone(), ProcedureComment { text, calls });
+        }
 
-        // inner_builder = inner_builder.next(replace_in_list(
-        //     BlockFieldBuilder::new("__wasm_function_stack".into()),
-        //     BlockInputBuilder::value(BlockInputValue::Integer { value: 1.into() }),
-        //     BlockInputBuilder::value(BlockInputValue::String {
-        //         value: "Hello world".to_owned().into(),
-        //     }),
-        // ));
+        let stack_builder = body
+            .into_iter()
+            .fold(define_custom_block(&name), |stack, next| stack.next(next));
 
-        vec![]
+        Ok(vec![stack_builder])
     }
 }
@@ -0,0 +1,164 @@
+// call_indirect, a dispatch procedure for each table and signature
+// the index is searched by a binary if_else tree over the elements of the signature
+
+use std::collections::BTreeMap;
+
+use eyre::{eyre, Result};
+use sb_itchy::{block::BlockInputBuilder, stack::StackBuilder};
+use sb_itchy_support::{block_generator_into::BlockGeneratorInto, blocks_wrapper::*};
+use walrus::{
+    ir::{CallIndirect, Instr, Value},
+    ElementKind, FunctionId, FunctionKind, GlobalKind, InitExpr, Module, TableId, TypeId,
+};
+
+use crate::{
+    pre_name::pre_func_name,
+    scratch::{
+        block::{define_procedure, function_code::callee_name, trap::wasm_trap},
+        sb3::ProjectZip,
+    },
+    GenCtx,
+};
+
+use super::reformat::procedures_definition::func_param_names;
+
+type Bib = BlockInputBuilder;
+
+pub fn call_indirect_func_name(table: TableId, ty: TypeId) -> String {
+    format!(
+        "{}call_indirect_{}_{}",
+        pre_func_name(),
+        table.index(),
+        ty.index()
+    )
+}
+
+/// the functions of the table by their index
+pub fn table_elements(module: &Module, table: TableId) -> Result<BTreeMap<usize, FunctionId>> {
+    let mut elements = BTreeMap::new();
+    for element in module.elements.iter() {
+        let ElementKind::Active {
+            table: target,
+            offset,
+        } = &element.kind
+        else {
+            continue;
+        };
+        if *target != table {
+            continue;
+        }
+        let offset = match offset {
+            InitExpr::Value(Value::I32(offset)) => *offset as usize,
+            InitExpr::Global(global) => match &module.globals.get(*global).kind {
+                GlobalKind::Local(InitExpr::Value(Value::I32(offset))) => *offset as usize,
+                _ => return Err(eyre!("element segment offset must be a constant")),
+            },
+            _ => return Err(eyre!("element segment offset must be a constant")),
+        };
+        for (k, member) in element.members.iter().enumerate() {
+            match member {
+                Some(func) => elements.insert(offset + k, *func),
+                None => elements.remove(&(offset + k)),
+            };
+        }
+    }
+    Ok(elements)
+}
+
+/// (table, signature) of every call_indirect, in the order of their indices
+pub fn call_indirect_signatures(module: &Module) -> Vec<(TableId, TypeId)> {
+    let mut signatures = Vec::new();
+    for function in module.funcs.iter() {
+        let FunctionKind::Local(local) = &function.kind else {
+            continue;
+        };
+        let mut seqs = vec![local.entry_block()];
+        while let Some(seq) = seqs.pop() {
+            for (instr, _) in local.block(seq).instrs.iter() {
+                match instr {
+                    Instr::CallIndirect(CallIndirect { ty, table }) => {
+                        signatures.push((*table, *ty))
+                    }
+                    Instr::Block(block) => seqs.push(block.seq),
+                    Instr::Loop(block) => seqs.push(block.seq),
+                    Instr::IfElse(block) => {
+                        seqs.push(block.consequent);
+                        seqs.push(block.alternative);
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+    signatures.sort_by_key(|(table, ty)| (table.index(), ty.index()));
+    signatures.dedup();
+    signatures
+}
+
+/// the dispatch procedures, `index` then the parameters of the signature
+///
+/// an index out of the table, a null element or a function of another signature traps
+pub fn table_generator(ctx: &mut ProjectZip, module: &Module, gen_ctx: &GenCtx) -> Result<()> {
+    for (table, ty) in call_indirect_signatures(module) {
+        let signature = module.types.get(ty);
+        let names = func_param_names(signature.params())?;
+        let index = || custom_block_var_string_number("index");
+
+        // signatures are compared by their types, not by their ids
+        let mut branches = Vec::new();
+        for (k, func) in table_elements(module, table)? {
+            let callee = module.funcs.get(func);
+            let callee_type = module.types.get(callee.ty());
+            if callee_type.params() != signature.params()
+                || callee_type.results() != signature.results()
+            {
+                continue;
+            }
+            let callee_names = gen_ctx.gen_param_names(func.index(), callee_type.params())?;
+            let args = callee_names
+                .iter()
+                .zip(&names)
+                .map(|(callee_name, name)| {
+                    (
+                        callee_name.as_str(),
+                        custom_block_var_string_number(name.as_str()),
+                    )
+                })
+                .collect();
+            let call = call_custom_block(&callee_name(module, gen_ctx, func)?, args);
+            branches.push((k, call));
+        }
+
+        let args = std::iter::once("index")
+            .chain(names.iter().map(String::as_str))
+            .collect::<Vec<_>>();
+        define_procedure(
+            ctx,
+            &call_indirect_func_name(table, ty),
+            &args,
+            dispatch_tree(&index(), branches),
+        );
+    }
+    Ok(())
+}
+
+/// binary search on `index` for the element, the leaves check the index itself
+fn dispatch_tree(index: &Bib, mut branches: Vec<(usize, StackBuilder)>) -> StackBuilder {
+    let trap = || wasm_trap("undefined element or indirect call type mismatch");
+    match branches.len() {
+        0 => trap(),
+        1 => {
+            let (k, call) = branches.pop().unwrap();
+            if_else(equals(index, k), call, trap())
+        }
+        len => {
+            let upper = branches.split_off(len / 2);
+            let pivot = upper[0].0;
+            if_else(
+                less_than(index, pivot),
+                dispatch_tree(index, branches),
+                dispatch_tree(index, upper),
+            )
+        }
+    }
+}
@@ -5,7 +5,7 @@ use sb_itchy::{
 use sb_itchy_support::{block_generator_into::BlockGeneratorInto, blocks_wrapper::*, stack};
 use sb_sbity::value::{Number, ValueWithBool};
 
-use crate::scratch::{block::to_utf8::PRE_UNICODE, sb3::ProjectZip};
+use crate::{pre_name::pre_unicode, scratch::sb3::ProjectZip};
 
 use super::{
     tmp_list_name, unicode::all_unicode_upper_letter_case_range, upper_case_data_list_name,
@@ -30,8 +30,8 @@ pub fn check_unicode_func_generator(ctx: &mut ProjectZip, list_init_data: &mut V
             .collect::<Vec<ValueWithBool>>(),
     );
 
-    let check_unicode_func_name = format!("{PRE_UNICODE}check_unicode");
-    let check_unicode_func_impl_name = format!("{PRE_UNICODE}check_unicode_impl");
+    let check_unicode_func_name = format!("{}check_unicode", pre_unicode());
+    let check_unicode_func_impl_name = format!("{}check_unicode_impl", pre_unicode());
 
     ctx.define_custom_block(
         vec![
@@ -77,55 +77,74 @@ pub fn check_unicode_func_generator(ctx: &mut ProjectZip, list_init_data: &mut V
     };
     let stop_this_script = || stop("this script", false);
 
+    // item # of is not case sensitive, so the list is reversed to find the lowercase letter first
+    // check_uppercase expects the lowercase code point
     let check_unicode_func_generator_inner_ascii = stack![
         repeat(
             length_of(ascii_var()),
             stack![add_to_list(
                 &tmp_list(),
-                letter_of(add(length_of_list(tmp_list()), 1), ascii_var()),
+                letter_of(
+                    sub(length_of(ascii_var()), length_of_list(tmp_list())),
+                    ascii_var()
+                ),
             )],
         ),
-        set_unicode(add(count_of_item_in_list(tmp_list(), unicode()), "0x1F")),
+        set_unicode(sub("0x7F", count_of_item_in_list(tmp_list(), unicode()))),
         delete_all_in_list(&tmp_list())
     ];
     let hexadecimal = |str: &str| -> BlockInputBuilder { add(str, 0) };
 
-    let check_surrogate_pair = stack![set_dichotomous_search_min(hexadecimal("0xD800"))];
-
-    let check_surrogate_no_pair = stack![
-        set_dichotomous_search_mid(hexadecimal("0x8000")),
-        set_dichotomous_search_min(1.to()),
-        set_dichotomous_search_max(hexadecimal("0xFFFF")),
-        forever(stack![
-            if_else(
-                less_than(unicode(), to_unicode(dichotomous_search_mid())),
-                stack![
-                    set_dichotomous_search_max(sub(dichotomous_search_mid(), 1)),
-                    call_custom_block(
-                        &check_unicode_func_impl_name,
-                        vec![("n", 8.to())].into_iter().collect()
-                    )
-                ],
+    // binary search between min and max
+    let search = |min: &str, max: &str| -> StackBuilder {
+        stack![
+            set_dichotomous_search_min(hexadecimal(min)),
+            set_dichotomous_search_max(hexadecimal(max)),
+            set_dichotomous_search_mid(math_op(
+                "floor",
+                div(add(dichotomous_search_min(), dichotomous_search_max()), 2)
+            )),
+            forever(stack![
                 if_else(
-                    less_than(to_unicode(dichotomous_search_mid()), unicode()),
+                    less_than(unicode(), to_unicode(dichotomous_search_mid())),
                     stack![
-                        set_dichotomous_search_min(add(dichotomous_search_mid(), 1)),
+                        set_dichotomous_search_max(sub(dichotomous_search_mid(), 1)),
                         call_custom_block(
                             &check_unicode_func_impl_name,
-                            vec![("n", 7.to()), ("min?", always_true())]
-                                .into_iter()
-                                .collect()
+                            vec![("n", 8.to())].into_iter().collect()
                         )
                     ],
-                    stack![set_unicode(dichotomous_search_mid()), stop_this_script()],
+                    if_else(
+                        less_than(to_unicode(dichotomous_search_mid()), unicode()),
+                        stack![
+                            set_dichotomous_search_min(add(dichotomous_search_mid(), 1)),
+                            call_custom_block(
+                                &check_unicode_func_impl_name,
+                                vec![("n", 7.to()), ("min?", always_true())]
+                                    .into_iter()
+                                    .collect()
+                            )
+                        ],
+                        stack![set_unicode(dichotomous_search_mid()), stop_this_script()],
+                    ),
                 ),
-            ),
-            set_dichotomous_search_mid(math_op(
-                "floor",
-                div(add(dichotomous_search_min(), dichotomous_search_max()), 2)
-            ))
-        ])
-    ];
+                set_dichotomous_search_mid(math_op(
+                    "floor",
+                    div(add(dichotomous_search_min(), dichotomous_search_max()), 2)
+                ))
+            ])
+        ]
+    };
+
+    // a surrogate pair is compared with the other pairs only, they are ordered by code point
+    let check_surrogate_pair = search("0x10000", "0x10FFFF");
+
+    // the surrogates in between are translated to U+FFFD, which breaks the order
+    let check_surrogate_no_pair = if_else(
+        greater_than(unicode(), "\u{D7FF}"),
+        search("0xE000", "0xFFFF"),
+        search("0x1", "0xD7FF"),
+    );
 
     let check_unicode_func = stack![
         define_custom_block(&check_unicode_func_name),
@@ -1,6 +1,6 @@
-use crate::scratch::sb3::ProjectZip;
+use crate::{pre_name::pre_unicode, scratch::sb3::ProjectZip};
 
-use super::{unicode::all_unicode_upper_letter_case, PRE_UNICODE};
+use super::unicode::all_unicode_upper_letter_case;
 use sb_itchy::prelude::*;
 use sb_itchy_support::stack;
 use sb_sbity::value::{Number, ValueWithBool};
@@ -14,9 +14,8 @@ pub fn check_uppercase_func_generator(
     ctx: &mut ProjectZip,
     offset: i32,
     list_init_data: &mut Vec<ValueWithBool>,
+    feature_surrogate_pair: bool,
 ) -> String {
-    let feature_surrogate_pair = false;
-
     let unicodes = all_unicode_upper_letter_case(feature_surrogate_pair);
 
     // for unicodes in &unicodes {
@@ -37,12 +36,12 @@ pub fn check_uppercase_func_generator(
             .collect::<Vec<ValueWithBool>>(),
     );
 
-    let check_uppercase_func_name = format!("{PRE_UNICODE}check_uppercase");
-    let check_uppercase_impl_func_name = format!("{PRE_UNICODE}check_uppercase_impl");
+    let check_uppercase_func_name = format!("{}check_uppercase", pre_unicode());
+    let check_uppercase_impl_func_name = format!("{}check_uppercase_impl", pre_unicode());
 
-    let tmp_list_name = format!("{PRE_UNICODE}tmp");
+    let tmp_list_name = format!("{}tmp", pre_unicode());
     ctx.add_list_builder(tmp_list_name.clone(), ListBuilder::new(Vec::new()));
-    let upper_case_data_list_name = format!("{PRE_UNICODE}uppercase_data");
+    let upper_case_data_list_name = format!("{}uppercase_data", pre_unicode());
 
     let default_resource = Resource::new(
         "svg".into(),
@@ -1,14 +1,61 @@
-use sb_itchy::{custom_block::CustomBlockInputType, data::ListBuilder};
+use sb_itchy::{block::BlockInputBuilder, data::ListBuilder};
+use sb_itchy_support::{block_generator_into::BlockGeneratorInto, blocks_wrapper::*, stack};
 use sb_sbity::value::{Number, ValueWithBool};
 
-use crate::scratch::sb3::ProjectZip;
+use crate::{
+    pre_name::{pre_unicode, MEMORY_LIST},
+    scratch::{
+        block::{
+            define_procedure,
+            function_code::{function_stack, local_stack},
+        },
+        sb3::ProjectZip,
+    },
+    GenCtx,
+};
 
 use super::{
-    check_unicode::check_unicode_func_generator, check_uppercase::check_uppercase_func_generator,
-    upper_case_data_list_name,
+    chars_list_name, check_unicode::check_unicode_func_generator,
+    check_uppercase::check_uppercase_func_generator, upper_case_data_list_name, utf8_func_name,
 };
 
-pub fn to_utf8_generator(target_ctx: &mut ProjectZip) {
+type Bib = BlockInputBuilder;
+
+// registers in __wasm_local_stack
+const I: usize = 0;
+const CP: usize = 1;
+const B: usize = 2;
+const S: usize = 3;
+const REGISTERS_LEN: usize = 4;
+
+/// the letters `from_utf8` picks from, `letter of` counts UTF-16 code units
+///
+/// 1: U+0000 ..= U+FFFF, the surrogates are U+FFFD
+/// 2: U+10000 + 0x400 * k, its high surrogate is letter 2k + 1
+/// 3: U+10000 + k, its low surrogate is letter 2k + 2
+fn chars_data() -> Vec<ValueWithBool> {
+    let chars = |cp: fn(u32) -> u32, len: u32| {
+        let text = (0..len)
+            .map(|k| char::from_u32(cp(k)).unwrap_or('\u{FFFD}'))
+            .collect::<String>();
+        ValueWithBool::Text(text)
+    };
+    vec![
+        chars(|k| k, 0x10000),
+        chars(|k| 0x10000 + 0x400 * k, 0x400),
+        chars(|k| 0x10000 + k, 0x400),
+    ]
+}
+
+/// `to_utf8`, `to_utf8_store` and `from_utf8` between scratch strings and the linear memory
+///
+/// without `feature_surrogate_pair`, the characters out of the BMP become U+FFFD,
+/// so do the surrogates without a pair
+pub fn to_utf8_generator(
+    target_ctx: &mut ProjectZip,
+    gen_ctx: &mut GenCtx,
+    feature_surrogate_pair: bool,
+) {
     let upper_case_data_list_name = upper_case_data_list_name();
 
     let mut list_init_data = vec![
@@ -33,28 +80,303 @@ pub fn to_utf8_generator(target_ctx: &mut ProjectZip) {
 
     let offset = list_init_data.len() as i32;
 
-    check_uppercase_func_generator(target_ctx, offset, &mut list_init_data);
+    check_uppercase_func_generator(
+        target_ctx,
+        offset,
+        &mut list_init_data,
+        feature_surrogate_pair,
+    );
 
     target_ctx.add_list_builder(
         upper_case_data_list_name.clone(),
         ListBuilder::new(list_init_data),
     );
 
-    target_ctx.define_custom_block(
-        vec![
-            CustomBlockInputType::Text("to_utf8".into()),
-            CustomBlockInputType::StringOrNumber("str".into()),
+    let code_point_name = format!("{}code_point", pre_unicode());
+    let bytes_name = format!("{}bytes", pre_unicode());
+    target_ctx.add_list_builder(bytes_name.clone(), ListBuilder::new(Vec::new()));
+    let chars_name = chars_list_name();
+    target_ctx.add_list_builder(chars_name.clone(), ListBuilder::new(chars_data()));
+
+    gen_ctx.reserve_function_stack(1);
+    let base = gen_ctx.alloc_local_stack(REGISTERS_LEN);
+
+    let upper_case_data =
+        |index: i32| item_in_list(global_list_menu(&upper_case_data_list_name), index);
+    let bytes = || global_list_menu(&bytes_name);
+    let memory = || global_list_menu(MEMORY_LIST);
+    let arg = |name: &str| custom_block_var_string_number(name);
+    let reg = |k: usize| item_in_list(local_stack(), base + k + 1);
+    let set_reg = |k: usize, value: Bib| replace_in_list(local_stack(), base + k + 1, value);
+    let ret = |value: Bib| replace_in_list(function_stack(), 1, value);
+    let chars = |k: i32| item_in_list(global_list_menu(&chars_name), k);
+    let letter = |k: Bib| letter_of(k, arg("str"));
+    // U+D800 ..= U+DFFF, rust strings have no lone surrogates so the bounds come from pairs
+    let is_surrogate = |k: Bib| {
+        and(
+            greater_than(letter(k.clone()), "\u{D7FF}"),
+            less_than(letter(k), "\u{E000}"),
+        )
+    };
+    let is_high = |k: Bib| {
+        and(
+            is_surrogate(k.clone()),
+            not(greater_than(letter(k), letter_of(1, "\u{10FFFF}"))),
+        )
+    };
+    let is_low = |k: Bib| {
+        and(
+            is_surrogate(k.clone()),
+            not(less_than(letter(k), letter_of(2, "\u{10000}"))),
+        )
+    };
+    // floor(CP / 64^k) mod 64 as a continuation byte
+    let continuation = |k: i32| {
+        add(
+            0x80,
+            modulo(math_op("floor", div(reg(CP), 64f64.powi(k))), 64),
+        )
+    };
+
+    // the code point of a letter or a surrogate pair
+    define_procedure(
+        target_ctx,
+        &code_point_name,
+        &["char"],
+        stack![
+            call_custom_block(
+                &format!("{}check_unicode", pre_unicode()),
+                vec![("unicode", arg("char"))].into_iter().collect()
+            ),
+            call_custom_block(
+                &format!("{}check_uppercase", pre_unicode()),
+                vec![("str", arg("char")), ("unicode", upper_case_data(5))]
+                    .into_iter()
+                    .collect()
+            ),
+            ret(upper_case_data(1))
+        ],
+    );
+
+    // str -> code points -> UTF-8 bytes in `to_utf8_bytes`, returns the number of bytes
+    define_procedure(
+        target_ctx,
+        &utf8_func_name("to_utf8"),
+        &["str"],
+        stack![
+            delete_all_in_list(bytes()),
+            set_reg(I, 1.to()),
+            repeat_until(
+                greater_than(reg(I), length_of(arg("str"))),
+                stack![
+                    // a high surrogate then a low one is a pair, any other surrogate is U+FFFD
+                    if_else(
+                        and(is_high(reg(I)), is_low(add(reg(I), 1))),
+                        stack![
+                            if feature_surrogate_pair {
+                                call_custom_block(
+                                    &code_point_name,
+                                    vec![("char", join(letter(reg(I)), letter(add(reg(I), 1))))]
+                                        .into_iter()
+                                        .collect(),
+                                )
+                            } else {
+                                ret(0xFFFD.to())
+                            },
+                            set_reg(I, add(reg(I), 2))
+                        ],
+                        stack![
+                            if_else(
+                                is_surrogate(reg(I)),
+                                ret(0xFFFD.to()),
+                                call_custom_block(
+                                    &code_point_name,
+                                    vec![("char", letter(reg(I)))].into_iter().collect()
+                                ),
+                            ),
+                            set_reg(I, add(reg(I), 1))
+                        ],
+                    ),
+                    set_reg(CP, item_in_list(function_stack(), 1)),
+                    if_else(
+                        less_than(reg(CP), 0x80),
+                        add_to_list(bytes(), reg(CP)),
+                        if_else(
+                            less_than(reg(CP), 0x800),
+                            stack![
+                                add_to_list(bytes(), add(0xC0, math_op("floor", div(reg(CP), 64)))),
+                                add_to_list(bytes(), continuation(0))
+                            ],
+                            if_else(
+                                less_than(reg(CP), 0x10000),
+                                stack![
+                                    add_to_list(
+                                        bytes(),
+                                        add(0xE0, math_op("floor", div(reg(CP), 4096)))
+                                    ),
+                                    add_to_list(bytes(), continuation(1)),
+                                    add_to_list(bytes(), continuation(0))
+                                ],
+                                stack![
+                                    add_to_list(
+                                        bytes(),
+                                        add(0xF0, math_op("floor", div(reg(CP), 262144)))
+                                    ),
+                                    add_to_list(bytes(), continuation(2)),
+                                    add_to_list(bytes(), continuation(1)),
+                                    add_to_list(bytes(), continuation(0))
+                                ],
+                            ),
+                        ),
+                    )
+                ]
+            ),
+            ret(length_of_list(bytes()))
+        ],
+    );
+
+    // the bytes of the last `to_utf8` into the linear memory
+    define_procedure(
+        target_ctx,
+        &utf8_func_name("to_utf8_store"),
+        &["addr"],
+        stack![
+            set_reg(I, 1.to()),
+            repeat(
+                length_of_list(bytes()),
+                stack![
+                    replace_in_list(
+                        memory(),
+                        add(arg("addr"), reg(I)),
+                        item_in_list(bytes(), reg(I))
+                    ),
+                    set_reg(I, add(reg(I), 1))
+                ]
+            )
         ],
-        true,
     );
-    // let stack_builder = define_custom_block("to_utf8");
-    // let block_input_builder = BiB::value(BiV::String {
-    //     value: String::from("t").into(),
-    // });
-    // let stack_builder = stack_builder.next(set_var_to(
-    //     BlockFieldBuilder::new("a".into()),
-    //     block_input_builder,
-    // ));
-
-    // stack_builder
+
+    // the byte at addr + I + k
+    let byte = |k: i32| item_in_list(memory(), add(add(arg("addr"), reg(I)), k + 1));
+    let payload = |k: i32, bits: i32| mul(modulo(byte(k), 64), 64f64.powi(bits - k - 1));
+    let push = |text: Bib| set_reg(S, join(reg(S), text));
+    // the halves of a pair out of chars 2 and 3
+    let push_pair = || {
+        push(join(
+            letter_of(
+                add(
+                    mul(math_op("floor", div(sub(reg(CP), 0x10000), 0x400)), 2),
+                    1,
+                ),
+                chars(2),
+            ),
+            letter_of(
+                add(mul(modulo(sub(reg(CP), 0x10000), 0x400), 2), 2),
+                chars(3),
+            ),
+        ))
+    };
+
+    // UTF-8 bytes in the linear memory -> str, the bytes are expected to be valid as `str`
+    define_procedure(
+        target_ctx,
+        &utf8_func_name("from_utf8"),
+        &["addr", "len"],
+        stack![
+            set_reg(S, "".to()),
+            set_reg(I, 0.to()),
+            repeat_until(
+                not(less_than(reg(I), arg("len"))),
+                stack![
+                    set_reg(B, byte(0)),
+                    if_else(
+                        less_than(reg(B), 0x80),
+                        stack![set_reg(CP, reg(B)), set_reg(I, add(reg(I), 1))],
+                        if_else(
+                            less_than(reg(B), 0xE0),
+                            stack![
+                                set_reg(CP, add(mul(modulo(reg(B), 32), 64), payload(1, 2))),
+                                set_reg(I, add(reg(I), 2))
+                            ],
+                            if_else(
+                                less_than(reg(B), 0xF0),
+                                stack![
+                                    set_reg(
+                                        CP,
+                                        add(
+                                            add(mul(modulo(reg(B), 16), 4096), payload(1, 3)),
+                                            payload(2, 3)
+                                        )
+                                    ),
+                                    set_reg(I, add(reg(I), 3))
+                                ],
+                                stack![
+                                    set_reg(
+                                        CP,
+                                        add(
+                                            add(
+                                                add(mul(modulo(reg(B), 8), 262144), payload(1, 4)),
+                                                payload(2, 4)
+                                            ),
+                                            payload(3, 4)
+                                        )
+                                    ),
+                                    set_reg(I, add(reg(I), 4))
+                                ],
+                            ),
+                        ),
+                    ),
+                    if_else(
+                        less_than(reg(CP), 0x10000),
+                        push(letter_of(add(reg(CP), 1), chars(1))),
+                        if feature_surrogate_pair {
+                            push_pair()
+                        } else {
+                            push("\u{FFFD}".to())
+                        },
+                    )
+                ]
+            ),
+            ret(reg(S))
+        ],
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use sb_sbity::value::ValueWithBool;
+
+    use super::chars_data;
+
+    /// the UTF-16 code units `letter of` counts
+    fn units(data: &ValueWithBool) -> Vec<u16> {
+        let ValueWithBool::Text(text) = data else {
+            panic!("not a text: {data:?}");
+        };
+        text.encode_utf16().collect()
+    }
+
+    #[test]
+    fn test_chars_data() {
+        let data = chars_data();
+        let [bmp, high, low] = [&data[0], &data[1], &data[2]].map(units);
+        assert_eq!(bmp.len(), 0x10000);
+
+        // the letters `from_utf8` joins for each code point
+        for c in (0..=0x10FFFF).filter_map(char::from_u32) {
+            let cp = c as usize;
+            let letters = if cp < 0x10000 {
+                vec![bmp[cp]]
+            } else {
+                let k = cp - 0x10000;
+                vec![high[2 * (k / 0x400)], low[2 * (k % 0x400) + 1]]
+            };
+            assert_eq!(letters, c.encode_utf16(&mut [0; 2]), "{cp:#X}");
+        }
+        assert!(bmp[0xD800..=0xDFFF].iter().all(|&unit| unit == 0xFFFD));
+
+        // the bounds of the high and the low surrogates in `to_utf8`
+        assert_eq!("\u{10FFFF}".encode_utf16().next(), Some(0xDBFF));
+        assert_eq!("\u{10000}".encode_utf16().nth(1), Some(0xDC00));
+    }
 }
@@ -3,12 +3,21 @@ pub mod check_uppercase;
 pub mod generator;
 pub mod unicode;
 
-pub const PRE_UNICODE: &str = "to_utf8_";
+use crate::pre_name::{pre_func_name, pre_unicode};
+
+pub fn utf8_func_name(name: &str) -> String {
+    format!("{}{name}", pre_func_name())
+}
 
 pub fn tmp_list_name() -> String {
-    format!("{PRE_UNICODE}tmp")
+    format!("{}tmp", pre_unicode())
 }
 
 pub fn upper_case_data_list_name() -> String {
-    format!("{PRE_UNICODE}uppercase_data")
+    format!("{}uppercase_data", pre_unicode())
+}
+
+/// the letters of the code points, see `generator::chars_data`
+pub fn chars_list_name() -> String {
+    format!("{}chars", pre_unicode())
 }
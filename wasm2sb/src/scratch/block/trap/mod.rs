@@ -0,0 +1,58 @@
+// wasm traps and rust panics, they never return
+// the message is kept in __wasm_trap, the other scripts stop, the sprite says it for a while
+// and then everything stops
+
+use sb_itchy::{block::BlockInputBuilder, data::VariableBuilder, stack::StackBuilder};
+use sb_itchy_support::{block_generator_into::BlockGeneratorInto, blocks_wrapper::*, stack};
+use sb_sbity::value::ValueWithBool;
+
+use crate::{
+    pre_name::{pre_func_name, TRAP_VAR},
+    scratch::{block::define_procedure, sb3::ProjectZip},
+};
+
+type Bib = BlockInputBuilder;
+
+pub fn trap_func_name() -> String {
+    format!("{}trap", pre_func_name())
+}
+
+/// call the trap procedure with `message`
+pub fn trap<T: BlockGeneratorInto<Bib>>(message: T) -> StackBuilder {
+    call_custom_block(
+        &trap_func_name(),
+        vec![("message", message.to())].into_iter().collect(),
+    )
+}
+
+/// trap with the wording of the spec, `integer divide by zero` and so on
+pub fn wasm_trap(kind: &str) -> StackBuilder {
+    trap(format!("wasm trap: {kind}").as_str())
+}
+
+/// `__wasm_trap` and the procedure writing it
+pub fn trap_generator(ctx: &mut ProjectZip) {
+    ctx.add_var_builder(
+        TRAP_VAR.into(),
+        VariableBuilder::new(ValueWithBool::Text(String::new())),
+    );
+
+    define_procedure(
+        ctx,
+        &trap_func_name(),
+        &["message"],
+        stack![
+            set_var_to(
+                global_var_menu(TRAP_VAR),
+                custom_block_var_string_number("message")
+            ),
+            // stop all clears the speech bubble, the monitor stays after it
+            show_var(global_var_menu(TRAP_VAR)),
+            // `stop this script` would only return from this procedure,
+            // the other scripts are stopped before saying so nothing runs on
+            stop("other scripts in sprite", true),
+            say_for_secs(global_var(TRAP_VAR), 3),
+            stop("all", false)
+        ],
+    );
+}
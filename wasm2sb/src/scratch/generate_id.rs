@@ -1,3 +1,7 @@
+use std::collections::{HashMap, HashSet};
+
+use serde_json::{Map, Value};
+
 pub fn generate_id() -> String {
     let mut id = String::new();
     for _ in 0..20 {
@@ -20,3 +24,419 @@ fn generate_id_impl() -> char {
     };
     ascii_chars.chars().nth(s as usize).unwrap()
 }
+
+// deterministic ids ===========================================================
+
+const ID_CHARS: &[u8] =
+    br"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789!@#$%^*()-_=+[{]};:,./?";
+
+/// FNV-1a, stable across builds unlike `DefaultHasher`
+pub fn fnv1a(seed: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(seed ^ 0xcbf29ce484222325, |hash, b| {
+        (hash ^ *b as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// the seed of the deterministic ids, from the module bytes
+pub fn id_seed(module: &[u8]) -> u64 {
+    fnv1a(0, module)
+}
+
+/// 20 characters like `generate_id`, from the seed and the path of the block
+pub fn deterministic_id(seed: u64, path: &str) -> String {
+    let mut id = String::new();
+    let mut hash = fnv1a(seed, path.as_bytes());
+    for i in 0..20 {
+        if i % 8 == 0 {
+            hash = fnv1a(hash, &[i]);
+        }
+        id.push(ID_CHARS[(hash % ID_CHARS.len() as u64) as usize] as char);
+        hash /= ID_CHARS.len() as u64;
+    }
+    id
+}
+
+/// the paths of the blocks, the stacks are ordered by their position and their name
+///
+/// `stack/<n>` is the top block, `/next` and `/<input>/<k>` follow the children
+pub fn block_paths(blocks: &Map<String, Value>) -> Vec<(String, String)> {
+    let position = |block: &Value| {
+        let get = |key: &str| block.get(key).and_then(Value::as_f64).unwrap_or_default();
+        (get("x"), get("y"))
+    };
+    let mut tops = blocks
+        .iter()
+        .filter(|(_, block)| block.get("topLevel").and_then(Value::as_bool) == Some(true))
+        .collect::<Vec<_>>();
+    // stacks at the same position by the name of the procedure, the opcode for the others
+    let name = |block: &Value| {
+        let prototype = block
+            .pointer("/inputs/custom_block/1")
+            .and_then(Value::as_str)
+            .and_then(|id| blocks.get(id));
+        prototype
+            .and_then(|prototype| prototype.pointer("/mutation/proccode"))
+            .or_else(|| block.get("opcode"))
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_string()
+    };
+    tops.sort_by(|(_, a), (_, b)| {
+        let (ax, ay) = position(a);
+        let (bx, by) = position(b);
+        ax.total_cmp(&bx)
+            .then(ay.total_cmp(&by))
+            .then_with(|| name(a).cmp(&name(b)))
+    });
+
+    let mut paths = Vec::new();
+    let mut visited = HashSet::new();
+    let mut todo = tops
+        .into_iter()
+        .enumerate()
+        .map(|(n, (id, _))| (id.clone(), format!("stack/{n}")))
+        .rev()
+        .collect::<Vec<_>>();
+    while let Some((id, path)) = todo.pop() {
+        if !visited.insert(id.clone()) {
+            continue;
+        }
+        let Some(block) = blocks.get(&id) else {
+            continue;
+        };
+        let mut children = Vec::new();
+        if let Some(inputs) = block.get("inputs").and_then(Value::as_object) {
+            // serde_json's map is sorted by the input name
+            for (name, input) in inputs {
+                for (k, item) in input.as_array().into_iter().flatten().enumerate() {
+                    if let Some(child) = item.as_str().filter(|child| blocks.contains_key(*child)) {
+                        children.push((child.to_string(), format!("{path}/{name}/{k}")));
+                    }
+                }
+            }
+        }
+        if let Some(next) = block.get("next").and_then(Value::as_str) {
+            children.push((next.to_string(), format!("{path}/next")));
+        }
+        todo.extend(children.into_iter().rev());
+        paths.push((id, path));
+    }
+
+    if paths.len() != blocks.len() {
+        log::warn!(
+            "{} blocks are not reachable from the top level blocks, their ids stay random",
+            blocks.len() - paths.len()
+        );
+    }
+    paths
+}
+
+/// `argumentids` of a prototype or a call, a json array in a string
+fn argument_ids(block: &Value) -> Option<Vec<String>> {
+    serde_json::from_str(block.pointer("/mutation/argumentids")?.as_str()?).ok()
+}
+
+/// the argument ids of the procedures, `<path of the prototype>/argument/<k>`
+///
+/// the calls and the inputs of the prototype use the same ids
+pub fn argument_paths(
+    blocks: &Map<String, Value>,
+    paths: &[(String, String)],
+) -> Vec<(String, String)> {
+    paths
+        .iter()
+        .filter_map(|(id, path)| {
+            let block = blocks.get(id)?;
+            if block.get("opcode")? != "procedures_prototype" {
+                return None;
+            }
+            let ids = argument_ids(block)?.into_iter().enumerate();
+            Some(ids.map(move |(k, arg)| (arg, format!("{path}/argument/{k}"))))
+        })
+        .flatten()
+        .collect()
+}
+
+/// the values of an object, nothing for a missing key
+fn values_mut<'a>(value: &'a mut Value, key: &str) -> impl Iterator<Item = &'a mut Value> {
+    value
+        .get_mut(key)
+        .and_then(Value::as_object_mut)
+        .into_iter()
+        .flat_map(|map| map.values_mut())
+}
+
+/// replaces the ids in `mapping` where the project refers to blocks, variables, lists
+/// and the arguments of procedures
+///
+/// only the id-bearing keys, a text or a list item equal to an id is kept
+pub fn rename_ids(project: &mut Value, mapping: &HashMap<String, String>) {
+    let rename_keys = |map: &mut Map<String, Value>| {
+        *map = std::mem::take(map)
+            .into_iter()
+            .map(|(id, item)| (mapping.get(&id).cloned().unwrap_or(id), item))
+            .collect();
+    };
+    let rename = |value: Option<&mut Value>| {
+        if let Some(value) = value {
+            if let Some(new) = value.as_str().and_then(|id| mapping.get(id)) {
+                *value = Value::String(new.clone());
+            }
+        }
+    };
+    // `[12, name, id]` of a variable and `[13, name, id]` of a list
+    let rename_primitive = |value: &mut Value| {
+        if let Some(items) = value.as_array_mut() {
+            if matches!(items.first().and_then(Value::as_u64), Some(12 | 13)) {
+                rename(items.get_mut(2));
+            }
+        }
+    };
+
+    let targets = project.get_mut("targets").and_then(Value::as_array_mut);
+    for target in targets.into_iter().flatten() {
+        for key in ["variables", "lists", "broadcasts", "blocks"] {
+            if let Some(map) = target.get_mut(key).and_then(Value::as_object_mut) {
+                rename_keys(map);
+            }
+        }
+        for block in values_mut(target, "blocks") {
+            if block.is_array() {
+                rename_primitive(block);
+                continue;
+            }
+            rename(block.get_mut("next"));
+            rename(block.get_mut("parent"));
+            for input in values_mut(block, "inputs") {
+                // `[shadow, value, shadowed]`, the values are ids or primitives
+                for item in input.as_array_mut().into_iter().flatten().skip(1) {
+                    rename_primitive(item);
+                    rename(Some(item));
+                }
+            }
+            // `[value, id]`, the id of a variable, a list or a broadcast
+            for field in values_mut(block, "fields") {
+                rename(field.get_mut(1));
+            }
+            // the inputs of a procedure are keyed by the argument ids
+            if let Some(ids) = argument_ids(block) {
+                if let Some(inputs) = block.get_mut("inputs").and_then(Value::as_object_mut) {
+                    rename_keys(inputs);
+                }
+                let ids = ids
+                    .into_iter()
+                    .map(|id| mapping.get(&id).cloned().unwrap_or(id))
+                    .collect::<Vec<_>>();
+                block["mutation"]["argumentids"] = Value::String(Value::from(ids).to_string());
+            }
+        }
+        for comment in values_mut(target, "comments") {
+            rename(comment.get_mut("blockId"));
+        }
+    }
+    // the monitor of a variable or a list has its id
+    let monitors = project.get_mut("monitors").and_then(Value::as_array_mut);
+    for monitor in monitors.into_iter().flatten() {
+        rename(monitor.get_mut("id"));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use serde_json::{json, Value};
+
+    use super::{argument_paths, block_paths, deterministic_id, fnv1a, rename_ids, ID_CHARS};
+
+    #[test]
+    fn test_fnv1a() {
+        // the test vectors of FNV-1a 64
+        assert_eq!(fnv1a(0, b""), 0xcbf29ce484222325);
+        assert_eq!(fnv1a(0, b"a"), 0xaf63dc4c8601ec8c);
+        assert_eq!(fnv1a(0, b"foobar"), 0x85944171f73967e8);
+        assert_ne!(fnv1a(1, b"foobar"), fnv1a(0, b"foobar"));
+    }
+
+    #[test]
+    fn test_deterministic_id() {
+        let id = deterministic_id(0, "stack/0");
+        assert_eq!(id.len(), 20);
+        assert!(id.bytes().all(|c| ID_CHARS.contains(&c)), "{id}");
+        assert_eq!(id, deterministic_id(0, "stack/0"));
+        assert_ne!(id, deterministic_id(1, "stack/0"));
+        assert_ne!(id, deterministic_id(0, "stack/1"));
+        // the ids are kept between builds
+        assert_eq!(id, "q@O!g8Gp82CUdSd:zBkv");
+    }
+
+    /// a definition at (0, 0) with a prototype of two arguments and a call,
+    /// a `say` at (0, 0), a `move` at (0, -100) and a block nothing refers to
+    fn blocks() -> Value {
+        json!({
+            "def": {
+                "opcode": "procedures_definition", "next": "call", "parent": null,
+                "inputs": { "custom_block": [1, "proto"] }, "fields": {},
+                "topLevel": true, "x": 0, "y": 0,
+            },
+            "proto": {
+                "opcode": "procedures_prototype", "next": null, "parent": "def",
+                "inputs": { "arg_a": [1, "reporter"], "arg_b": [1, "reporter_b"] },
+                "fields": {}, "shadow": true, "topLevel": false,
+                "mutation": {
+                    "tagName": "mutation", "children": [], "proccode": "f %s %s",
+                    "argumentids": "[\"arg_a\",\"arg_b\"]",
+                    "argumentnames": "[\"a\",\"b\"]",
+                    "argumentdefaults": "[\"\",\"\"]", "warp": "true",
+                },
+            },
+            "reporter": {
+                "opcode": "argument_reporter_string_number", "next": null, "parent": "proto",
+                "inputs": {}, "fields": { "VALUE": ["a", null] },
+                "shadow": true, "topLevel": false,
+            },
+            "reporter_b": {
+                "opcode": "argument_reporter_string_number", "next": null, "parent": "proto",
+                "inputs": {}, "fields": { "VALUE": ["b", null] },
+                "shadow": true, "topLevel": false,
+            },
+            "call": {
+                "opcode": "procedures_call", "next": null, "parent": "def",
+                "inputs": { "arg_a": [1, [10, "x"]], "arg_b": [3, "join", [10, ""]] },
+                "fields": {}, "topLevel": false,
+                "mutation": {
+                    "tagName": "mutation", "children": [], "proccode": "f %s %s",
+                    "argumentids": "[\"arg_a\",\"arg_b\"]", "warp": "true",
+                },
+            },
+            "join": {
+                "opcode": "operator_join", "next": null, "parent": "call",
+                "inputs": { "STRING1": [3, [12, "v", "var"], [10, ""]], "STRING2": [1, [10, "join"]] },
+                "fields": {}, "topLevel": false,
+            },
+            "say": {
+                "opcode": "looks_say", "next": null, "parent": null,
+                "inputs": { "MESSAGE": [1, [10, "hi"]] }, "fields": {},
+                "topLevel": true, "x": 0, "y": 0,
+            },
+            "move": {
+                "opcode": "motion_movesteps", "next": null, "parent": null,
+                "inputs": {}, "fields": {}, "topLevel": true, "x": 0, "y": -100,
+            },
+            "lost": {
+                "opcode": "looks_think", "next": null, "parent": "gone",
+                "inputs": {}, "fields": {}, "topLevel": false,
+            },
+        })
+    }
+
+    #[test]
+    fn test_block_paths() {
+        let blocks = blocks();
+        let paths = block_paths(blocks.as_object().unwrap());
+        let paths = paths
+            .iter()
+            .map(|(id, path)| (id.as_str(), path.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            paths,
+            [
+                ("move", "stack/0"),
+                // at the same position, `f %s %s` before `looks_say`
+                ("def", "stack/1"),
+                ("proto", "stack/1/custom_block/1"),
+                ("reporter", "stack/1/custom_block/1/arg_a/1"),
+                ("reporter_b", "stack/1/custom_block/1/arg_b/1"),
+                ("call", "stack/1/next"),
+                ("join", "stack/1/next/arg_b/1"),
+                ("say", "stack/2"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_argument_paths() {
+        let blocks = blocks();
+        let blocks = blocks.as_object().unwrap();
+        let paths = argument_paths(blocks, &block_paths(blocks));
+        assert_eq!(
+            paths,
+            [
+                ("arg_a".into(), "stack/1/custom_block/1/argument/0".into()),
+                ("arg_b".into(), "stack/1/custom_block/1/argument/1".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rename_ids() {
+        let mut project = json!({
+            "targets": [{
+                "variables": { "var": ["v", 0] },
+                "lists": { "list": ["l", []] },
+                "broadcasts": { "msg": "message" },
+                "blocks": blocks(),
+                "comments": { "comment": { "blockId": "say", "text": "say" } },
+            }],
+            "monitors": [{ "id": "var", "opcode": "data_variable" }],
+        });
+        let targets = &mut project["targets"][0];
+        targets["blocks"]["top_var"] = json!([12, "v", "var", 10, 10]);
+        targets["blocks"]["say"]["fields"] = json!({ "BROADCAST_OPTION": ["message", "msg"] });
+
+        let mapping = [
+            "var", "list", "msg", "def", "proto", "reporter", "call", "join", "say", "arg_a",
+            "arg_b",
+        ]
+        .into_iter()
+        .map(|id| (id.to_string(), format!("new_{id}")))
+        .collect::<HashMap<_, _>>();
+        rename_ids(&mut project, &mapping);
+
+        let target = &project["targets"][0];
+        for key in ["variables", "lists", "broadcasts"] {
+            let ids = target[key].as_object().unwrap().keys().collect::<Vec<_>>();
+            assert_eq!(ids.len(), 1);
+            assert!(ids[0].starts_with("new_"), "{key}: {ids:?}");
+        }
+        let blocks = &target["blocks"];
+        assert!(blocks.get("def").is_none());
+        assert_eq!(blocks["new_def"]["next"], "new_call");
+        assert_eq!(
+            blocks["new_def"]["inputs"]["custom_block"],
+            json!([1, "new_proto"])
+        );
+        assert_eq!(blocks["new_proto"]["parent"], "new_def");
+        assert_eq!(
+            blocks["new_proto"]["inputs"],
+            json!({ "new_arg_a": [1, "new_reporter"], "new_arg_b": [1, "reporter_b"] })
+        );
+        for block in ["new_proto", "new_call"] {
+            assert_eq!(
+                blocks[block]["mutation"]["argumentids"],
+                "[\"new_arg_a\",\"new_arg_b\"]"
+            );
+        }
+        assert_eq!(
+            blocks["new_call"]["inputs"],
+            json!({ "new_arg_a": [1, [10, "x"]], "new_arg_b": [3, "new_join", [10, ""]] })
+        );
+        // a text equal to an id is kept
+        assert_eq!(
+            blocks["new_join"]["inputs"],
+            json!({ "STRING1": [3, [12, "v", "new_var"], [10, ""]], "STRING2": [1, [10, "join"]] })
+        );
+        assert_eq!(
+            blocks["new_say"]["fields"]["BROADCAST_OPTION"],
+            json!(["message", "new_msg"])
+        );
+        assert_eq!(blocks["top_var"], json!([12, "v", "new_var", 10, 10]));
+        // the argument names of the reporters are not ids
+        assert_eq!(
+            blocks["new_reporter"]["fields"]["VALUE"],
+            json!(["a", null])
+        );
+        assert_eq!(target["comments"]["comment"]["blockId"], "new_say");
+        assert_eq!(project["monitors"][0]["id"], "new_var");
+    }
+}
@@ -1,14 +1,22 @@
 use sb_itchy::data::ListBuilder;
+use sb_sbity::value::{Number, ValueWithBool};
+
+use crate::{
+    pre_name::{FUNCTION_STACK_LIST, LOCAL_STACK_LIST},
+    GenCtx,
+};
 
 use super::sb3::ProjectZip;
 
-pub fn rewrite_list(ctx: &mut ProjectZip) {
+pub fn rewrite_list(ctx: &mut ProjectZip, gen_ctx: &GenCtx) {
     let lists = vec![
-        "__wasm_global_stack".into(),
-        "__wasm_local_stack".into(),
-        "__wasm_function_stack".into(),
+        (LOCAL_STACK_LIST, gen_ctx.local_stack_len),
+        (FUNCTION_STACK_LIST, gen_ctx.function_stack_len),
     ];
-    for list in lists {
-        ctx.add_list_builder(list, ListBuilder::new(Vec::new()));
+    for (list, len) in lists {
+        ctx.add_list_builder(
+            list.into(),
+            ListBuilder::new(vec![ValueWithBool::Number(Number::Int(0)); len]),
+        );
     }
 }
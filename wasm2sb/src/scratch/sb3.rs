@@ -1,7 +1,4 @@
-use std::{
-    collections::HashMap,
-    sync::{atomic::AtomicUsize, Arc},
-};
+use std::collections::{HashMap, HashSet};
 
 use async_zip::ZipEntryBuilder;
 use futures::{io::Cursor, AsyncReadExt};
@@ -11,7 +8,7 @@ use sb_itchy::{
     asset::CostumeBuilder,
     build_context::{GlobalVarListContext, TargetContext},
     custom_block::{CustomBlockInputType, CustomBlockTy},
-    data::ListBuilder,
+    data::{ListBuilder, VariableBuilder},
     resource::Resource,
     stack::{BlockHeightData, StackBuilder},
     target::SpriteBuilder,
@@ -21,13 +18,43 @@ use sb_sbity::{
     block::Block, comment::Comment, project::Project, string_hashmap::StringHashMap,
     target::SpriteOrStage,
 };
-
-use crate::util::get_preview_rect_from_block;
+use serde_json::{json, Map, Value};
+
+use crate::{
+    project_config::{LayoutConfig, StackLayout},
+    scratch::generate_id::{
+        argument_paths, block_paths, deterministic_id, generate_id, rename_ids,
+    },
+    util::get_preview_rect_from_block,
+};
 
 use eyre::{eyre, Context, Result};
 
 pub type CommentMap = HashMap<Uid, Comment>;
 
+/// what the procedures of a stack are for, `StackLayout::Grouped` places them apart
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum StackGroup {
+    /// the functions of the module
+    User,
+    #[default]
+    Runtime,
+    Utf8,
+}
+
+impl StackGroup {
+    pub const ALL: [StackGroup; 3] = [StackGroup::User, StackGroup::Runtime, StackGroup::Utf8];
+}
+
+/// comments of a procedure, attached to the blocks in `build`
+#[derive(Debug, Clone, Default)]
+pub struct ProcedureComment {
+    /// on the define block
+    pub text: String,
+    /// on the calls in the body in the order they run, by the label of the callee
+    pub calls: Vec<(String, String)>,
+}
+
 #[derive(Debug)]
 pub struct ProjectZip {
     path: String,
@@ -36,11 +63,19 @@ pub struct ProjectZip {
     x: i32,
     y: i32,
     target_context: TargetContextWrapper,
-    stack_builders: Vec<StackBuilder>,
+    stack_builders: Vec<(StackGroup, StackBuilder)>,
+    group: StackGroup,
+    global_var_builders: HashMap<String, VariableBuilder>,
     global_list_builders: HashMap<String, ListBuilder>,
     costume_builders: Vec<CostumeBuilder>,
     res_buff: Vec<Resource>,
     comment_buff: CommentMap,
+    /// by the label of the procedure
+    procedure_comments: HashMap<String, ProcedureComment>,
+    /// sprite the scripts are written to, the first sprite if `None`
+    sprite_name: Option<String>,
+    layout: LayoutConfig,
+    id_seed: Option<u64>,
 }
 
 impl ProjectZip {
@@ -134,13 +169,87 @@ impl ProjectZip {
             y: top_y as i32,
             x: (left_x - 2000) as i32,
             comment_buff: HashMap::new(),
+            procedure_comments: HashMap::new(),
             stack_builders: Vec::new(),
+            group: StackGroup::default(),
+            global_var_builders: HashMap::new(),
             global_list_builders: HashMap::new(),
             costume_builders: Vec::new(),
             res_buff: Vec::new(),
+            sprite_name: None,
+            layout: LayoutConfig::default(),
+            id_seed: None,
         })
     }
 
+    /// places the stacks from (x, y), the columns go to the left
+    fn place_stacks(&mut self) -> Vec<StackBuilder> {
+        let stacks = std::mem::take(&mut self.stack_builders);
+        let groups = match self.layout.strategy {
+            StackLayout::Column | StackLayout::Grid => {
+                vec![stacks
+                    .into_iter()
+                    .map(|(_, stack)| stack)
+                    .collect::<Vec<_>>()]
+            }
+            StackLayout::Grouped => {
+                let mut groups = StackGroup::ALL.map(|_| Vec::new());
+                for (group, stack) in stacks {
+                    groups[group as usize].push(stack);
+                }
+                groups.into_iter().collect()
+            }
+        };
+        let wrap_height = match self.layout.strategy {
+            StackLayout::Column => None,
+            StackLayout::Grid | StackLayout::Grouped => Some(self.layout.wrap_height),
+        };
+
+        let mut placed = Vec::new();
+        let mut rects = Vec::new();
+        let mut column = 0;
+        for group in groups.into_iter().filter(|group| !group.is_empty()) {
+            if !placed.is_empty() {
+                column += 1;
+            }
+            let mut y = self.get_y();
+            for mut stack in group {
+                let height = stack.calc_block_height(&BlockHeightData::default(), false) as i32 / 2;
+                if wrap_height
+                    .is_some_and(|wrap| y > self.get_y() && y - self.get_y() + height > wrap)
+                {
+                    column += 1;
+                    y = self.get_y();
+                }
+                let x = self.get_x() - column * self.layout.column_width;
+                stack.set_top_block_position(x as f64, y as f64);
+                rects.push((x, y, height));
+                placed.push(stack);
+                y += height + 100;
+            }
+        }
+
+        // the stacks of a column must not overlap
+        rects.sort();
+        for pair in rects.windows(2) {
+            let ((x1, y1, h1), (x2, y2, _)) = (pair[0], pair[1]);
+            if x1 == x2 && y1 + h1 > y2 {
+                warn!("stacks overlap at ({}, {}) and ({}, {})", x1, y1, x2, y2);
+            }
+        }
+
+        placed
+    }
+
+    /// the sprite is renamed if the project has no sprite with `name`
+    pub fn set_sprite_name(&mut self, name: Option<String>) {
+        self.sprite_name = name;
+    }
+
+    pub fn set_layout(&mut self, layout: LayoutConfig) {
+        self.layout = layout;
+    }
+
     pub fn define_custom_block(&mut self, args: Vec<CustomBlockInputType>, warp: bool) {
         let custom_func = CustomBlockTy::new(args, warp);
         self.target_context
@@ -160,19 +269,18 @@ impl ProjectZip {
         self.y += y;
     }
 
-    // pub fn get_target_context(&self) -> TargetContextGuard {
-    //     self.target_context.get_target_context()
-    // }
-
     // pub fn target_context_mut(&mut self) -> &mut TargetContextWrapper {
     //     &mut self.target_context
     // }
 
-    pub fn add_stack_builder(&mut self, mut stack_builder: StackBuilder) {
-        stack_builder.set_top_block_position(self.get_x() as f64, self.get_y() as f64);
-        let height = stack_builder.calc_block_height(&BlockHeightData::default(), false);
-        self.stack_builders.push(stack_builder);
-        self.update_y(height as i32 / 2 + 100);
+    /// the group of the stacks added after this
+    pub fn set_group(&mut self, group: StackGroup) {
+        self.group = group;
+    }
+
+    /// the position is decided in `build` by the layout
+    pub fn add_stack_builder(&mut self, stack_builder: StackBuilder) {
+        self.stack_builders.push((self.group, stack_builder));
     }
 
     pub fn add_stack_builders(&mut self, stack_builders: Vec<StackBuilder>) {
@@ -181,6 +289,10 @@ impl ProjectZip {
         }
     }
 
+    pub fn add_var_builder(&mut self, name: String, var_builder: VariableBuilder) {
+        self.global_var_builders.insert(name, var_builder);
+    }
+
     pub fn add_list_builder(&mut self, name: String, list_builder: ListBuilder) {
         self.global_list_builders.insert(name, list_builder);
     }
@@ -189,32 +301,73 @@ impl ProjectZip {
         self.costume_builders.push(costume_builder);
     }
 
-    pub fn build(&mut self) {
+    pub fn add_procedure_comment(&mut self, label: String, comment: ProcedureComment) {
+        self.procedure_comments.insert(label, comment);
+    }
+
+    /// `Some` for the deterministic ids, see `generate_id::id_seed`
+    pub fn set_id_seed(&mut self, seed: Option<u64>) {
+        self.id_seed = seed;
+    }
+
+    pub fn build(&mut self) -> Result<()> {
+        // placed before the targets are borrowed, it needs all of `self`
+        let stack_builders = self.place_stacks();
+
+        let sprite_index = self
+            .project
+            .targets
+            .iter()
+            .position(|target| match (target, &self.sprite_name) {
+                (SpriteOrStage::Sprite(sprite), Some(name)) => sprite.target.name == *name,
+                _ => false,
+            })
+            .or_else(|| {
+                self.project
+                    .targets
+                    .iter()
+                    .position(|target| matches!(target, SpriteOrStage::Sprite(_)))
+            });
         let mut sprite = None;
-        for target in self.project.targets.iter_mut() {
+        // (path, id) of the generated variables and lists
+        let mut generated_data = Vec::new();
+        for (i, target) in self.project.targets.iter_mut().enumerate() {
             match target {
                 SpriteOrStage::Sprite(sprite_impl) => {
-                    sprite = Some(sprite_impl);
-                    break;
+                    if Some(i) == sprite_index {
+                        sprite = Some(sprite_impl);
+                    }
                 }
                 SpriteOrStage::Stage(stage) => {
                     stage.target.variables.0.clear();
+                    let global_var_builders = std::mem::take(&mut self.global_var_builders);
+                    for (name, var_builder) in global_var_builders {
+                        let (var, uid) = var_builder.build(name.clone());
+                        stage.target.variables.0.insert(uid.inner().into(), var);
+                        generated_data.push((format!("var/{name}"), uid.inner().to_string()));
+                        self.target_context.global_vars.insert(name.clone(), uid);
+                    }
                     let global_list_builders = std::mem::take(&mut self.global_list_builders);
                     for (name, list_builder) in global_list_builders {
                         let (list, uid) = list_builder.build(name.clone());
                         stage.target.lists.0.insert(uid.inner().into(), list);
+                        generated_data.push((format!("list/{name}"), uid.inner().to_string()));
                         self.target_context.global_lists.insert(name.clone(), uid);
                     }
                 }
             }
         }
-        let sprite = sprite.unwrap();
-        let target_context = self.target_context.get_target_context();
-        let stack_builders = std::mem::take(&mut self.stack_builders);
+        let (Some(sprite), Some(sprite_index)) = (sprite, sprite_index) else {
+            return Err(eyre!("no sprite to write the scripts to"));
+        };
+        if let Some(name) = &self.sprite_name {
+            sprite.target.name = name.clone();
+        }
+        let target_context = self.target_context.target_context();
         let blocks = stack_builders
             .into_iter()
             .flat_map(|stack_builder| {
-                stack_builder.build(&Uid::generate(), &mut self.comment_buff, &*target_context)
+                stack_builder.build(&Uid::generate(), &mut self.comment_buff, &target_context)
             })
             .map(|(uid, block)| (uid.into_inner(), block))
             .collect::<HashMap<_, _>>();
@@ -226,10 +379,138 @@ impl ProjectZip {
         for costume_builder in costume_builders {
             costume.push(costume_builder.build(&mut self.res_buff));
         }
+
+        if !self.procedure_comments.is_empty() {
+            self.attach_comments(sprite_index)?;
+        }
+        if let Some(seed) = self.id_seed {
+            self.rename_to_deterministic_ids(seed, sprite_index, &generated_data)?;
+        }
+
+        Ok(())
+    }
+
+    /// `procedure_comments` to the define blocks and the calls
+    fn attach_comments(&mut self, sprite_index: usize) -> Result<()> {
+        let mut project = serde_json::to_value(&self.project)
+            .wrap_err("failed to serialize project to attach comments")?;
+        let target = &mut project["targets"][sprite_index];
+        let blocks = target["blocks"]
+            .as_object()
+            .cloned()
+            .ok_or_else(|| eyre!("the sprite has no blocks"))?;
+
+        // by the proccode, the ids may be renamed later
+        let mut definitions = blocks
+            .iter()
+            .filter(|(_, block)| block["opcode"] == "procedures_definition")
+            .filter_map(|(id, block)| {
+                let prototype = blocks.get(block["inputs"]["custom_block"][1].as_str()?)?;
+                Some((prototype["mutation"]["proccode"].as_str()?, id, block))
+            })
+            .collect::<Vec<_>>();
+        definitions.sort_by(|a, b| (a.0, a.1).cmp(&(b.0, b.1)));
+
+        let mut attached = Vec::new();
+        for (proccode, id, block) in definitions {
+            let Some(comment) = self
+                .procedure_comments
+                .iter()
+                .find(|(label, _)| proccode_matches(proccode, label))
+                .map(|(_, comment)| comment)
+            else {
+                continue;
+            };
+            attached.push((id.clone(), comment.text.clone(), false));
+
+            // the calls are matched in order, the others are skipped
+            let mut calls = comment.calls.iter().peekable();
+            for call in calls_in_order(&blocks, block["next"].as_str()) {
+                let proccode = blocks[&call]["mutation"]["proccode"]
+                    .as_str()
+                    .unwrap_or_default();
+                if let Some((_, text)) =
+                    calls.next_if(|(label, _)| proccode_matches(proccode, label))
+                {
+                    attached.push((call, text.clone(), true));
+                }
+            }
+        }
+
+        let mut comments = target["comments"].as_object().cloned().unwrap_or_default();
+        for (n, (block_id, text, minimized)) in attached.into_iter().enumerate() {
+            let id = match self.id_seed {
+                Some(seed) => deterministic_id(seed, &format!("comment/{n}")),
+                None => generate_id(),
+            };
+            target["blocks"][&block_id]["comment"] = Value::String(id.clone());
+            comments.insert(
+                id,
+                json!({
+                    "blockId": block_id,
+                    "x": null,
+                    "y": null,
+                    "width": 300,
+                    "height": 120,
+                    "minimized": minimized,
+                    "text": text,
+                }),
+            );
+        }
+        target["comments"] = Value::Object(comments);
+
+        self.project =
+            serde_json::from_value(project).wrap_err("failed to deserialize commented project")?;
+        Ok(())
+    }
+
+    /// replaces the random ids of the generated blocks, variables and lists
+    fn rename_to_deterministic_ids(
+        &mut self,
+        seed: u64,
+        sprite_index: usize,
+        data: &[(String, String)],
+    ) -> Result<()> {
+        let mut project = serde_json::to_value(&self.project)
+            .wrap_err("failed to serialize project to rename ids")?;
+        let blocks = project["targets"][sprite_index]["blocks"]
+            .as_object()
+            .ok_or_else(|| eyre!("the sprite has no blocks"))?;
+
+        let data = data.iter().map(|(path, uid)| (uid.clone(), path.clone()));
+        let mut mapping = HashMap::new();
+        let mut used = HashSet::new();
+        let paths = block_paths(blocks);
+        let arguments = argument_paths(blocks, &paths);
+        for (old, path) in paths.into_iter().chain(arguments).chain(data) {
+            let mut id = deterministic_id(seed, &path);
+            let mut n = 0;
+            while !used.insert(id.clone()) {
+                n += 1;
+                id = deterministic_id(seed, &format!("{path}#{n}"));
+            }
+            mapping.insert(old, id);
+        }
+
+        rename_ids(&mut project, &mapping);
+        self.project =
+            serde_json::from_value(project).wrap_err("failed to deserialize renamed project")?;
+
+        let uids = self.target_context.global_vars.values_mut();
+        for uid in uids.chain(self.target_context.global_lists.values_mut()) {
+            if let Some(id) = mapping.get(uid.inner()) {
+                *uid = Uid::new(id.clone());
+            }
+        }
+
+        Ok(())
     }
 
     pub fn zip(&self) -> Result<Vec<u8>> {
-        let json = serde_json::to_string(&self.project).wrap_err(
+        // through `Value` the maps are written sorted by their keys
+        let json = serde_json::to_value(&self.project)
+            .and_then(|project| serde_json::to_string(&project))
+            .wrap_err(
             "failed to serialize project to json, this is a bug, please report it to the developers",
         )?;
 
@@ -270,12 +551,21 @@ impl ProjectZip {
             .wrap_err("failed to write entry to zip file")?;
         }
 
-        for res in &self.res_buff {
-            let mut res = res.clone();
-            let builder = ZipEntryBuilder::new(
-                res.generate_file_name().to_str().unwrap().into(),
-                async_zip::Compression::Deflate,
-            );
+        // the resources in the order of their names, a name is written once
+        let mut resources = self
+            .res_buff
+            .iter()
+            .map(|res| {
+                let mut res = res.clone();
+                let name = res.generate_file_name().to_string_lossy().into_owned();
+                (name, res)
+            })
+            .collect::<Vec<_>>();
+        resources.sort_by(|(a, _), (b, _)| a.cmp(b));
+        resources.dedup_by(|(a, _), (b, _)| a == b);
+
+        for (name, res) in resources {
+            let builder = ZipEntryBuilder::new(name.into(), async_zip::Compression::Deflate);
             futures::future::block_on(async {
                 writer.write_entry_whole(builder, res.content()).await
             })
@@ -298,7 +588,12 @@ impl ProjectZip {
         let out = out.as_ref();
 
         if out.parent().is_some() {
-            if out.parent().unwrap().parent().is_some_and(|i| i.exists()) {
+            if out
+                .parent()
+                .unwrap()
+                .parent()
+                .is_some_and(|i| !i.as_os_str().is_empty() && !i.exists())
+            {
                 return Err(eyre!(
                     "ancestor directory does not exist: {:?}",
                     out.parent().unwrap().parent()
@@ -333,6 +628,40 @@ impl ProjectZip {
     }
 }
 
+/// `label %s %b` is the proccode of a procedure labeled `label`
+fn proccode_matches(proccode: &str, label: &str) -> bool {
+    proccode.strip_prefix(label).is_some_and(|rest| {
+        rest.split_whitespace()
+            .all(|input| input == "%s" || input == "%b")
+    })
+}
+
+/// the calls of a script in the order they run, the substacks before the next block
+fn calls_in_order(blocks: &Map<String, Value>, first: Option<&str>) -> Vec<String> {
+    let mut calls = Vec::new();
+    let mut todo = first.into_iter().map(str::to_string).collect::<Vec<_>>();
+    while let Some(id) = todo.pop() {
+        let Some(block) = blocks.get(&id) else {
+            continue;
+        };
+        let mut children = ["SUBSTACK", "SUBSTACK2"]
+            .iter()
+            .filter_map(|name| block["inputs"][name][1].as_str())
+            .chain(block["next"].as_str())
+            .map(str::to_string)
+            .collect::<Vec<_>>();
+        children.reverse();
+        todo.extend(children);
+        if block["opcode"] == "procedures_call" {
+            calls.push(id);
+        }
+    }
+    calls
+}
+
+/// the names and ids the stacks are built against
+///
+/// `target_context` borrows the maps, so they can not be changed while stacks are built
 pub struct TargetContextWrapper {
     global_vars: HashMap<String, Uid>,
     global_lists: HashMap<String, Uid>,
@@ -340,7 +669,6 @@ pub struct TargetContextWrapper {
     this_sprite_lists: HashMap<String, Uid>,
     all_broadcasts: HashMap<String, Uid>,
     custom_blocks: Vec<CustomBlockTy>,
-    atomic_counter: Arc<AtomicUsize>,
 }
 
 impl TargetContextWrapper {
@@ -359,97 +687,41 @@ impl TargetContextWrapper {
             this_sprite_lists,
             all_broadcasts,
             custom_blocks,
-            atomic_counter: Arc::new(AtomicUsize::new(0)),
         }
     }
 
-    pub fn get_target_context(&self) -> TargetContextGuard {
-        let target_context: &'static mut TargetContext<'static> =
-            Box::leak(Box::new(TargetContext {
-                global_vars: Box::leak(Box::new(self.global_vars.clone())),
-                global_lists: Box::leak(Box::new(self.global_lists.clone())),
-                this_sprite_vars: Box::leak(Box::new(self.this_sprite_vars.clone())),
-                this_sprite_lists: Box::leak(Box::new(self.this_sprite_lists.clone())),
-                all_broadcasts: Box::leak(Box::new(self.all_broadcasts.clone())),
-                custom_blocks: Box::leak(Box::new(self.custom_blocks.clone())),
-            }));
-
-        self.atomic_counter
-            .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
-
-        return TargetContextGuard {
-            target_context,
-            atomic_counter: self.atomic_counter.clone(),
-        };
+    pub fn target_context(&mut self) -> TargetContext<'_> {
+        TargetContext {
+            global_vars: &mut self.global_vars,
+            global_lists: &mut self.global_lists,
+            this_sprite_vars: &mut self.this_sprite_vars,
+            this_sprite_lists: &mut self.this_sprite_lists,
+            all_broadcasts: &mut self.all_broadcasts,
+            custom_blocks: &mut self.custom_blocks,
+        }
     }
 
     pub fn get_mut_global_vars(&mut self) -> &mut HashMap<String, Uid> {
-        if self
-            .atomic_counter
-            .load(std::sync::atomic::Ordering::SeqCst)
-            > 0
-        {
-            panic!("cannot get mutable reference to global_vars while target_context is in use");
-        }
         &mut self.global_vars
     }
 
     pub fn get_mut_global_lists(&mut self) -> &mut HashMap<String, Uid> {
-        if self
-            .atomic_counter
-            .load(std::sync::atomic::Ordering::SeqCst)
-            > 0
-        {
-            panic!("cannot get mutable reference to global_lists while target_context is in use");
-        }
         &mut self.global_lists
     }
 
     pub fn get_mut_this_sprite_vars(&mut self) -> &mut HashMap<String, Uid> {
-        if self
-            .atomic_counter
-            .load(std::sync::atomic::Ordering::SeqCst)
-            > 0
-        {
-            panic!(
-                "cannot get mutable reference to this_sprite_vars while target_context is in use"
-            );
-        }
         &mut self.this_sprite_vars
     }
 
     pub fn get_mut_this_sprite_lists(&mut self) -> &mut HashMap<String, Uid> {
-        if self
-            .atomic_counter
-            .load(std::sync::atomic::Ordering::SeqCst)
-            > 0
-        {
-            panic!(
-                "cannot get mutable reference to this_sprite_lists while target_context is in use"
-            );
-        }
         &mut self.this_sprite_lists
     }
 
     pub fn get_mut_all_broadcasts(&mut self) -> &mut HashMap<String, Uid> {
-        if self
-            .atomic_counter
-            .load(std::sync::atomic::Ordering::SeqCst)
-            > 0
-        {
-            panic!("cannot get mutable reference to all_broadcasts while target_context is in use");
-        }
         &mut self.all_broadcasts
     }
 
     pub fn get_mut_custom_blocks(&mut self) -> &mut Vec<CustomBlockTy> {
-        if self
-            .atomic_counter
-            .load(std::sync::atomic::Ordering::SeqCst)
-            > 0
-        {
-            panic!("cannot get mutable reference to custom_blocks while target_context is in use");
-        }
         &mut self.custom_blocks
     }
 
@@ -502,11 +774,11 @@ impl TargetContextWrapper {
         self.custom_blocks.push(custom_func);
     }
 
-    pub fn build(&self, stack_builder: StackBuilder) -> StringHashMap<Block> {
+    pub fn build(&mut self, stack_builder: StackBuilder) -> StringHashMap<Block> {
         let blocks = stack_builder.build(
             &Uid::generate(),
             &mut HashMap::default(),
-            &*self.get_target_context(),
+            &self.target_context(),
         );
 
         let blocks = blocks
@@ -529,50 +801,3 @@ impl std::fmt::Debug for TargetContextWrapper {
             .finish()
     }
 }
-
-pub struct TargetContextGuard {
-    target_context: &'static mut TargetContext<'static>,
-    atomic_counter: Arc<AtomicUsize>,
-}
-
-impl std::ops::Deref for TargetContextGuard {
-    type Target = TargetContext<'static>;
-
-    #[inline]
-    fn deref(&self) -> &Self::Target {
-        self.target_context
-    }
-}
-
-impl TargetContextGuard {
-    pub fn deref_mut(&mut self) -> &'static mut TargetContext {
-        self.target_context
-    }
-}
-
-impl std::ops::Drop for TargetContextGuard {
-    fn drop(&mut self) {
-        unsafe {
-            let target_context = Box::from_raw(self.target_context);
-            // let TargetContext {
-            //     global_vars,
-            //     global_lists,
-            //     this_sprite_vars,
-            //     this_sprite_lists,
-            //     all_broadcasts,
-            //     custom_blocks,
-            // } = self.target_context;
-
-            // std::mem::drop(Box::from_raw(global_vars));
-            // std::mem::drop(Box::from_raw(global_lists));
-            // std::mem::drop(Box::from_raw(this_sprite_vars));
-            // std::mem::drop(Box::from_raw(this_sprite_lists));
-            // std::mem::drop(Box::from_raw(all_broadcasts));
-            // std::mem::drop(Box::from_raw(custom_blocks));
-
-            std::mem::drop(target_context);
-            self.atomic_counter
-                .fetch_sub(1, std::sync::atomic::Ordering::SeqCst);
-        }
-    }
-}
@@ -1,5 +1,13 @@
+use std::collections::HashMap;
+
+use eyre::Result;
 use sb_sbity::{block::Block, string_hashmap::StringHashMap};
-use walrus::{Function, ModuleTypes, Type};
+use walrus::{Function, ModuleTypes, Type, ValType};
+
+use crate::{
+    pre_name::pre_func_name, scratch::block::reformat::procedures_definition::func_param_names,
+    wasm::source_map::SourceMap,
+};
 
 pub fn wrap_by_len(i: usize, len: usize) -> String {
     let len = format!("{:x}", len).len();
@@ -79,6 +87,18 @@ pub fn get_preview_rect_from_block<'a>(func: &StringHashMap<Block>) -> (i64, i64
 pub struct GenCtx {
     pub index_of_func_block: usize,
     pub functions_count: usize,
+    /// number of items reserved in `__wasm_local_stack`
+    pub local_stack_len: usize,
+    /// number of items reserved in `__wasm_function_stack`
+    pub function_stack_len: usize,
+    /// first item of each global in `__wasm_global_stack`, by global index
+    pub global_slots: HashMap<usize, usize>,
+    /// readable labels by function index, see `reformat::names`
+    pub func_names: HashMap<usize, String>,
+    /// readable parameter names by function index
+    pub param_names: HashMap<usize, Vec<String>>,
+    /// comments are generated with it
+    pub source_map: Option<SourceMap>,
 }
 
 impl GenCtx {
@@ -86,6 +106,12 @@ impl GenCtx {
         GenCtx {
             index_of_func_block: 0,
             functions_count: 0,
+            local_stack_len: 0,
+            function_stack_len: 0,
+            global_slots: HashMap::new(),
+            func_names: HashMap::new(),
+            param_names: HashMap::new(),
+            source_map: None,
         }
     }
 
@@ -93,7 +119,37 @@ impl GenCtx {
         wrap_by_len(self.index_of_func_block, self.functions_count)
     }
 
+    pub fn gen_func_name(&self, index: usize) -> String {
+        if let Some(name) = self.func_names.get(&index) {
+            return name.clone();
+        }
+        format!(
+            "{}{}",
+            pre_func_name(),
+            wrap_by_len(index, self.functions_count)
+        )
+    }
+
+    /// names of the custom block inputs of the function, numbered without the name section
+    pub fn gen_param_names(&self, index: usize, params: &[ValType]) -> Result<Vec<String>> {
+        match self.param_names.get(&index) {
+            Some(names) => Ok(names.clone()),
+            None => func_param_names(params),
+        }
+    }
+
     pub fn update_func_block(&mut self) {
         self.index_of_func_block += 1;
     }
+
+    /// reserve `len` items in `__wasm_local_stack` and return the offset of the first one
+    pub fn alloc_local_stack(&mut self, len: usize) -> usize {
+        let offset = self.local_stack_len;
+        self.local_stack_len += len;
+        offset
+    }
+
+    pub fn reserve_function_stack(&mut self, len: usize) {
+        self.function_stack_len = self.function_stack_len.max(len);
+    }
 }
@@ -1,8 +1,13 @@
-use eyre::Result;
+use eyre::{Context, Result};
 use tempfile::NamedTempFile;
 use walrus::Module;
 use wasm_opt::OptimizationOptions;
 
+use crate::{
+    project_config::{ProjectConfig, WasmOptPreset},
+    scratch::block::bindgen::unsupported_imports,
+};
+
 pub fn rm_export_fn(module: &mut Module, rm_types: Vec<String>) -> Result<()> {
     let prefix = "__wasm_sb_bindgen_describe_";
 
@@ -50,10 +55,19 @@ pub fn check_rm_import_fn(module: &Module) -> Result<()> {
         None => (),
     };
 
+    let unsupported = unsupported_imports(module);
+    if !unsupported.is_empty() {
+        return Err(eyre::eyre!(
+            "unsupported imports:\n  {}",
+            unsupported.join("\n  ")
+        ));
+    }
+
     Ok(())
 }
 
-pub fn wasm_opt_module(mut module: Module) -> Result<Module> {
+/// runs the preset and the passes of `config`, then writes `emit_intermediate`
+pub fn wasm_opt_module(mut module: Module, config: &ProjectConfig) -> Result<(Module, Vec<u8>)> {
     let tmp = NamedTempFile::new()?;
 
     // save
@@ -61,14 +75,39 @@ pub fn wasm_opt_module(mut module: Module) -> Result<Module> {
         .emit_wasm_file(tmp.path())
         .map_err(|e| eyre::eyre!("{:?}", e.to_string()))?;
 
-    OptimizationOptions::new_optimize_for_size_aggressively().run(tmp.path(), tmp.path())?;
+    let mut options = match config.wasm_opt {
+        WasmOptPreset::None => OptimizationOptions::new_opt_level_0(),
+        WasmOptPreset::O1 => OptimizationOptions::new_opt_level_1(),
+        WasmOptPreset::O2 => OptimizationOptions::new_opt_level_2(),
+        WasmOptPreset::O3 => OptimizationOptions::new_opt_level_3(),
+        WasmOptPreset::O4 => OptimizationOptions::new_opt_level_4(),
+        WasmOptPreset::Os => OptimizationOptions::new_optimize_for_size(),
+        WasmOptPreset::Oz => OptimizationOptions::new_optimize_for_size_aggressively(),
+    };
+    options.passes.add_default_passes =
+        config.default_passes && config.wasm_opt != WasmOptPreset::None;
+    options.passes.more_passes.extend(
+        config
+            .passes
+            .iter()
+            .filter(|pass| !config.removed_passes.contains(pass))
+            .map(|pass| pass.pass()),
+    );
+    // keep the name section and DWARF
+    options.debug_info(config.readable_names || config.comments);
 
-    // copy on debug
-    std::fs::copy(tmp.path(), "debug.wasm")?;
+    if options.passes.add_default_passes || !options.passes.more_passes.is_empty() {
+        options.run(tmp.path(), tmp.path())?;
+    }
+
+    if let Some(path) = &config.emit_intermediate {
+        std::fs::copy(tmp.path(), path).wrap_err(format!("failed to write {:?}", path))?;
+    }
 
-    // load
+    // load, the offsets of the instructions are in these bytes
+    let data = std::fs::read(tmp.path())?;
     let module =
-        walrus::Module::from_file(tmp.path()).map_err(|e| eyre::eyre!("{:?}", e.to_string()))?;
+        walrus::Module::from_buffer(&data).map_err(|e| eyre::eyre!("{:?}", e.to_string()))?;
 
-    Ok(module)
+    Ok((module, data))
 }
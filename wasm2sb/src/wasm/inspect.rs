@@ -0,0 +1,354 @@
+// `wasm2sb inspect`, the signatures decoded from `__wasm_sb_bindgen_describe_*`
+
+use std::collections::HashMap;
+
+use eyre::{Context, Result};
+use serde::Serialize;
+
+use super::descriptor::{Closure, Descriptor, Function};
+
+/// one argument or the result of an exported function
+#[derive(Debug, Clone, Serialize)]
+pub struct InspectValue {
+    /// rust-ish type
+    pub ty: String,
+    /// input kind of the custom block, `None` for `()`
+    pub input: Option<&'static str>,
+    /// f64 primitives the value is split into, in order
+    pub slots: Vec<&'static str>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct InspectFunction {
+    pub name: String,
+    pub params: Vec<InspectValue>,
+    pub ret: InspectValue,
+}
+
+impl InspectValue {
+    pub fn new(descriptor: &Descriptor) -> Self {
+        InspectValue {
+            ty: rust_ty(descriptor),
+            input: input_kind(descriptor),
+            slots: abi_slots(descriptor),
+        }
+    }
+}
+
+impl InspectFunction {
+    pub fn new(name: &str, descriptor: &Descriptor) -> Self {
+        let (params, ret) = match descriptor {
+            Descriptor::Function(f) => (f.arguments.iter().collect(), &f.ret),
+            Descriptor::Closure(c) => (c.function.arguments.iter().collect(), &c.function.ret),
+            // not a function, shown as a getter
+            other => (Vec::new(), other),
+        };
+        InspectFunction {
+            name: name.to_string(),
+            params: params.into_iter().map(InspectValue::new).collect(),
+            ret: InspectValue::new(ret),
+        }
+    }
+}
+
+/// sorted by name
+pub fn inspect_functions(tys: &HashMap<String, Descriptor>) -> Vec<InspectFunction> {
+    let mut functions = tys
+        .iter()
+        .map(|(name, descriptor)| InspectFunction::new(name, descriptor))
+        .collect::<Vec<_>>();
+    functions.sort_by(|a, b| a.name.cmp(&b.name));
+    functions
+}
+
+/// the signature table, or json with `json`
+pub fn inspect(tys: &HashMap<String, Descriptor>, json: bool) -> Result<String> {
+    let functions = inspect_functions(tys);
+    if json {
+        let json =
+            serde_json::to_string_pretty(&functions).wrap_err("failed to serialize signatures")?;
+        return Ok(json + "\n");
+    }
+
+    let mut out = String::new();
+    for function in &functions {
+        let params = function
+            .params
+            .iter()
+            .map(|param| param.ty.as_str())
+            .collect::<Vec<_>>()
+            .join(", ");
+        out.push_str(&format!(
+            "fn {}({}) -> {}\n",
+            function.name, params, function.ret.ty
+        ));
+
+        let rows = function
+            .params
+            .iter()
+            .enumerate()
+            .map(|(i, param)| (i.to_string(), param))
+            .chain(std::iter::once(("ret".to_string(), &function.ret)))
+            .collect::<Vec<_>>();
+        let width = rows
+            .iter()
+            .map(|(_, v)| v.ty.len())
+            .chain(["type".len()])
+            .max()
+            .unwrap_or(0);
+        out.push_str(&format!(
+            "    {:<4} {:<width$} {:<13} {}\n",
+            "#", "type", "input", "slots"
+        ));
+        for (index, value) in rows {
+            let slots = if value.slots.is_empty() {
+                "-".to_string()
+            } else {
+                value.slots.join(", ")
+            };
+            out.push_str(&format!(
+                "    {:<4} {:<width$} {:<13} {}\n",
+                index,
+                value.ty,
+                value.input.unwrap_or("-"),
+                slots
+            ));
+        }
+        out.push('\n');
+    }
+    Ok(out)
+}
+
+pub fn rust_ty(descriptor: &Descriptor) -> String {
+    match descriptor {
+        Descriptor::I8 => "i8".into(),
+        Descriptor::U8 => "u8".into(),
+        Descriptor::I16 => "i16".into(),
+        Descriptor::U16 => "u16".into(),
+        Descriptor::I32 => "i32".into(),
+        Descriptor::U32 => "u32".into(),
+        Descriptor::I64 => "i64".into(),
+        Descriptor::U64 => "u64".into(),
+        Descriptor::F32 => "f32".into(),
+        Descriptor::F64 => "f64".into(),
+        Descriptor::Boolean => "bool".into(),
+        Descriptor::Char => "char".into(),
+        Descriptor::String => "String".into(),
+        Descriptor::Unit => "()".into(),
+        Descriptor::NonNull => "NonNull<u8>".into(),
+        Descriptor::Externref => "SbValue".into(),
+        Descriptor::NamedExternref(name) => name.clone(),
+        Descriptor::Enum { name, .. } => name.clone(),
+        Descriptor::RustStruct(name) => name.clone(),
+        Descriptor::Ref(d) => format!("&{}", rust_ty(d)),
+        Descriptor::RefMut(d) => format!("&mut {}", rust_ty(d)),
+        Descriptor::Slice(d) => format!("[{}]", rust_ty(d)),
+        Descriptor::Vector(d) => format!("Box<[{}]>", rust_ty(d)),
+        Descriptor::Option(d) => format!("Option<{}>", rust_ty(d)),
+        Descriptor::Result(d) => format!("Result<{}, SbValue>", rust_ty(d)),
+        Descriptor::Function(f) => fn_ty("fn", f),
+        Descriptor::Closure(c) => closure_ty(c),
+    }
+}
+
+fn fn_ty(head: &str, f: &Function) -> String {
+    let arguments = f.arguments.iter().map(rust_ty).collect::<Vec<_>>();
+    format!("{head}({}) -> {}", arguments.join(", "), rust_ty(&f.ret))
+}
+
+fn closure_ty(c: &Closure) -> String {
+    fn_ty(if c.mutable { "dyn FnMut" } else { "dyn Fn" }, &c.function)
+}
+
+/// `boolean` is only for `bool`, the others are `string_number`
+pub fn input_kind(descriptor: &Descriptor) -> Option<&'static str> {
+    match descriptor {
+        Descriptor::Unit => None,
+        Descriptor::Boolean => Some("boolean"),
+        _ => Some("string_number"),
+    }
+}
+
+/// the primitives of `WasmAbi::split`, keep in sync with wasm-sb-bindgen/src/convert
+pub fn abi_slots(descriptor: &Descriptor) -> Vec<&'static str> {
+    match descriptor {
+        Descriptor::Unit => vec![],
+        Descriptor::I64 | Descriptor::U64 => vec!["hi", "lo"],
+        Descriptor::String | Descriptor::Vector(_) | Descriptor::Slice(_) => vec!["ptr", "len"],
+        Descriptor::Function(_) | Descriptor::Closure(_) => vec!["ptr", "len"],
+        Descriptor::Ref(d) => abi_slots(d),
+        Descriptor::RefMut(d) => match **d {
+            Descriptor::Slice(_) => vec!["ptr", "len", "idx"],
+            _ => abi_slots(d),
+        },
+        // only `Option<f64>` has a flag, the others implement `OptionIntoWasmAbi`
+        // and keep `None` in their own slots, as `f64::MAX`, NaN or a null ptr
+        Descriptor::Option(d) => match **d {
+            Descriptor::F64 => vec!["is_some", "value"],
+            _ => abi_slots(d),
+        },
+        Descriptor::Result(d) => {
+            let mut slots = abi_slots(d);
+            slots.extend(["err", "is_err"]);
+            slots
+        }
+        _ => vec!["value"],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::{abi_slots, inspect, rust_ty};
+    use crate::wasm::descriptor::{Closure, Descriptor, Function};
+
+    fn boxed(descriptor: fn(Box<Descriptor>) -> Descriptor, inner: Descriptor) -> Descriptor {
+        descriptor(Box::new(inner))
+    }
+
+    fn function(arguments: Vec<Descriptor>, ret: Descriptor) -> Function {
+        Function {
+            arguments,
+            shim_idx: 0,
+            ret,
+            inner_ret: None,
+        }
+    }
+
+    #[test]
+    fn test_abi_slots() {
+        use Descriptor as D;
+
+        let slice = || boxed(D::Slice, D::U8);
+        for (descriptor, slots) in [
+            (D::Unit, vec![]),
+            (D::U32, vec!["value"]),
+            (D::F64, vec!["value"]),
+            (D::Boolean, vec!["value"]),
+            (D::Externref, vec!["value"]),
+            (D::I64, vec!["hi", "lo"]),
+            (D::String, vec!["ptr", "len"]),
+            (boxed(D::Vector, D::U8), vec!["ptr", "len"]),
+            (boxed(D::Ref, slice()), vec!["ptr", "len"]),
+            (boxed(D::Ref, D::String), vec!["ptr", "len"]),
+            (boxed(D::RefMut, slice()), vec!["ptr", "len", "idx"]),
+            (boxed(D::RefMut, D::RustStruct("S".into())), vec!["value"]),
+            // only `Option<f64>` has a flag
+            (boxed(D::Option, D::F64), vec!["is_some", "value"]),
+            (boxed(D::Option, D::U32), vec!["value"]),
+            (boxed(D::Option, D::Char), vec!["value"]),
+            (boxed(D::Option, D::U64), vec!["hi", "lo"]),
+            (boxed(D::Option, D::String), vec!["ptr", "len"]),
+            (
+                boxed(D::Option, boxed(D::Vector, D::I32)),
+                vec!["ptr", "len"],
+            ),
+            (boxed(D::Result, D::Unit), vec!["err", "is_err"]),
+            (boxed(D::Result, D::U32), vec!["value", "err", "is_err"]),
+            (
+                boxed(D::Result, D::String),
+                vec!["ptr", "len", "err", "is_err"],
+            ),
+            (
+                boxed(D::Result, boxed(D::Option, D::F64)),
+                vec!["is_some", "value", "err", "is_err"],
+            ),
+        ] {
+            assert_eq!(abi_slots(&descriptor), slots, "{descriptor:?}");
+        }
+    }
+
+    #[test]
+    fn test_rust_ty() {
+        use Descriptor as D;
+
+        let closure = |mutable| Closure {
+            shim_idx: 0,
+            dtor_idx: 0,
+            function: function(vec![D::U32], D::Unit),
+            mutable,
+        };
+        for (descriptor, ty) in [
+            (D::Unit, "()"),
+            (D::Externref, "SbValue"),
+            (D::NamedExternref("HtmlElement".into()), "HtmlElement"),
+            (boxed(D::Ref, boxed(D::Slice, D::U8)), "&[u8]"),
+            (boxed(D::RefMut, boxed(D::Slice, D::F32)), "&mut [f32]"),
+            (boxed(D::Vector, D::String), "Box<[String]>"),
+            (boxed(D::Option, D::I64), "Option<i64>"),
+            (boxed(D::Result, D::Boolean), "Result<bool, SbValue>"),
+            (
+                D::Function(Box::new(function(vec![D::I32, D::Char], D::F64))),
+                "fn(i32, char) -> f64",
+            ),
+            (D::Closure(Box::new(closure(false))), "dyn Fn(u32) -> ()"),
+            (D::Closure(Box::new(closure(true))), "dyn FnMut(u32) -> ()"),
+        ] {
+            assert_eq!(rust_ty(&descriptor), ty, "{descriptor:?}");
+        }
+    }
+
+    #[test]
+    fn test_inspect() {
+        use Descriptor as D;
+
+        let tys = HashMap::from([
+            (
+                "greet".to_string(),
+                D::Function(Box::new(function(
+                    vec![boxed(D::Ref, D::String), D::Boolean],
+                    boxed(D::Option, D::F64),
+                ))),
+            ),
+            (
+                "answer".to_string(),
+                D::Function(Box::new(function(vec![], D::U32))),
+            ),
+        ]);
+
+        let table = inspect(&tys, false).unwrap();
+        assert_eq!(
+            table,
+            [
+                "fn answer() -> u32",
+                "    #    type input         slots",
+                "    ret  u32  string_number value",
+                "",
+                "fn greet(&String, bool) -> Option<f64>",
+                "    #    type        input         slots",
+                "    0    &String     string_number ptr, len",
+                "    1    bool        boolean       value",
+                "    ret  Option<f64> string_number is_some, value",
+                "",
+                "",
+            ]
+            .join("\n")
+        );
+
+        let json = inspect(&tys, true).unwrap();
+        let json = serde_json::from_str::<serde_json::Value>(&json).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!([
+                {
+                    "name": "answer",
+                    "params": [],
+                    "ret": { "ty": "u32", "input": "string_number", "slots": ["value"] },
+                },
+                {
+                    "name": "greet",
+                    "params": [
+                        { "ty": "&String", "input": "string_number", "slots": ["ptr", "len"] },
+                        { "ty": "bool", "input": "boolean", "slots": ["value"] },
+                    ],
+                    "ret": {
+                        "ty": "Option<f64>",
+                        "input": "string_number",
+                        "slots": ["is_some", "value"],
+                    },
+                },
+            ])
+        );
+    }
+}
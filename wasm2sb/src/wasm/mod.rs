@@ -2,7 +2,6 @@ use std::collections::HashMap;
 
 use crate::wasm::descriptor::Descriptor;
 
-use colored::Colorize as _;
 use eyre::{eyre, Context, Result};
 
 use self::interpreter_descriptor::interpreter_descriptor;
@@ -10,9 +9,10 @@ use self::interpreter_descriptor::interpreter_descriptor;
 pub mod adjust;
 pub mod decode;
 pub mod descriptor;
+pub mod inspect;
 pub mod interpreter_descriptor;
-pub mod sb;
 pub mod scheme_versions;
+pub mod source_map;
 
 pub fn load_schema_version(module: &wain_ast::Module) -> Result<()> {
     for func in &module.funcs {
@@ -49,7 +49,7 @@ pub fn load_schema_version(module: &wain_ast::Module) -> Result<()> {
     Err(eyre!("schema version not found"))
 }
 
-pub fn get_ty(buff: &Vec<u8>) -> Result<HashMap<String, Descriptor>> {
+pub fn get_ty(buff: &[u8]) -> Result<HashMap<String, Descriptor>> {
     let module = match wain_syntax_binary::parse(buff) {
         Ok(m) => m,
         Err(err) => {
@@ -60,7 +60,6 @@ pub fn get_ty(buff: &Vec<u8>) -> Result<HashMap<String, Descriptor>> {
     .module;
 
     load_schema_version(&module)?;
-    println!("{}", "schema version loaded successfully!".green().bold());
 
     let prefix = "__wasm_sb_bindgen_describe_";
     let exports = module
@@ -88,7 +87,7 @@ pub fn get_ty(buff: &Vec<u8>) -> Result<HashMap<String, Descriptor>> {
         .collect::<HashMap<_, _>>();
 
     for (name, ty) in &tys {
-        println!("{}: {:?}", name, ty);
+        log::debug!("{}: {:?}", name, ty);
     }
 
     Ok(tys)
@@ -1,6 +0,0 @@
-use wain_ast::Module;
-
-pub fn inject_stack_pointer_shim(_module: &Module) {
-    // module.funcs.push({
-    // });
-}
@@ -0,0 +1,116 @@
+// wasm offsets to the rust source lines, from the DWARF custom sections
+
+use std::collections::HashMap;
+
+use eyre::{Context, Result};
+use gimli::{EndianSlice, LittleEndian};
+use wasmparser::{Parser, Payload};
+
+/// the lines of the code, empty without DWARF
+#[derive(Debug, Clone, Default)]
+pub struct SourceMap {
+    /// start of the code section payload, DWARF addresses are relative to it
+    code_start: usize,
+    /// sorted by address, `None` is the end of a sequence
+    rows: Vec<(u64, Option<(usize, u64)>)>,
+    files: Vec<String>,
+}
+
+impl SourceMap {
+    /// `data` must be the bytes the module was parsed from, `InstrLocId` is an offset in them
+    pub fn new(data: &[u8]) -> Result<Self> {
+        let (sections, code_start) = sections(data)?;
+        let mut map = SourceMap {
+            code_start,
+            ..Default::default()
+        };
+        if sections.contains_key(".debug_line") {
+            map.load_lines(&sections)
+                .wrap_err("failed to read DWARF line programs")?;
+        }
+        Ok(map)
+    }
+
+    pub fn has_lines(&self) -> bool {
+        !self.rows.is_empty()
+    }
+
+    /// `file:line` of the instruction at `offset` in the module
+    pub fn line(&self, offset: usize) -> Option<String> {
+        let address = offset.checked_sub(self.code_start)? as u64;
+        let index = self.rows.partition_point(|(row, _)| *row <= address);
+        let (file, line) = self.rows.get(index.checked_sub(1)?)?.1?;
+        Some(format!("{}:{}", self.files[file], line))
+    }
+
+    /// the offset and the line if known, for the comments
+    pub fn describe(&self, offset: usize) -> String {
+        match self.line(offset) {
+            Some(line) => format!("@ 0x{offset:x}\n{line}"),
+            None => format!("@ 0x{offset:x}"),
+        }
+    }
+
+    fn load_lines(&mut self, sections: &HashMap<String, &[u8]>) -> Result<()> {
+        let dwarf = gimli::Dwarf::load(|id| -> Result<_, gimli::Error> {
+            let data = sections.get(id.name()).copied().unwrap_or(&[]);
+            Ok(EndianSlice::new(data, LittleEndian))
+        })?;
+
+        let mut files = HashMap::new();
+        let mut units = dwarf.units();
+        while let Some(header) = units.next()? {
+            let unit = dwarf.unit(header)?;
+            let Some(program) = unit.line_program.clone() else {
+                continue;
+            };
+            let mut rows = program.rows();
+            while let Some((header, row)) = rows.next_row()? {
+                if row.end_sequence() {
+                    self.rows.push((row.address(), None));
+                    continue;
+                }
+                let Some(file) = row.file(header) else {
+                    continue;
+                };
+                let name = dwarf.attr_string(&unit, file.path_name())?;
+                let name = name.to_string_lossy();
+                let path = match file.directory(header) {
+                    Some(dir) if !name.starts_with('/') => {
+                        let dir = dwarf.attr_string(&unit, dir)?;
+                        format!("{}/{}", dir.to_string_lossy(), name)
+                    }
+                    _ => name.into_owned(),
+                };
+                let next = self.files.len();
+                let file = *files.entry(path.clone()).or_insert(next);
+                if file == next {
+                    self.files.push(path);
+                }
+                let line = row.line().map(|line| line.get()).unwrap_or(0);
+                self.rows.push((row.address(), Some((file, line))));
+            }
+        }
+        // the end of a sequence goes before a row at the same address
+        self.rows
+            .sort_by_key(|(address, row)| (*address, row.is_some()));
+        Ok(())
+    }
+}
+
+/// the custom sections by name and the start of the code section payload
+fn sections(data: &[u8]) -> Result<(HashMap<String, &[u8]>, usize)> {
+    let mut custom = HashMap::new();
+    let mut code_start = 0;
+    for payload in Parser::new(0).parse_all(data) {
+        match payload.wrap_err("failed to parse wasm sections")? {
+            Payload::CustomSection { name, data, .. } => {
+                custom.insert(name.to_string(), data);
+            }
+            // the range starts at the count of the functions, as the DWARF addresses
+            Payload::CodeSectionStart { range, .. } => code_start = range.start,
+            _ => {}
+        }
+    }
+    Ok((custom, code_start))
+}